@@ -1,10 +1,17 @@
 use glob::glob;
 use spirv_builder::{Capability, MetadataPrintout, SpirvBuilder};
 
-// TODO for glsl shaders (e.g.):
-// glslangValidator -V -e main_vs --source-entrypoint main .\vertex.vert -o .\vertex.spv
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsStr;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const GLSL_PATH: &str = "../shaders/glsl";
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut names = HashSet::new();
     for path in glob("../shaders/*")
         .unwrap()
         .filter_map(Result::ok)
@@ -12,8 +19,108 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     {
         SpirvBuilder::new(path.as_path().as_os_str(), "spirv-unknown-spv1.5")
             .capability(Capability::ImageQuery)
+            .capability(Capability::StorageImageExtendedFormats)
+            .capability(Capability::Int64)
+            .capability(Capability::RuntimeDescriptorArray)
+            // buffer device address loads
+            .capability(Capability::PhysicalStorageBufferAddresses)
+            .extension("SPV_KHR_physical_storage_buffer")
             .print_metadata(MetadataPrintout::Full)
             .build()?;
+        names.extend(
+            path.file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+        );
+    }
+
+    if !Path::new(GLSL_PATH).exists() {
+        return Ok(());
+    }
+    // NB! picks up added and removed GLSL sources
+    println!("cargo:rerun-if-changed={GLSL_PATH}");
+    let out_dir = env::var("OUT_DIR")?;
+    for path in glob(&format!("{GLSL_PATH}/*"))?.filter_map(Result::ok) {
+        compile_glsl(&path, Path::new(&out_dir), &mut names)?;
+    }
+    Ok(())
+}
+
+// The glslangValidator stage and entry point of a GLSL or HLSL source; HLSL sources name their
+// stage before the extension, e.g. skybox.frag.hlsl. Entry points follow the rust-gpu naming, so
+// pipelines don't care which language a shader is in.
+fn get_glsl_stage(path: &Path) -> Option<(&'static str, &'static str)> {
+    let stage_path = match path.extension().and_then(OsStr::to_str)? {
+        "hlsl" => Path::new(path.file_stem()?),
+        _ => path,
+    };
+    match stage_path.extension().and_then(OsStr::to_str)? {
+        "vert" => Some(("vert", "main_vs")),
+        "frag" => Some(("frag", "main_fs")),
+        "comp" => Some(("comp", "main_cs")),
+        _ => None,
+    }
+}
+
+// Compiles a .vert, .frag or .comp GLSL file, or a .<stage>.hlsl HLSL one, to <name>.spv, exposed
+// like the rust-gpu outputs as env!("<name>.spv"), where name is the file name up to its first dot;
+// other files are includes. Sources keep a plain `main`, renamed on the way out, and may #include
+// (GL_GOOGLE_include_directive in GLSL) relative to themselves or shaders/glsl.
+fn compile_glsl(
+    path: &Path,
+    out_dir: &Path,
+    names: &mut HashSet<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some((stage, entry_point)) = get_glsl_stage(path) else {
+        return Ok(());
+    };
+    let name = path
+        .file_name()
+        .and_then(OsStr::to_str)
+        .and_then(|file_name| file_name.split('.').next())
+        .ok_or("koi::build - invalid GLSL shader name")?;
+    let output_path = out_dir.join(format!("{name}.spv"));
+    let depfile_path = out_dir.join(format!("{name}.d"));
+    // NB! stems share one namespace with the rust-gpu crates
+    if !names.insert(name.to_owned()) {
+        return Err(format!("koi::build - more than one shader is named {name}").into());
+    }
+
+    let mut command = Command::new("glslangValidator");
+    if path.extension() == Some(OsStr::new("hlsl")) {
+        command.arg("-D");
+    }
+    let output = command
+        .args(["-V", "--target-env", "vulkan1.3", "-S", stage])
+        .args(["-e", entry_point, "--source-entrypoint", "main"])
+        .arg(format!("-I{GLSL_PATH}"))
+        .arg("--depfile")
+        .arg(&depfile_path)
+        .arg(path)
+        .arg("-o")
+        .arg(&output_path)
+        .output()
+        .map_err(|error| format!("koi::build - failed to run glslangValidator: {error}"))?;
+    // NB! glslangValidator reports errors on stdout
+    if !output.status.success() {
+        return Err(format!(
+            "koi::build - failed to compile {}:\n{}",
+            path.display(),
+            String::from_utf8_lossy(&output.stdout)
+        )
+        .into());
+    }
+
+    // the shader itself and everything it includes
+    let depfile = fs::read_to_string(&depfile_path)?;
+    let dependencies = depfile
+        .split_once(':')
+        .map_or("", |(_, dependencies)| dependencies);
+    for dependency in dependencies
+        .split_whitespace()
+        .filter(|&token| token != "\\")
+    {
+        println!("cargo:rerun-if-changed={dependency}");
     }
+    println!("cargo:rustc-env={name}.spv={}", output_path.display());
     Ok(())
 }
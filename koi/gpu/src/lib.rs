@@ -1,66 +1,348 @@
-#![cfg_attr(target_arch = "spirv", no_std)]
+#![cfg_attr(target_arch = "spirv", no_std, feature(asm_experimental_arch))]
 
+#[cfg(target_arch = "spirv")]
+use core::arch::asm;
 #[cfg(not(target_arch = "spirv"))]
-use bytemuck::cast;
+use spirv_std::glam::UVec3;
+use spirv_std::glam::{IVec2, Mat4, UVec2, UVec4, Vec2, Vec3, Vec4, Vec4Swizzles};
+use spirv_std::image::Image;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
 
-use spirv_std::glam::{Mat4, Vec4};
+// Byte offset and size of every field, in declaration order; what the shaders' reflection is
+// checked against.
 #[cfg(not(target_arch = "spirv"))]
-use spirv_std::glam::{Vec2, Vec3, Vec4Swizzles};
+pub trait GpuLayout: bytemuck::Pod {
+    const MEMBERS: &'static [(usize, usize)];
+}
+
+#[cfg(not(target_arch = "spirv"))]
+const fn get_field_size<T, F>(_: fn(&T) -> &F) -> usize {
+    size_of::<F>()
+}
+
+// Pins the layout of a struct shared with the shaders: its size and field offsets are asserted at
+// compile time, as is the absence of padding, which is what makes the Pod impl sound. Fields are
+// listed in declaration order.
+macro_rules! gpu_layout {
+    ($type:ty, $size:expr, { $($field:ident: $offset:expr),+ $(,)? }) => {
+        #[cfg(not(target_arch = "spirv"))]
+        const _: () = {
+            assert!(size_of::<$type>() == $size);
+            $(assert!(core::mem::offset_of!($type, $field) == $offset);)+
+            assert!(0 $(+ get_field_size(|value: &$type| &value.$field))+ == $size);
+        };
+
+        #[cfg(not(target_arch = "spirv"))]
+        unsafe impl bytemuck::Zeroable for $type {}
+
+        #[cfg(not(target_arch = "spirv"))]
+        unsafe impl bytemuck::Pod for $type {}
+
+        #[cfg(not(target_arch = "spirv"))]
+        impl GpuLayout for $type {
+            const MEMBERS: &'static [(usize, usize)] = &[$((
+                core::mem::offset_of!($type, $field),
+                get_field_size(|value: &$type| &value.$field),
+            )),+];
+        }
+    };
+}
+
+// Reads a value through a buffer device address, the counterpart of GLSL's buffer_reference; T
+// must be one of the repr(C) types here so host and shader layouts agree. ALIGNMENT is the largest
+// power of two the address is known to be a multiple of.
+// NB! needs the PhysicalStorageBufferAddresses capability, enabled for every crate in build.rs
+#[cfg(target_arch = "spirv")]
+pub unsafe fn load<T, const ALIGNMENT: u32>(address: u64, result: &mut T) {
+    unsafe {
+        asm!(
+            "%pointer_type = OpTypePointer PhysicalStorageBuffer typeof*{result}",
+            "%pointer = OpConvertUToPtr %pointer_type {address}",
+            "%value = OpLoad typeof*{result} %pointer Aligned {alignment}",
+            "OpStore {result} %value",
+            address = in(reg) address,
+            result = in(reg) result,
+            alignment = const ALIGNMENT,
+        );
+    }
+}
+
+/// # Safety
+/// Never called on the host; host builds only type-check the shaders, and a device address
+/// doesn't point into host memory.
+#[cfg(not(target_arch = "spirv"))]
+pub unsafe fn load<T, const ALIGNMENT: u32>(_address: u64, _result: &mut T) {
+    unreachable!("koi::gpu - buffer device addresses can only be read on the GPU")
+}
 
+// Camera and lighting inputs of the mesh pass; written once per frame through the frame allocator
+// and read by both mesh shaders from PushConstants::frame_data_address.
 #[cfg_attr(not(target_arch = "spirv"), derive(Clone, Copy))]
 #[repr(C)]
-pub struct PushConstants {
-    pub world_transform: Mat4,
-    pub vertex_buffer_address: u64,
+pub struct FrameData {
+    // model transforms are read per instance
+    pub view_projection: Mat4,
+    pub previous_view_projection: Mat4,
+    // xy: current frame jitter, zw: previous frame jitter; both in NDC units
+    pub jitter: Vec4,
+    pub camera_position: Vec4,
+    // x: IBL intensity, y: prefiltered environment max LOD
+    pub environment: Vec4,
+}
+
+#[cfg(target_arch = "spirv")]
+impl FrameData {
+    /// # Safety
+    /// address must be the device address of a FrameData the GPU can still read, such as this
+    /// frame's allocation from the frame allocator; those are at least 16-byte aligned.
+    pub unsafe fn load(address: u64) -> Self {
+        let mut frame_data = Self {
+            view_projection: Mat4::ZERO,
+            previous_view_projection: Mat4::ZERO,
+            jitter: Vec4::ZERO,
+            camera_position: Vec4::ZERO,
+            environment: Vec4::ZERO,
+        };
+        unsafe { load::<Self, 16>(address, &mut frame_data) };
+        frame_data
+    }
 }
 
 #[cfg(not(target_arch = "spirv"))]
-impl PushConstants {
-    pub fn world_transform(mut self, world_transform: Mat4) -> Self {
-        self.world_transform = world_transform;
+impl FrameData {
+    pub fn view_projection(mut self, view_projection: Mat4) -> Self {
+        self.view_projection = view_projection;
+        self
+    }
+
+    pub fn previous_view_projection(mut self, previous_view_projection: Mat4) -> Self {
+        self.previous_view_projection = previous_view_projection;
+        self
+    }
+
+    pub fn jitter(mut self, current: Vec2, previous: Vec2) -> Self {
+        self.jitter = Vec4::from((current, previous));
+        self
+    }
+
+    pub fn camera_position(mut self, camera_position: Vec3) -> Self {
+        self.camera_position = Vec4::from((camera_position, 1.0));
         self
     }
 
-    pub fn vertex_buffer_address(mut self, address: u64) -> Self {
-        self.vertex_buffer_address = address;
+    pub fn environment(mut self, ibl_intensity: f32, max_lod: f32) -> Self {
+        self.environment = Vec4::new(ibl_intensity, max_lod, 0.0, 0.0);
         self
     }
+}
 
-    // TODO this is bad, and you should feel bad
-    pub fn as_buffer(&self) -> [u8; 72] {
-        [
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(0).xy().to_array()),
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(0).zw().to_array()),
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(1).xy().to_array()),
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(1).zw().to_array()),
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(2).xy().to_array()),
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(2).zw().to_array()),
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(3).xy().to_array()),
-            cast::<[f32; 2], [u8; 8]>(self.world_transform.col(3).zw().to_array()),
-            cast::<u64, [u8; 8]>(self.vertex_buffer_address),
-        ]
-        .as_flattened()
-        .try_into()
-        .unwrap()
+#[cfg(not(target_arch = "spirv"))]
+impl Default for FrameData {
+    fn default() -> Self {
+        Self {
+            view_projection: Mat4::IDENTITY,
+            previous_view_projection: Mat4::IDENTITY,
+            jitter: Vec4::ZERO,
+            camera_position: Vec4::W,
+            environment: Vec4::new(1.0, 0.0, 0.0, 0.0),
+        }
     }
 }
 
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct PushConstants {
+    // the frame's FrameData
+    pub frame_data_address: u64,
+}
+
 #[cfg(not(target_arch = "spirv"))]
-impl Default for PushConstants {
+impl PushConstants {
+    pub fn frame_data_address(mut self, address: u64) -> Self {
+        self.frame_data_address = address;
+        self
+    }
+}
+
+// One drawable surface placed in the world; indexed by gl_InstanceIndex in the mesh shaders.
+#[cfg_attr(not(target_arch = "spirv"), derive(Clone, Copy))]
+#[repr(C)]
+pub struct Instance {
+    pub transform: Mat4,
+    // last frame's transform, for motion vectors
+    pub previous_transform: Mat4,
+    // object space; xyz: center, w: radius
+    pub bounding_sphere: Vec4,
+    pub vertex_buffer_address: u64,
+    // index range within the shared scene index buffer
+    pub first_index: u32,
+    pub index_count: u32,
+    // compact vertices only; object-space box the positions are quantized within
+    pub quantization_origin: Vec4,
+    pub quantization_extent: Vec4,
+    // x: VERTEX_FORMAT_*, y: 1 when skinning or morphing rewrites the vertices every frame; such
+    // instances outgrow their bind-pose bounds and are never culled
+    pub vertex_format: UVec4,
+    // x: normal texture, y: base color texture, NO_TEXTURE when none; index the bindless texture
+    // table
+    pub textures: UVec4,
+    // base color factor; multiplies the vertex color and the base color texture
+    pub base_color: Vec4,
+    // x: normal scale, y: metallic, z: roughness, w: alpha cutoff, 0 unless the material is masked
+    pub factors: Vec4,
+    // x: first draw command of the instance's draw group, or the instance's own command when z is
+    // 1, y: index of the group's draw count in the cull counters, z: 1 when the group keeps one
+    // command per instance, in instance order
+    pub draw_group: UVec4,
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl Default for Instance {
     fn default() -> Self {
         Self {
-            world_transform: Mat4::IDENTITY,
+            transform: Mat4::IDENTITY,
+            previous_transform: Mat4::IDENTITY,
+            bounding_sphere: Vec4::ZERO,
             vertex_buffer_address: Default::default(),
+            first_index: 0,
+            index_count: 0,
+            quantization_origin: Vec4::ZERO,
+            quantization_extent: Vec4::ONE,
+            vertex_format: UVec4::ZERO,
+            textures: UVec4::splat(NO_TEXTURE),
+            base_color: Vec4::ONE,
+            factors: Vec4::new(1.0, 1.0, 1.0, 0.0),
+            draw_group: UVec4::ZERO,
         }
     }
 }
 
+pub const NO_TEXTURE: u32 = u32::MAX;
+// size of the bindless texture table
+pub const MAX_TEXTURES: u32 = 1024;
+
+// Mirrors VkDrawIndexedIndirectCommand.
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct DrawIndexedIndirectCommand {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub vertex_offset: i32,
+    pub first_instance: u32,
+}
+
+pub const MAX_LODS: usize = 4;
+// fraction of the pixel threshold a level must clear before switching; avoids popping back and forth
+pub const LOD_HYSTERESIS: f32 = 0.1;
+
+// Index ranges of one instance's levels of detail, parallel to the instance buffer. Level 0 is the
+// full-detail surface; errors are object-space and grow with the level.
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct InstanceLods {
+    pub first_indices: UVec4,
+    pub index_counts: UVec4,
+    pub errors: Vec4,
+    // x: level count
+    pub counts: UVec4,
+}
+
+// Keeps the previous level unless the projected error of a neighbouring level clears the threshold
+// by the hysteresis margin. error_scale converts object-space error to threshold units: world scale
+// times pixels per unit at distance 1, over the distance and the pixel threshold. <= 0 forces level 0.
+pub fn select_lod(lods: &InstanceLods, error_scale: f32, previous: u32) -> u32 {
+    if error_scale <= 0.0 || lods.counts.x <= 1 {
+        return 0;
+    }
+
+    let last = lods.counts.x - 1;
+    let mut level = if previous < last { previous } else { last };
+    while level > 0 && lods.errors[level as usize] * error_scale > 1.0 + LOD_HYSTERESIS {
+        level -= 1;
+    }
+    while level < last && lods.errors[level as usize + 1] * error_scale < 1.0 - LOD_HYSTERESIS {
+        level += 1;
+    }
+    level
+}
+
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct CullPushConstants {
+    // left, right, bottom, top, near, far; xyz: normal pointing inwards, w: distance
+    pub frustum_planes: [Vec4; 6],
+    // x: instance count
+    pub counts: UVec4,
+    // xyz: camera position, w: pixels per world unit at distance 1 over the LOD pixel threshold
+    pub lod: Vec4,
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl CullPushConstants {
+    // Gribb-Hartmann plane extraction; expects a reverse-Z projection with [0, 1] depth.
+    pub fn frustum(mut self, view_projection: Mat4) -> Self {
+        let row = |index: usize| view_projection.row(index);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) - row(2),
+            row(2),
+        ];
+        self.frustum_planes = planes.map(|plane| plane / plane.xyz().length());
+        self
+    }
+
+    pub fn instance_count(mut self, instance_count: u32) -> Self {
+        self.counts.x = instance_count;
+        self
+    }
+
+    pub fn lod(mut self, camera_position: Vec3, lod_scale: f32) -> Self {
+        self.lod = Vec4::from((camera_position, lod_scale));
+        self
+    }
+}
+
+// Hi-Z occlusion test inputs; written into the cull pass' storage buffer ahead of every dispatch.
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct OcclusionData {
+    // view-projection the depth pyramid was rendered with
+    pub view_projection: Mat4,
+    // xy: pyramid size in texels, z: mip count, w: 1 when the test is enabled and the pyramid is valid
+    pub pyramid: Vec4,
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl OcclusionData {
+    pub fn view_projection(mut self, view_projection: Mat4) -> Self {
+        self.view_projection = view_projection;
+        self
+    }
+
+    pub fn pyramid(mut self, width: u32, height: u32, mip_levels: u32, enabled: bool) -> Self {
+        self.pyramid = Vec4::new(
+            width as f32,
+            height as f32,
+            mip_levels as f32,
+            enabled as u32 as f32,
+        );
+        self
+    }
+}
+
 #[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
 #[repr(C)]
 pub struct Vertex {
     pub position_uv_x: Vec4,
     pub normal_uv_y: Vec4,
     pub color: Vec4,
+    // xyz: tangent, w: bitangent sign (glTF convention); all zero when the vertex has none
+    pub tangent: Vec4,
 }
 
 #[cfg(not(target_arch = "spirv"))]
@@ -70,12 +352,622 @@ impl Vertex {
             position_uv_x: Vec4::from((position, uv.x)),
             normal_uv_y: Vec4::from((normal, uv.y)),
             color,
+            tangent: Vec4::ZERO,
+        }
+    }
+
+    pub fn tangent(mut self, tangent: Vec4) -> Self {
+        self.tangent = tangent;
+        self
+    }
+}
+
+// Instance::vertex_format values
+pub const VERTEX_FORMAT_FULL: u32 = 0;
+pub const VERTEX_FORMAT_COMPACT: u32 = 1;
+
+// Layout of a mesh's vertex buffer; chosen per mesh.
+#[cfg(not(target_arch = "spirv"))]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u32)]
+pub enum VertexFormat {
+    #[default]
+    Full = VERTEX_FORMAT_FULL,
+    Compact = VERTEX_FORMAT_COMPACT,
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl VertexFormat {
+    pub fn get_size(&self) -> u64 {
+        match self {
+            Self::Full => VERTEX_SIZE,
+            Self::Compact => COMPACT_VERTEX_SIZE,
+        }
+    }
+}
+
+// Vertex in half the bytes: positions are unorm16 within the mesh's quantization box, normals and
+// tangents octahedral snorm16, UVs half floats and colors unorm8.
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct CompactVertex {
+    // x | y << 16
+    pub position_xy: u32,
+    // z | tangent flags << 16
+    pub position_z_sign: u32,
+    pub normal: u32,
+    pub tangent: u32,
+    pub uv: u32,
+    // r in the low byte
+    pub color: u32,
+}
+
+// CompactVertex::position_z_sign high half
+const TANGENT_NEGATIVE: u32 = 1 << 16;
+const TANGENT_NONE: u32 = 1 << 17;
+
+impl CompactVertex {
+    #[cfg(not(target_arch = "spirv"))]
+    pub fn encode(vertex: &Vertex, origin: Vec3, extent: Vec3) -> Self {
+        let position =
+            (vertex.position_uv_x.xyz() - origin) / extent.max(Vec3::splat(f32::EPSILON));
+        let position = pack_unorm16(position);
+        let tangent_flags = if vertex.tangent.w == 0.0 {
+            TANGENT_NONE
+        } else if vertex.tangent.w < 0.0 {
+            TANGENT_NEGATIVE
+        } else {
+            0
+        };
+        Self {
+            position_xy: position.x | position.y << 16,
+            position_z_sign: position.z | tangent_flags,
+            normal: pack_snorm2x16(encode_octahedral(vertex.normal_uv_y.xyz())),
+            tangent: pack_snorm2x16(encode_octahedral(vertex.tangent.xyz())),
+            uv: pack_half2x16(Vec2::new(vertex.position_uv_x.w, vertex.normal_uv_y.w)),
+            color: pack_unorm4x8(vertex.color),
+        }
+    }
+
+    pub fn decode(&self, origin: Vec3, extent: Vec3) -> Vertex {
+        let position = Vec3::new(
+            (self.position_xy & 0xffff) as f32,
+            (self.position_xy >> 16) as f32,
+            (self.position_z_sign & 0xffff) as f32,
+        ) / 65535.0;
+        let position = origin + position * extent;
+        let normal = decode_octahedral(unpack_snorm2x16(self.normal));
+        let uv = unpack_half2x16(self.uv);
+        let tangent = match self.position_z_sign & TANGENT_NONE != 0 {
+            true => Vec4::ZERO,
+            false => {
+                let sign = match self.position_z_sign & TANGENT_NEGATIVE != 0 {
+                    true => -1.0,
+                    false => 1.0,
+                };
+                Vec4::from((decode_octahedral(unpack_snorm2x16(self.tangent)), sign))
+            }
+        };
+        Vertex {
+            position_uv_x: Vec4::from((position, uv.x)),
+            normal_uv_y: Vec4::from((normal, uv.y)),
+            color: unpack_unorm4x8(self.color),
+            tangent,
+        }
+    }
+}
+
+// Joints and weights of one skinned vertex, parallel to the mesh's vertices; joints index the
+// skin's joint list.
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Default, Clone, Copy, PartialEq, Debug)
+)]
+#[repr(C)]
+pub struct SkinVertex {
+    pub joints: UVec4,
+    pub weights: Vec4,
+}
+
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct SkinningPushConstants {
+    // x: first source vertex, y: first output vertex, z: vertex count, w: first joint matrix
+    pub offsets: UVec4,
+    // x: morph target count, y: first morph weight, z: 1 if skinned, w: unused
+    pub morph: UVec4,
+}
+
+#[cfg(not(target_arch = "spirv"))]
+impl SkinningPushConstants {
+    pub fn offsets(
+        mut self,
+        source_offset: u32,
+        output_offset: u32,
+        vertex_count: u32,
+        joint_offset: u32,
+    ) -> Self {
+        self.offsets = UVec4::new(source_offset, output_offset, vertex_count, joint_offset);
+        self
+    }
+
+    pub fn morph(mut self, target_count: u32, weight_offset: u32, skinned: bool) -> Self {
+        self.morph = UVec4::new(target_count, weight_offset, skinned as u32, 0);
+        self
+    }
+}
+
+// One morph target's displacement of one vertex; w is unused. A mesh's deltas are target-major:
+// target t's delta for vertex v is at t * vertex count + v.
+#[cfg_attr(
+    not(target_arch = "spirv"),
+    derive(Default, Clone, Copy, PartialEq, Debug)
+)]
+#[repr(C)]
+pub struct MorphDelta {
+    pub position: Vec4,
+    pub normal: Vec4,
+    pub tangent: Vec4,
+}
+
+// Adds a weighted morph target delta; normals and tangents are left unnormalized, as further
+// targets may follow. The tangent's bitangent sign is kept.
+pub fn morph_vertex(vertex: &Vertex, delta: &MorphDelta, weight: f32) -> Vertex {
+    Vertex {
+        position_uv_x: vertex.position_uv_x + Vec4::from((delta.position.xyz() * weight, 0.0)),
+        normal_uv_y: vertex.normal_uv_y + Vec4::from((delta.normal.xyz() * weight, 0.0)),
+        color: vertex.color,
+        tangent: vertex.tangent + Vec4::from((delta.tangent.xyz() * weight, 0.0)),
+    }
+}
+
+// Weighted sum of the vertex's joint matrices. Weights are renormalized; vertices without any
+// weight stay in bind pose.
+pub fn get_skin_matrix(weights: Vec4, joint_matrices: [Mat4; 4]) -> Mat4 {
+    let total = weights.x + weights.y + weights.z + weights.w;
+    if total <= 0.0 {
+        return Mat4::IDENTITY;
+    }
+    let weights = weights / total;
+    joint_matrices[0] * weights.x
+        + joint_matrices[1] * weights.y
+        + joint_matrices[2] * weights.z
+        + joint_matrices[3] * weights.w
+}
+
+// Linear blend skinning. NB! normals and tangents assume joints without non-uniform scale
+pub fn skin_vertex(vertex: &Vertex, skin_matrix: Mat4) -> Vertex {
+    let position = skin_matrix.transform_point3(vertex.position_uv_x.xyz());
+    let normal = skin_matrix
+        .transform_vector3(vertex.normal_uv_y.xyz())
+        .normalize_or_zero();
+    let tangent = skin_matrix
+        .transform_vector3(vertex.tangent.xyz())
+        .normalize_or_zero();
+    Vertex {
+        position_uv_x: Vec4::from((position, vertex.position_uv_x.w)),
+        normal_uv_y: Vec4::from((normal, vertex.normal_uv_y.w)),
+        color: vertex.color,
+        tangent: Vec4::from((tangent, vertex.tangent.w)),
+    }
+}
+
+// Packing helpers; the unpackers match GLSL's unpack*() built-ins bit for bit.
+#[cfg(not(target_arch = "spirv"))]
+pub fn pack_unorm16(value: Vec3) -> UVec3 {
+    (value.clamp(Vec3::ZERO, Vec3::ONE) * 65535.0)
+        .round()
+        .as_uvec3()
+}
+
+#[cfg(not(target_arch = "spirv"))]
+pub fn pack_snorm2x16(value: Vec2) -> u32 {
+    let value = (value.clamp(Vec2::NEG_ONE, Vec2::ONE) * 32767.0)
+        .round()
+        .as_ivec2();
+    (value.x as u32 & 0xffff) | (value.y as u32) << 16
+}
+
+pub fn unpack_snorm2x16(bits: u32) -> Vec2 {
+    let value = Vec2::new(
+        ((bits << 16) as i32 >> 16) as f32,
+        (bits as i32 >> 16) as f32,
+    );
+    (value / 32767.0).max(Vec2::NEG_ONE)
+}
+
+#[cfg(not(target_arch = "spirv"))]
+pub fn pack_unorm4x8(value: Vec4) -> u32 {
+    let value = (value.clamp(Vec4::ZERO, Vec4::ONE) * 255.0)
+        .round()
+        .as_uvec4();
+    value.x | value.y << 8 | value.z << 16 | value.w << 24
+}
+
+pub fn unpack_unorm4x8(bits: u32) -> Vec4 {
+    Vec4::new(
+        (bits & 0xff) as f32,
+        (bits >> 8 & 0xff) as f32,
+        (bits >> 16 & 0xff) as f32,
+        (bits >> 24) as f32,
+    ) / 255.0
+}
+
+#[cfg(not(target_arch = "spirv"))]
+pub fn pack_half2x16(value: Vec2) -> u32 {
+    f32_to_f16(value.x) | f32_to_f16(value.y) << 16
+}
+
+pub fn unpack_half2x16(bits: u32) -> Vec2 {
+    Vec2::new(f16_to_f32(bits & 0xffff), f16_to_f32(bits >> 16))
+}
+
+// Round to nearest even; out of range values become infinities, tiny ones subnormals or zero.
+#[cfg(not(target_arch = "spirv"))]
+fn f32_to_f16(value: f32) -> u32 {
+    let bits = value.to_bits();
+    let sign = bits >> 16 & 0x8000;
+    let exponent = (bits >> 23 & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let exponent = exponent - 112;
+    if exponent >= 31 {
+        return sign | 0x7c00;
+    }
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let round = mantissa >> (shift - 1) & 1;
+        return sign | ((mantissa >> shift) + round);
+    }
+
+    let half = (exponent as u32) << 10 | mantissa >> 13;
+    let remainder = mantissa & 0x1fff;
+    let round = (remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1)) as u32;
+    // NB! a carry out of the mantissa correctly bumps the exponent
+    sign | (half + round)
+}
+
+fn f16_to_f32(bits: u32) -> f32 {
+    let sign = (bits & 0x8000) << 16;
+    let exponent = bits >> 10 & 0x1f;
+    let mantissa = bits & 0x3ff;
+
+    if exponent == 0 {
+        // subnormal; multiples of 2^-24
+        let magnitude = mantissa as f32 / 16_777_216.0;
+        return f32::from_bits(sign | magnitude.to_bits());
+    }
+    if exponent == 31 {
+        return f32::from_bits(sign | 0x7f80_0000 | mantissa << 13);
+    }
+    f32::from_bits(sign | (exponent + 112) << 23 | mantissa << 13)
+}
+
+// Octahedral unit vector encoding (Meyer et al.); both components in [-1, 1].
+#[cfg(not(target_arch = "spirv"))]
+pub fn encode_octahedral(normal: Vec3) -> Vec2 {
+    let length = normal.abs().element_sum();
+    if length == 0.0 {
+        return Vec2::ZERO;
+    }
+
+    let normal = normal / length;
+    match normal.z >= 0.0 {
+        true => normal.truncate(),
+        // fold the lower hemisphere over the diagonals
+        false => (Vec2::ONE - Vec2::new(normal.y, normal.x).abs()) * normal.truncate().signum(),
+    }
+}
+
+pub fn decode_octahedral(encoded: Vec2) -> Vec3 {
+    let z = 1.0 - encoded.x.abs() - encoded.y.abs();
+    let fold = (-z).max(0.0);
+    let x = encoded.x + if encoded.x >= 0.0 { -fold } else { fold };
+    let y = encoded.y + if encoded.y >= 0.0 { -fold } else { fold };
+    Vec3::new(x, y, z).normalize_or_zero()
+}
+
+// The rgba16f storage images the compute passes read and write.
+pub type StorageImage2 = Image!(2D, format = rgba16f, sampled = false, depth = false);
+
+// Reads the texel at coord, clamped to the image's edges.
+pub fn load_clamped(image: &StorageImage2, coord: IVec2, image_size: UVec2) -> Vec4 {
+    let clamped = coord.clamp(IVec2::ZERO, image_size.as_ivec2() - IVec2::ONE);
+    image.read(clamped.as_uvec2())
+}
+
+// Storage images can't be filtered; emulate a bilinear fetch with four loads.
+pub fn sample_bilinear(image: &StorageImage2, uv: Vec2, image_size: UVec2) -> Vec4 {
+    let position = uv * image_size.as_vec2() - Vec2::splat(0.5);
+    let base = position.floor();
+    let weight = position - base;
+    let coord = base.as_ivec2();
+
+    let top = load_clamped(image, coord, image_size).lerp(
+        load_clamped(image, coord + IVec2::new(1, 0), image_size),
+        weight.x,
+    );
+    let bottom = load_clamped(image, coord + IVec2::new(0, 1), image_size).lerp(
+        load_clamped(image, coord + IVec2::new(1, 1), image_size),
+        weight.x,
+    );
+    top.lerp(bottom, weight.y)
+}
+
+// World-space direction of the view ray through the center of texel_coord.
+pub fn get_view_direction(
+    inverse_view_projection: Mat4,
+    texel_coord: UVec2,
+    image_size: UVec2,
+) -> Vec3 {
+    // y is flipped by the negative viewport height; reverse-Z puts the far plane at 0
+    let uv = (texel_coord.as_vec2() + Vec2::splat(0.5)) / image_size.as_vec2();
+    let ndc = Vec2::new(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    let near = inverse_view_projection * Vec4::new(ndc.x, ndc.y, 1.0, 1.0);
+    let far = inverse_view_projection * Vec4::new(ndc.x, ndc.y, 0.0, 1.0);
+    (far.xyz() / far.w - near.xyz() / near.w).normalize()
+}
+
+// CPU stand-in for a 2D compute dispatch over an image, to test shader logic without a GPU. Runs
+// every invocation of the workgroups the host would dispatch and stores what each writes at its
+// global_invocation_id.xy; texels nothing writes stay NaN.
+#[cfg(all(not(target_arch = "spirv"), feature = "test-support"))]
+pub fn dispatch_2d(
+    image_size: UVec2,
+    group_size: UVec2,
+    invocation: impl Fn(UVec3) -> Option<Vec4>,
+) -> Vec<Vec4> {
+    let mut image = vec![Vec4::NAN; (image_size.x * image_size.y) as usize];
+    let invocation_count = (image_size + group_size - UVec2::ONE) / group_size * group_size;
+    for y in 0..invocation_count.y {
+        for x in 0..invocation_count.x {
+            let Some(color) = invocation(UVec3::new(x, y, 0)) else {
+                continue;
+            };
+            if x >= image_size.x || y >= image_size.y {
+                panic!("koi_gpu - invocation ({x}, {y}) wrote outside the {image_size} image");
+            }
+            image[(y * image_size.x + x) as usize] = color;
         }
     }
+    image
+}
+
+// Generic compute push constants; what each vec4 holds is up to the shader.
+#[cfg_attr(not(target_arch = "spirv"), derive(Default, Clone, Copy))]
+#[repr(C)]
+pub struct ComputePushConstants {
+    pub data_0: Vec4,
+    pub data_1: Vec4,
+    pub data_2: Vec4,
+    pub data_3: Vec4,
+    pub data_4: Vec4,
 }
 
 #[cfg(not(target_arch = "spirv"))]
+impl ComputePushConstants {
+    pub fn data_0(mut self, data_0: Vec4) -> Self {
+        self.data_0 = data_0;
+        self
+    }
+    pub fn data_1(mut self, data_1: Vec4) -> Self {
+        self.data_1 = data_1;
+        self
+    }
+    pub fn data_2(mut self, data_2: Vec4) -> Self {
+        self.data_2 = data_2;
+        self
+    }
+    pub fn data_3(mut self, data_3: Vec4) -> Self {
+        self.data_3 = data_3;
+        self
+    }
+    pub fn data_4(mut self, data_4: Vec4) -> Self {
+        self.data_4 = data_4;
+        self
+    }
+}
+
+// std430/scalar layouts as the shaders see them; vec4s and mat4s are 16-byte aligned on both sides.
+gpu_layout!(FrameData, 176, {
+    view_projection: 0,
+    previous_view_projection: 64,
+    jitter: 128,
+    camera_position: 144,
+    environment: 160,
+});
+
+gpu_layout!(PushConstants, 8, {
+    frame_data_address: 0,
+});
+
+gpu_layout!(Instance, 272, {
+    transform: 0,
+    previous_transform: 64,
+    bounding_sphere: 128,
+    vertex_buffer_address: 144,
+    first_index: 152,
+    index_count: 156,
+    quantization_origin: 160,
+    quantization_extent: 176,
+    vertex_format: 192,
+    textures: 208,
+    base_color: 224,
+    factors: 240,
+    draw_group: 256,
+});
+
+gpu_layout!(DrawIndexedIndirectCommand, 20, {
+    index_count: 0,
+    instance_count: 4,
+    first_index: 8,
+    vertex_offset: 12,
+    first_instance: 16,
+});
+
+gpu_layout!(InstanceLods, 64, {
+    first_indices: 0,
+    index_counts: 16,
+    errors: 32,
+    counts: 48,
+});
+
+gpu_layout!(CullPushConstants, 128, {
+    frustum_planes: 0,
+    counts: 96,
+    lod: 112,
+});
+
+gpu_layout!(OcclusionData, 80, {
+    view_projection: 0,
+    pyramid: 64,
+});
+
+gpu_layout!(Vertex, 64, {
+    position_uv_x: 0,
+    normal_uv_y: 16,
+    color: 32,
+    tangent: 48,
+});
+
+gpu_layout!(CompactVertex, 24, {
+    position_xy: 0,
+    position_z_sign: 4,
+    normal: 8,
+    tangent: 12,
+    uv: 16,
+    color: 20,
+});
+
+gpu_layout!(SkinVertex, 32, {
+    joints: 0,
+    weights: 16,
+});
+
+gpu_layout!(SkinningPushConstants, 32, {
+    offsets: 0,
+    morph: 16,
+});
+
+gpu_layout!(MorphDelta, 48, {
+    position: 0,
+    normal: 16,
+    tangent: 32,
+});
+
+gpu_layout!(ComputePushConstants, 80, {
+    data_0: 0,
+    data_1: 16,
+    data_2: 32,
+    data_3: 48,
+    data_4: 64,
+});
+
+// NB! also the strides of device-address vertex loads in the vertex shader
 pub const VERTEX_SIZE: u64 = size_of::<Vertex>() as u64;
 
+pub const COMPACT_VERTEX_SIZE: u64 = size_of::<CompactVertex>() as u64;
+
+#[cfg(not(target_arch = "spirv"))]
+pub const INSTANCE_SIZE: u64 = size_of::<Instance>() as u64;
+
 #[cfg(not(target_arch = "spirv"))]
-pub const PUSH_CONSTANTS_SIZE: u64 = size_of::<PushConstants>() as u64;
+pub const DRAW_INDEXED_INDIRECT_COMMAND_SIZE: u64 = size_of::<DrawIndexedIndirectCommand>() as u64;
+
+#[cfg(not(target_arch = "spirv"))]
+pub const OCCLUSION_DATA_SIZE: u64 = size_of::<OcclusionData>() as u64;
+
+#[cfg(not(target_arch = "spirv"))]
+pub const MORPH_DELTA_SIZE: u64 = size_of::<MorphDelta>() as u64;
+
+// The push constant space Vulkan guarantees; every push constant block must fit in it.
+pub const MAX_PUSH_CONSTANTS_SIZE: usize = 128;
+
+#[cfg(not(target_arch = "spirv"))]
+const _: () = {
+    assert!(size_of::<PushConstants>() <= MAX_PUSH_CONSTANTS_SIZE);
+    assert!(size_of::<ComputePushConstants>() <= MAX_PUSH_CONSTANTS_SIZE);
+    assert!(size_of::<CullPushConstants>() <= MAX_PUSH_CONSTANTS_SIZE);
+    assert!(size_of::<SkinningPushConstants>() <= MAX_PUSH_CONSTANTS_SIZE);
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: Vec4, expected: Vec4, tolerance: f32, name: &str) {
+        assert!(
+            actual.abs_diff_eq(expected, tolerance),
+            "{name}: {actual}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn compact_vertex_round_trips() {
+        let origin = Vec3::new(-2.0, 0.5, -8.0);
+        let extent = Vec3::new(4.0, 1.0, 16.0);
+        let vertices = [
+            Vertex {
+                position_uv_x: Vec4::new(-2.0, 0.5, -8.0, 0.0),
+                normal_uv_y: Vec4::new(0.0, 1.0, 0.0, 1.0),
+                color: Vec4::new(1.0, 0.0, 0.5, 1.0),
+                tangent: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            },
+            Vertex {
+                position_uv_x: Vec4::new(1.3, 1.1, 7.9, 0.37),
+                normal_uv_y: Vec4::from((Vec3::new(-0.3, -0.5, -0.8).normalize(), 0.81)),
+                color: Vec4::new(0.2, 0.4, 0.6, 0.8),
+                tangent: Vec4::from((Vec3::new(0.8, -0.5, 0.3).normalize(), -1.0)),
+            },
+            Vertex {
+                position_uv_x: Vec4::new(2.0, 1.5, 8.0, 2.5),
+                normal_uv_y: Vec4::new(0.0, 0.0, -1.0, -0.25),
+                color: Vec4::ZERO,
+                // no tangent
+                tangent: Vec4::ZERO,
+            },
+        ];
+
+        // about one quantization step per channel
+        let position_tolerance = extent.max_element() / 65535.0;
+        let direction_tolerance = 1e-4;
+        let uv_tolerance = 2e-3;
+        let color_tolerance = 1.0 / 255.0;
+        for vertex in &vertices {
+            let decoded = CompactVertex::encode(vertex, origin, extent).decode(origin, extent);
+            assert_close(
+                decoded.position_uv_x.xyz().extend(0.0),
+                vertex.position_uv_x.xyz().extend(0.0),
+                position_tolerance,
+                "position",
+            );
+            assert_close(
+                decoded.normal_uv_y.xyz().extend(0.0),
+                vertex.normal_uv_y.xyz().extend(0.0),
+                direction_tolerance,
+                "normal",
+            );
+            assert_close(
+                decoded.tangent,
+                vertex.tangent,
+                direction_tolerance,
+                "tangent",
+            );
+            assert_close(
+                Vec4::new(decoded.position_uv_x.w, decoded.normal_uv_y.w, 0.0, 0.0),
+                Vec4::new(vertex.position_uv_x.w, vertex.normal_uv_y.w, 0.0, 0.0),
+                uv_tolerance,
+                "uv",
+            );
+            assert_close(decoded.color, vertex.color, color_tolerance, "color");
+        }
+    }
+}
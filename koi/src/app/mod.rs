@@ -7,6 +7,7 @@ use crate::scene::Scene;
 
 use std::ffi::CStr;
 use std::path::Path;
+use std::time::Instant;
 use winit::dpi::PhysicalSize;
 use winit::{application, dpi, error, event, event_loop, window};
 
@@ -15,6 +16,9 @@ pub struct Runtime {
     pub ren: ren::Handle,
     pub imgui: imgui::ImGui,
     pub scene: Option<Scene>,
+    pub animation_player: scene::animation::AnimationPlayer,
+    // start of the previous update
+    pub now: Instant,
 }
 
 impl Drop for Runtime {
@@ -30,6 +34,8 @@ impl Runtime {
             ren,
             imgui,
             scene: None,
+            animation_player: scene::animation::AnimationPlayer::default(),
+            now: Instant::now(),
         }
     }
 
@@ -37,6 +43,12 @@ impl Runtime {
         let scene = scene::load(path);
         self.ren.load_scene(&scene);
         self.scene = Some(scene);
+        self.animation_player = scene::animation::AnimationPlayer::default();
+    }
+
+    pub fn load_environment(&mut self, path: &Path) {
+        let environment = scene::environment::load(path);
+        self.ren.load_environment(&environment);
     }
 
     pub fn handle_resize(&mut self, width: u32, height: u32) {
@@ -44,7 +56,20 @@ impl Runtime {
     }
 
     fn update(&mut self) {
-        self.imgui.update(&self.window, &mut self.ren);
+        let now = Instant::now();
+        let delta = now.duration_since(self.now).as_secs_f32();
+        self.now = now;
+        if let Some(scene) = &mut self.scene {
+            self.animation_player.update(scene, delta);
+            self.ren.update_scene(scene);
+        }
+
+        self.imgui.update(
+            &self.window,
+            &mut self.ren,
+            self.scene.as_mut(),
+            &mut self.animation_player,
+        );
         self.ren.draw(&mut self.imgui);
         self.window.request_redraw();
     }
@@ -113,6 +138,11 @@ impl application::ApplicationHandler for App<'_> {
             event::WindowEvent::RedrawRequested => {
                 if runtime.scene.is_none() {
                     runtime.load_scene(Path::new("assets/models/test.glb"));
+                    // optional; lighting falls back to the default environment
+                    let environment_path = Path::new("assets/environments/environment.hdr");
+                    if environment_path.exists() {
+                        runtime.load_environment(environment_path);
+                    }
                 }
                 runtime.update();
             }
@@ -3,6 +3,8 @@ use crate::ren::api::dx::Renderer as dxRenderer;
 #[cfg(feature = "vulkan")]
 use crate::ren::api::vk::Renderer as vkRenderer;
 use crate::ren::{self, Handle as Renderer};
+use crate::scene::Scene;
+use crate::scene::animation::{AnimationPlayer, Blend};
 
 use imgui::{Context, FontSource, StyleColor};
 use imgui_winit_support::{HiDpiMode, WinitPlatform};
@@ -123,7 +125,13 @@ impl ImGui {
             .handle_window_event(self.context.io_mut(), window_handle, event);
     }
 
-    pub fn update(&mut self, window_handle: &WindowHandle, ren: &mut Renderer) {
+    pub fn update(
+        &mut self,
+        window_handle: &WindowHandle,
+        ren: &mut Renderer,
+        scene: Option<&mut Scene>,
+        animation_player: &mut AnimationPlayer,
+    ) {
         self.tick();
         self.platform
             .prepare_frame(self.context.io_mut(), &window_handle)
@@ -157,18 +165,15 @@ impl ImGui {
                             "Starfield Threshold",
                             0.98,
                             1.0,
-                            &mut compute_effect.push_constants.data_1.w,
+                            &mut compute_effect.push_constants.data_4.w,
                         );
+                        ui.slider("Time of Day", 0.0, 24.0, &mut draw_manager.time_of_day);
                     }
-                    ui.color_picker4(
-                        if is_sky {
-                            "Sky Color"
-                        } else {
-                            "Gradient Top Color"
-                        },
-                        compute_effect.push_constants.data_0.as_mut(),
-                    );
                     if is_gradient {
+                        ui.color_picker4(
+                            "Gradient Top Color",
+                            compute_effect.push_constants.data_0.as_mut(),
+                        );
                         ui.color_picker4(
                             "Gradient Bottom Color",
                             compute_effect.push_constants.data_1.as_mut(),
@@ -177,6 +182,195 @@ impl ImGui {
                 }
             });
 
+        ui.window("Lighting")
+            .size([300.0, 100.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                #[cfg(feature = "vulkan")]
+                {
+                    let draw_manager = &mut ren.api.draw_manager;
+                    ui.slider("IBL Intensity", 0.0, 4.0, &mut draw_manager.ibl.intensity);
+                }
+            });
+
+        ui.window("Scene")
+            .size([300.0, 160.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                #[cfg(feature = "vulkan")]
+                {
+                    let draw_manager = &mut ren.api.draw_manager;
+                    if draw_manager.wireframe_supported {
+                        ui.checkbox("Wireframe", &mut draw_manager.wireframe);
+                    }
+                    let gpu_scene = &mut draw_manager.gpu_scene;
+                    ui.text(format!("Instances: {}", gpu_scene.instances.len()));
+                    ui.text(format!("Batches: {}", gpu_scene.batches.len()));
+                    ui.text(format!("Draw Groups: {}", gpu_scene.draw_groups.len()));
+                    ui.checkbox("GPU-Driven Culling", &mut gpu_scene.gpu_driven);
+                    if gpu_scene.gpu_driven {
+                        ui.checkbox("Occlusion Culling", &mut gpu_scene.occlusion_culling);
+                    }
+                    ui.checkbox("LOD", &mut gpu_scene.lod_enabled);
+                    if gpu_scene.lod_enabled {
+                        ui.slider(
+                            "LOD Threshold (px)",
+                            0.25,
+                            8.0,
+                            &mut gpu_scene.lod_threshold,
+                        );
+                    }
+                    let statistics = gpu_scene.statistics;
+                    ui.text(format!("Visible: {}", statistics.visible));
+                    ui.text(format!("Frustum Culled: {}", statistics.frustum_culled));
+                    if gpu_scene.gpu_driven {
+                        ui.text(format!("Occlusion Culled: {}", statistics.occlusion_culled));
+                    }
+                }
+            });
+
+        // NB! the player poses the scene at the start of the next frame
+        if let Some(scene) = scene
+            .as_deref()
+            .filter(|scene| !scene.animations.is_empty())
+        {
+            ui.window("Animation")
+                .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    let names: Vec<String> = scene
+                        .animations
+                        .iter()
+                        .enumerate()
+                        .map(|(index, animation)| match animation.name.is_empty() {
+                            true => format!("Animation {index}"),
+                            false => animation.name.clone(),
+                        })
+                        .collect();
+                    let mut animation =
+                        animation_player.animation.unwrap_or(0).min(names.len() - 1);
+                    if ui.combo_simple_string("Clip", &mut animation, &names) {
+                        animation_player.set_animation(Some(animation));
+                    }
+                    let duration = scene.animations[animation].duration;
+
+                    match animation_player.playing {
+                        true if ui.button("Pause") => animation_player.pause(),
+                        false if ui.button("Play") => {
+                            // a finished clip starts over
+                            if !animation_player.looping && animation_player.time >= duration {
+                                animation_player.seek(0.0);
+                            }
+                            animation_player.animation = Some(animation);
+                            animation_player.play();
+                        }
+                        _ => {}
+                    }
+                    ui.same_line();
+                    ui.checkbox("Loop", &mut animation_player.looping);
+                    ui.slider("Speed", -2.0, 2.0, &mut animation_player.speed);
+
+                    let mut time = animation_player.time;
+                    if ui.slider("Time", 0.0, duration, &mut time) {
+                        animation_player.pause();
+                        animation_player.seek(time);
+                    }
+
+                    let mut blending = animation_player.blend.is_some();
+                    if ui.checkbox("Blend", &mut blending) {
+                        animation_player.blend = blending.then_some(Blend {
+                            animation,
+                            weight: 0.5,
+                        });
+                    }
+                    if let Some(blend) = &mut animation_player.blend {
+                        ui.combo_simple_string("Blend Clip", &mut blend.animation, &names);
+                        ui.slider("Blend Weight", 0.0, 1.0, &mut blend.weight);
+                    }
+                });
+        }
+
+        // NB! edits land on the next frame, and animated weights are overwritten by playback
+        if let Some(scene) = scene {
+            ui.window("Morph Targets")
+                .size([300.0, 200.0], imgui::Condition::FirstUseEver)
+                .build(|| {
+                    for (node_index, node) in scene.nodes.iter_mut().enumerate() {
+                        if node.weights.is_empty() {
+                            continue;
+                        }
+                        let _id = ui.push_id_usize(node_index);
+                        ui.text(format!("{} ({node_index})", node.name));
+                        for (target, weight) in node.weights.iter_mut().enumerate() {
+                            ui.slider(format!("Target {target}"), 0.0, 1.0, weight);
+                        }
+                    }
+                });
+        }
+
+        #[cfg(all(feature = "vulkan", feature = "hot-reload"))]
+        {
+            let shader_watcher = &ren.api.shader_watcher;
+            if !shader_watcher.compiling.is_empty() || !shader_watcher.errors.is_empty() {
+                ui.window("Shaders")
+                    .size([500.0, 300.0], imgui::Condition::FirstUseEver)
+                    .build(|| {
+                        for name in &shader_watcher.compiling {
+                            ui.text_disabled(format!("compiling {name}..."));
+                        }
+                        for (name, error) in &shader_watcher.errors {
+                            ui.text_colored([1.0, 0.4, 0.4, 1.0], name);
+                            ui.text_wrapped(error);
+                        }
+                    });
+            }
+        }
+
+        ui.window("Post Processing")
+            .size([300.0, 120.0], imgui::Condition::FirstUseEver)
+            .build(|| {
+                #[cfg(feature = "vulkan")]
+                {
+                    use ren::api::vk::post::AntiAliasing;
+
+                    let post_processing = &mut ren.api.draw_manager.post_processing;
+
+                    ui.text("Anti-Aliasing");
+                    ui.radio_button(
+                        "None",
+                        &mut post_processing.anti_aliasing,
+                        AntiAliasing::None,
+                    );
+                    ui.same_line();
+                    ui.radio_button(
+                        "FXAA",
+                        &mut post_processing.anti_aliasing,
+                        AntiAliasing::Fxaa,
+                    );
+                    ui.same_line();
+                    ui.radio_button("TAA", &mut post_processing.anti_aliasing, AntiAliasing::Taa);
+
+                    match post_processing.anti_aliasing {
+                        AntiAliasing::Taa => {
+                            ui.slider(
+                                "Current Frame Weight",
+                                0.01,
+                                1.0,
+                                &mut post_processing.taa_pipeline.push_constants.data_0.x,
+                            );
+                        }
+                        AntiAliasing::Fxaa => {
+                            let push_constants = &mut post_processing.fxaa_pipeline.push_constants;
+                            ui.slider("Edge Threshold", 0.063, 0.333, &mut push_constants.data_0.z);
+                            ui.slider(
+                                "Edge Threshold Min",
+                                0.0,
+                                0.0833,
+                                &mut push_constants.data_0.w,
+                            );
+                        }
+                        AntiAliasing::None => {}
+                    }
+                }
+            });
+
         self.platform.prepare_render(ui, window_handle);
     }
 
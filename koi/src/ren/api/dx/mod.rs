@@ -3,7 +3,7 @@ pub mod imgui;
 use crate::{
     app::info::Info,
     ren::{Renderer as RendererTrait, settings::Settings, window::Window},
-    scene::Scene,
+    scene::{Scene, environment::Environment},
 };
 pub struct Renderer {}
 
@@ -16,6 +16,14 @@ impl RendererTrait for Renderer {
         todo!()
     }
 
+    fn load_environment(&mut self, environment: &Environment) {
+        todo!()
+    }
+
+    fn update_scene(&mut self, scene: &Scene) {
+        todo!()
+    }
+
     fn draw(&mut self, imgui: &mut crate::imgui::ImGui) {
         todo!()
     }
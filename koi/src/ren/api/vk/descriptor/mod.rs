@@ -1,3 +1,5 @@
+use super::reflect::{self, EntryPoint};
+
 use ash::{Device as DeviceHandle, vk};
 
 pub struct DescriptorSetLayoutBuilder<'a> {
@@ -10,6 +12,13 @@ impl<'a> DescriptorSetLayoutBuilder<'a> {
         Self { bindings: vec![] }
     }
 
+    // The bindings the entry points declare in set, with the stages that use each.
+    pub fn from_entry_points(entry_points: &[&EntryPoint], set: u32) -> Self {
+        Self {
+            bindings: reflect::get_set_bindings(entry_points, set),
+        }
+    }
+
     pub fn add_binding(mut self, binding: u32, descriptor_type: vk::DescriptorType) -> Self {
         self.bindings.push(
             vk::DescriptorSetLayoutBinding::default()
@@ -20,6 +29,31 @@ impl<'a> DescriptorSetLayoutBuilder<'a> {
         self
     }
 
+    pub fn add_array_binding(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: u32,
+    ) -> Self {
+        self.bindings.push(
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_count(descriptor_count)
+                .descriptor_type(descriptor_type),
+        );
+        self
+    }
+
+    // Sizes a reflected binding, such as a runtime array, that the shader leaves unsized.
+    pub fn descriptor_count(mut self, binding: u32, descriptor_count: u32) -> Self {
+        self.bindings
+            .iter_mut()
+            .find(|reflected| reflected.binding == binding)
+            .expect("koi::ren::vk::descriptor - no such binding to size")
+            .descriptor_count = descriptor_count;
+        self
+    }
+
     pub fn clear(&mut self) {
         self.bindings.clear();
     }
@@ -31,6 +65,16 @@ impl<'a> DescriptorSetLayoutBuilder<'a> {
         flags: Option<vk::DescriptorSetLayoutCreateFlags>,
         next: Option<&'a mut T>,
     ) -> vk::DescriptorSetLayout {
+        if let Some(binding) = self
+            .bindings
+            .iter()
+            .find(|binding| binding.descriptor_count == 0)
+        {
+            panic!(
+                "koi::ren::vk::descriptor - binding {} is a runtime array; size it before building",
+                binding.binding
+            );
+        }
         self.bindings
             .iter_mut()
             .for_each(|binding| binding.stage_flags = binding.stage_flags | shader_stages);
@@ -37,8 +37,15 @@ pub struct MemoryType {
 
 #[derive(Clone, PartialEq, Eq, PartialOrd)]
 pub struct PhysicalDeviceProperties {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    // changes with the driver; pipeline cache data is only valid for a matching UUID
+    pub pipeline_cache_uuid: [u8; vk::UUID_SIZE],
     pub max_image_dimension_2d: u32,
     pub min_memory_map_alignment: usize,
+    pub min_uniform_buffer_offset_alignment: vk::DeviceSize,
+    pub min_storage_buffer_offset_alignment: vk::DeviceSize,
+    pub max_sampler_anisotropy: u32,
     memory_types: Vec<MemoryType>,
 }
 
@@ -64,8 +71,18 @@ impl PhysicalDeviceProperties {
             })
             .collect();
         Self {
+            vendor_id: properties.vendor_id,
+            device_id: properties.device_id,
+            pipeline_cache_uuid: properties.pipeline_cache_uuid,
             max_image_dimension_2d: properties.limits.max_image_dimension2_d,
             min_memory_map_alignment: properties.limits.min_memory_map_alignment,
+            min_uniform_buffer_offset_alignment: properties
+                .limits
+                .min_uniform_buffer_offset_alignment,
+            min_storage_buffer_offset_alignment: properties
+                .limits
+                .min_storage_buffer_offset_alignment,
+            max_sampler_anisotropy: properties.limits.max_sampler_anisotropy as u32,
             memory_types,
         }
     }
@@ -168,8 +185,20 @@ impl DeviceConfig<'_> {
 
         validate_extensions(instance, valid_physical_device.handle, &extensions)?;
 
+        // NB! optional; block-compressed textures are transcoded on the CPU when unsupported
+        let supported_features =
+            unsafe { instance.get_physical_device_features(valid_physical_device.handle) };
+
         let mut features: vk::PhysicalDeviceFeatures = Default::default();
-        features = features.geometry_shader(true);
+        features = features
+            .geometry_shader(true)
+            .multi_draw_indirect(true)
+            .draw_indirect_first_instance(true)
+            .sampler_anisotropy(true)
+            .shader_storage_image_extended_formats(true)
+            .texture_compression_bc(supported_features.texture_compression_bc == vk::TRUE)
+            // NB! optional; wireframe drawing is unavailable without it
+            .fill_mode_non_solid(supported_features.fill_mode_non_solid == vk::TRUE);
 
         let mut vk_13_features: vk::PhysicalDeviceVulkan13Features = Default::default();
         vk_13_features.dynamic_rendering = vk::TRUE;
@@ -178,6 +207,10 @@ impl DeviceConfig<'_> {
         let mut vk_12_features: vk::PhysicalDeviceVulkan12Features = Default::default();
         vk_12_features.buffer_device_address = vk::TRUE;
         vk_12_features.descriptor_indexing = vk::TRUE;
+        vk_12_features.runtime_descriptor_array = vk::TRUE;
+        vk_12_features.descriptor_binding_partially_bound = vk::TRUE;
+        vk_12_features.shader_sampled_image_array_non_uniform_indexing = vk::TRUE;
+        vk_12_features.draw_indirect_count = vk::TRUE;
 
         let queue_create_infos = valid_physical_device
             .queue_families
@@ -279,6 +312,26 @@ fn validate_physical_device_feature_requirements(
     if features_2.features.geometry_shader == vk::FALSE {
         return Err(DeviceConfigError::FeatureNotSupported(c"geometry_shader"));
     }
+    if features_2.features.multi_draw_indirect == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"multi_draw_indirect",
+        ));
+    }
+    if features_2.features.draw_indirect_first_instance == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"draw_indirect_first_instance",
+        ));
+    }
+    if features_2.features.sampler_anisotropy == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"sampler_anisotropy",
+        ));
+    }
+    if features_2.features.shader_storage_image_extended_formats == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"shader_storage_image_extended_formats",
+        ));
+    }
     if vk_13_features.dynamic_rendering == vk::FALSE {
         return Err(DeviceConfigError::FeatureNotSupported(
             c"vk_13_dynamic_rendering",
@@ -299,6 +352,26 @@ fn validate_physical_device_feature_requirements(
             c"vk_12_descriptor_indexing",
         ));
     }
+    if vk_12_features.runtime_descriptor_array == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"vk_12_runtime_descriptor_array",
+        ));
+    }
+    if vk_12_features.descriptor_binding_partially_bound == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"vk_12_descriptor_binding_partially_bound",
+        ));
+    }
+    if vk_12_features.shader_sampled_image_array_non_uniform_indexing == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"vk_12_shader_sampled_image_array_non_uniform_indexing",
+        ));
+    }
+    if vk_12_features.draw_indirect_count == vk::FALSE {
+        return Err(DeviceConfigError::FeatureNotSupported(
+            c"vk_12_draw_indirect_count",
+        ));
+    }
     Ok(())
 }
 
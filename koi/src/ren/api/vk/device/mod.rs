@@ -8,9 +8,12 @@ use ash::{Device as DeviceHandle, Instance, vk};
 
 #[allow(unused)]
 pub struct Device {
+    pub instance: Instance,
     pub physical_device: vk::PhysicalDevice,
     pub physical_device_properties: PhysicalDeviceProperties,
     pub queue_families: PhysicalDeviceQueueFamilies,
+    pub texture_compression_bc: bool,
+    pub fill_mode_non_solid: bool,
     pub handle: DeviceHandle,
 }
 
@@ -56,9 +59,12 @@ impl Device {
         };
 
         Self {
+            instance: instance.clone(),
             physical_device: selected_physical_device.handle,
             physical_device_properties: selected_physical_device.properties.clone(),
             queue_families: selected_physical_device.queue_families.clone(),
+            texture_compression_bc: device_config.features.texture_compression_bc == vk::TRUE,
+            fill_mode_non_solid: device_config.features.fill_mode_non_solid == vk::TRUE,
             handle: device,
         }
     }
@@ -74,6 +80,32 @@ impl Device {
     pub fn get_min_memory_map_alignment(&self) -> usize {
         self.physical_device_properties.min_memory_map_alignment
     }
+
+    // satisfies both uniform and storage buffer descriptor offsets
+    pub fn get_min_buffer_offset_alignment(&self) -> vk::DeviceSize {
+        self.physical_device_properties
+            .min_uniform_buffer_offset_alignment
+            .max(
+                self.physical_device_properties
+                    .min_storage_buffer_offset_alignment,
+            )
+    }
+
+    pub fn get_max_sampler_anisotropy(&self) -> u32 {
+        self.physical_device_properties.max_sampler_anisotropy
+    }
+
+    pub fn supports_format_features(
+        &self,
+        format: vk::Format,
+        features: vk::FormatFeatureFlags,
+    ) -> bool {
+        let properties = unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+        };
+        properties.optimal_tiling_features.contains(features)
+    }
 }
 
 impl traits::Drop for Device {
@@ -0,0 +1,248 @@
+use super::{buffer::Buffer, device::Device, resource_allocator::ResourceAllocator};
+
+use ash::{Device as DeviceHandle, vk};
+use bytemuck::{Pod, cast_slice};
+use gpu_allocator::{MemoryLocation, vulkan as vka};
+
+pub const FRAME_CHUNK_SIZE: vk::DeviceSize = 1 << 20;
+// NB! keeps vec4 and mat4 loads through device addresses aligned
+const MIN_FRAME_ALLOCATION_ALIGNMENT: vk::DeviceSize = 16;
+
+// Data written by the host for one frame only: camera data, light lists, per-draw data.
+#[derive(Clone, Copy)]
+pub struct FrameAllocation {
+    pub buffer: vk::Buffer,
+    // from the start of buffer; also the dynamic offset when bound through a descriptor
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub device_address: vk::DeviceAddress,
+}
+
+impl FrameAllocation {
+    pub fn get_buffer_info(&self) -> vk::DescriptorBufferInfo {
+        vk::DescriptorBufferInfo::default()
+            .buffer(self.buffer)
+            .offset(self.offset)
+            .range(self.size)
+    }
+
+    pub fn get_dynamic_offset(&self) -> u32 {
+        u32::try_from(self.offset)
+            .expect("koi::ren::vk::frame_allocator - offset too large for a dynamic offset")
+    }
+}
+
+// Bookkeeping of one chunk, apart from its memory.
+struct LinearRange {
+    capacity: vk::DeviceSize,
+    // next free byte
+    head: vk::DeviceSize,
+}
+
+impl LinearRange {
+    fn new(capacity: vk::DeviceSize) -> Self {
+        Self { capacity, head: 0 }
+    }
+
+    // Offset of size bytes at the next multiple of alignment; None when they don't fit.
+    fn allocate(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<vk::DeviceSize> {
+        let offset = self.head.next_multiple_of(alignment);
+        if offset + size > self.capacity {
+            return None;
+        }
+        self.head = offset + size;
+        Some(offset)
+    }
+
+    fn reset(&mut self) {
+        self.head = 0;
+    }
+}
+
+// Capacity of the chunk replacing a full one; at least double, and enough for size bytes.
+fn get_grown_capacity(capacity: vk::DeviceSize, size: vk::DeviceSize) -> vk::DeviceSize {
+    (capacity * 2).max(size.next_power_of_two())
+}
+
+struct FrameChunk {
+    buffer: Buffer,
+    allocation: vka::Allocation,
+    device_address: vk::DeviceAddress,
+    range: LinearRange,
+}
+
+impl FrameChunk {
+    fn new(
+        device_handle: &DeviceHandle,
+        allocator: &mut vka::Allocator,
+        size: vk::DeviceSize,
+    ) -> Self {
+        let (buffer, allocation) = Buffer::create(
+            device_handle,
+            allocator,
+            size,
+            vk::BufferUsageFlags::UNIFORM_BUFFER
+                | vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            "frame_allocator_chunk",
+            MemoryLocation::CpuToGpu,
+        );
+        let device_address = unsafe {
+            device_handle.get_buffer_device_address(
+                &vk::BufferDeviceAddressInfo::default().buffer(buffer.handle),
+            )
+        };
+        Self {
+            buffer,
+            allocation,
+            device_address,
+            range: LinearRange::new(size),
+        }
+    }
+}
+
+// Linear allocator over one host-visible chunk per frame in flight. Allocations are valid until the
+// frame's fence next signals, when reset rewinds the chunk. A chunk that runs out is handed to the
+// frame's resources, so it is freed on that same signal, and replaced with one twice as large.
+pub struct FrameAllocator {
+    chunks: Vec<FrameChunk>,
+    alignment: vk::DeviceSize,
+}
+
+impl FrameAllocator {
+    pub fn new(device: &Device, resource_allocator: &mut ResourceAllocator) -> Self {
+        let chunks = (0..resource_allocator.frame_resources.len())
+            .map(|_| {
+                FrameChunk::new(
+                    &device.handle,
+                    &mut resource_allocator.handle,
+                    FRAME_CHUNK_SIZE,
+                )
+            })
+            .collect();
+        Self {
+            chunks,
+            alignment: device
+                .get_min_buffer_offset_alignment()
+                .max(MIN_FRAME_ALLOCATION_ALIGNMENT),
+        }
+    }
+
+    // Only once the frame's render fence has signalled.
+    pub fn reset(&mut self, frame: usize) {
+        self.chunks[frame].range.reset();
+    }
+
+    pub fn allocate<T: Pod>(
+        &mut self,
+        device_handle: &DeviceHandle,
+        resource_allocator: &mut ResourceAllocator,
+        frame: usize,
+        data: &[T],
+    ) -> FrameAllocation {
+        let bytes: &[u8] = cast_slice(data);
+        let size = bytes.len() as vk::DeviceSize;
+        let offset = match self.chunks[frame].range.allocate(size, self.alignment) {
+            Some(offset) => offset,
+            None => {
+                self.grow(device_handle, resource_allocator, frame, size);
+                self.chunks[frame]
+                    .range
+                    .allocate(size, self.alignment)
+                    .expect("koi::ren::vk::frame_allocator - grown Frame Chunk too small")
+            }
+        };
+
+        let chunk = &mut self.chunks[frame];
+        chunk
+            .allocation
+            .mapped_slice_mut()
+            .expect("koi::ren::vk::frame_allocator - failed to map Frame Chunk")
+            [offset as usize..(offset + size) as usize]
+            .copy_from_slice(bytes);
+
+        FrameAllocation {
+            buffer: chunk.buffer.handle,
+            offset,
+            size,
+            device_address: chunk.device_address + offset,
+        }
+    }
+
+    fn grow(
+        &mut self,
+        device_handle: &DeviceHandle,
+        resource_allocator: &mut ResourceAllocator,
+        frame: usize,
+        size: vk::DeviceSize,
+    ) {
+        let chunk_size = get_grown_capacity(self.chunks[frame].range.capacity, size);
+        log::warn!(
+            "koi::ren::vk::frame_allocator - frame {frame} chunk full; growing to {chunk_size} bytes"
+        );
+        let chunk = FrameChunk::new(device_handle, &mut resource_allocator.handle, chunk_size);
+        let FrameChunk {
+            buffer, allocation, ..
+        } = std::mem::replace(&mut self.chunks[frame], chunk);
+        // NB! earlier allocations of this frame may still be in flight
+        resource_allocator.frame_resources[frame].add_buffer(buffer.handle, allocation);
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle, allocator: &mut vka::Allocator) {
+        self.chunks.drain(..).for_each(|chunk| {
+            unsafe { device_handle.destroy_buffer(chunk.buffer.handle, None) };
+            allocator
+                .free(chunk.allocation)
+                .expect("koi::ren::vk::frame_allocator - failed to Free Frame Chunk allocation");
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligns_allocations() {
+        let mut range = LinearRange::new(256);
+        assert_eq!(range.allocate(4, 64), Some(0));
+        assert_eq!(range.allocate(100, 64), Some(64));
+        // 164 rounds up to the next multiple of 64
+        assert_eq!(range.allocate(1, 64), Some(192));
+        assert_eq!(range.allocate(0, 16), Some(208));
+    }
+
+    #[test]
+    fn rejects_allocations_past_capacity() {
+        let mut range = LinearRange::new(128);
+        assert_eq!(range.allocate(100, 16), Some(0));
+        // 112 + 32 doesn't fit, though 100 + 28 would without alignment
+        assert_eq!(range.allocate(28, 16), None);
+        assert_eq!(range.allocate(16, 16), Some(112));
+        assert_eq!(range.allocate(1, 16), None);
+    }
+
+    #[test]
+    fn grows_to_fit() {
+        assert_eq!(
+            get_grown_capacity(FRAME_CHUNK_SIZE, 64),
+            2 * FRAME_CHUNK_SIZE
+        );
+        assert_eq!(get_grown_capacity(1024, 3000), 4096);
+        let mut range = LinearRange::new(get_grown_capacity(1024, 3000));
+        assert_eq!(range.allocate(3000, 256), Some(0));
+    }
+
+    #[test]
+    fn reset_rewinds() {
+        let mut range = LinearRange::new(64);
+        assert_eq!(range.allocate(64, 16), Some(0));
+        assert_eq!(range.allocate(16, 16), None);
+        range.reset();
+        assert_eq!(range.allocate(16, 16), Some(0));
+    }
+}
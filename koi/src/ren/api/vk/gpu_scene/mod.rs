@@ -0,0 +1,1066 @@
+use crate::scene::{
+    AlphaMode, Scene, Surface,
+    bounds::{Bounds, Frustum},
+};
+
+use super::{
+    ImmediateManager,
+    buffer::Buffer,
+    descriptor::{DescriptorSetAllocator, DescriptorSetLayoutBuilder, DescriptorSetPoolSizeRatio},
+    hiz::HiZ,
+    mesh::Mesh,
+    pipeline,
+    reflect::{EntryPoint, ShaderReflection},
+    resource_allocator::ResourceAllocator,
+};
+
+use ash::{Device as DeviceHandle, vk};
+use bytemuck::{bytes_of, pod_read_unaligned};
+use gpu_allocator::{MemoryLocation, vulkan as vka};
+use koi_gpu::{
+    CullPushConstants, DRAW_INDEXED_INDIRECT_COMMAND_SIZE, GpuLayout, INSTANCE_SIZE, Instance,
+    InstanceLods, NO_TEXTURE, OCCLUSION_DATA_SIZE, OcclusionData,
+};
+use spirv_std::glam::{Mat4, UVec4, Vec3, Vec4, Vec4Swizzles};
+
+// NB! checked against the threads() declared by cull.spv
+const CULL_GROUP_SIZE: u32 = 64;
+// visible, frustum culled, occlusion culled, padding
+const COUNTERS_SIZE: u64 = 4 * size_of::<u32>() as u64;
+// the draw groups' draw counts follow the statistics counters
+const FIRST_DRAW_COUNT: u32 = 4;
+
+pub struct SceneBuffers {
+    pub instances: Buffer,
+    pub indices: Buffer,
+    pub draw_commands: Buffer,
+    // the culling counters followed by each draw group's draw count
+    pub draw_count: Buffer,
+    pub lods: Buffer,
+    // level each instance was last drawn at; read back by the cull pass for hysteresis
+    pub lod_levels: Buffer,
+}
+
+// Pipeline variant a surface's material asks for; variants sort, and so draw, opaque first.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub struct MaterialVariant {
+    pub alpha_mode: AlphaMode,
+    pub double_sided: bool,
+}
+
+// Every instance of one mesh surface; batch instances are contiguous, so a run of them is drawn
+// with a single instanced call.
+#[derive(Default, Clone, Copy)]
+pub struct DrawBatch {
+    pub first_instance: u32,
+    pub instance_count: u32,
+    pub variant: MaterialVariant,
+    // index into GpuScene::draw_groups
+    pub draw_group: usize,
+}
+
+// Every instance drawn with one material variant's pipeline; the group's draw commands are
+// contiguous, so the GPU-driven path draws it with one indirect count draw.
+pub struct DrawGroup {
+    pub variant: MaterialVariant,
+    pub first_draw_command: u32,
+    pub instance_count: u32,
+    // CPU path only; rebuilt by cull_cpu every frame
+    pub visible_runs: Vec<DrawRun>,
+}
+
+// Consecutive visible instances of a batch sharing a level of detail; drawn with one call.
+#[derive(Default, Clone, Copy)]
+pub struct DrawRun {
+    pub first_instance: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+// Where one of the last loaded scene's instances comes from.
+#[derive(Clone, Copy)]
+pub struct NodeInstance {
+    pub node: usize,
+    // relative to the node; EXT_mesh_gpu_instancing, identity when the mesh is drawn once
+    pub transform: Mat4,
+    // object space
+    pub bounds: Bounds,
+}
+
+// Per-frame camera inputs to culling and LOD selection.
+pub struct CullView {
+    pub view_projection: Mat4,
+    pub camera_position: Vec3,
+    // pixels per world unit at distance 1, over the LOD pixel threshold; 0 disables LOD selection
+    pub lod_scale: f32,
+}
+
+// GPU-driven: culling results of the frame last submitted with the same frame index.
+// CPU path: results of the current frame.
+#[derive(Default, Clone, Copy)]
+pub struct CullStatistics {
+    pub visible: u32,
+    pub frustum_culled: u32,
+    pub occlusion_culled: u32,
+}
+
+// Flattened, GPU-resident view of every loaded scene: one Instance per node surface (and per
+// EXT_mesh_gpu_instancing instance), drawing out of a single shared index buffer. Draws are either
+// recorded per visible run of a batch on the CPU, or culled and compacted on the GPU and submitted
+// with one indirect count draw per draw group.
+pub struct GpuScene {
+    pub instances: Vec<Instance>,
+    // world space, parallel to instances
+    pub instance_bounds: Vec<Bounds>,
+    pub batches: Vec<DrawBatch>,
+    pub draw_groups: Vec<DrawGroup>,
+    // parallel to instances
+    pub instance_lods: Vec<InstanceLods>,
+    // CPU path only; GPU-driven levels live in SceneBuffers::lod_levels
+    pub lod_levels: Vec<u32>,
+    pub lod_enabled: bool,
+    // largest tolerated projected error, in pixels
+    pub lod_threshold: f32,
+    pub indices: Vec<u32>,
+    // the last loaded scene's instances, from first_node_instance on; moved by update_transforms
+    pub node_instances: Vec<NodeInstance>,
+    pub first_node_instance: usize,
+    // set by update_transforms, cleared once the instances buffer has been patched
+    pub transforms_changed: bool,
+    // one host-visible copy of the last loaded scene's instances per frame in flight
+    pub instance_staging: Vec<(Buffer, vka::Allocation)>,
+    pub buffering: u32,
+    pub buffers: Option<SceneBuffers>,
+    pub gpu_driven: bool,
+    pub occlusion_culling: bool,
+    pub occlusion_data: Buffer,
+    // one host-visible copy of the counters per frame in flight
+    pub statistics_readback: Vec<(Buffer, vka::Allocation)>,
+    pub statistics: CullStatistics,
+
+    // consumed by the mesh pass: 0 instances
+    pub instance_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub instance_descriptor_set: vk::DescriptorSet,
+    // consumed by the cull pass: 0 instances, 1 draw commands, 2 draw count, 3 Hi-Z pyramid,
+    // 4 occlusion data, 5 lods, 6 lod levels
+    pub cull_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub cull_descriptor_set: vk::DescriptorSet,
+    pub descriptor_set_allocator: DescriptorSetAllocator,
+
+    pub cull_pipeline_layout: vk::PipelineLayout,
+    pub cull_shader: vk::ShaderModule,
+    pub cull_pipeline: vk::Pipeline,
+}
+
+impl GpuScene {
+    pub fn new(
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+        resource_allocator: &mut ResourceAllocator,
+        hiz: &HiZ,
+        buffering: u32,
+        mesh_entry_points: &[&EntryPoint],
+    ) -> Self {
+        let instance_descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(mesh_entry_points, 1)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+        let cull_shader_code = include_bytes!(env!("cull.spv"));
+        let cull_reflection = ShaderReflection::new(cull_shader_code);
+        let cull_entry_point = cull_reflection.get_entry_point(c"main_cs");
+        cull_entry_point.check_workgroup_size([CULL_GROUP_SIZE, 1, 1]);
+        let cull_descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(&[cull_entry_point], 0)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+
+        let mut descriptor_set_allocator = DescriptorSetAllocator::new(device_handle, 2, &[
+            DescriptorSetPoolSizeRatio::new(vk::DescriptorType::STORAGE_BUFFER, 4.0),
+            DescriptorSetPoolSizeRatio::new(vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+        ]);
+        let instance_descriptor_set =
+            descriptor_set_allocator.allocate(device_handle, &[instance_descriptor_set_layout]);
+        let cull_descriptor_set =
+            descriptor_set_allocator.allocate(device_handle, &[cull_descriptor_set_layout]);
+
+        let occlusion_data = Buffer::new(
+            device_handle,
+            &mut resource_allocator.handle,
+            &mut resource_allocator.global_resources,
+            OCCLUSION_DATA_SIZE,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+            "scene_occlusion_data",
+            MemoryLocation::GpuOnly,
+        );
+        let pyramid_info = [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::GENERAL)
+            .image_view(hiz.pyramid.view)];
+        let occlusion_data_info = [vk::DescriptorBufferInfo::default()
+            .buffer(occlusion_data.handle)
+            .offset(0)
+            .range(vk::WHOLE_SIZE)];
+        let descriptor_writes = [
+            vk::WriteDescriptorSet::default()
+                .dst_binding(3)
+                .dst_set(cull_descriptor_set)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&pyramid_info),
+            vk::WriteDescriptorSet::default()
+                .dst_binding(4)
+                .dst_set(cull_descriptor_set)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(&occlusion_data_info),
+        ];
+        unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        let statistics_readback = (0..buffering)
+            .map(|_| {
+                let (mut buffer, mut allocation) = Buffer::create(
+                    device_handle,
+                    &mut resource_allocator.handle,
+                    COUNTERS_SIZE,
+                    vk::BufferUsageFlags::TRANSFER_DST,
+                    "scene_statistics_readback",
+                    MemoryLocation::GpuToCpu,
+                );
+                buffer.upload(&[0u32; 4], &mut allocation, 0);
+                (buffer, allocation)
+            })
+            .collect();
+
+        let cull_pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            device_handle,
+            &[cull_entry_point],
+            &[cull_descriptor_set_layout],
+            CullPushConstants::MEMBERS,
+        );
+        let cull_shader = pipeline::load_shader_module(device_handle, cull_shader_code, None);
+        let cull_pipeline = pipeline::create_compute_pipeline(
+            device_handle,
+            pipeline_cache,
+            cull_shader,
+            cull_pipeline_layout,
+        );
+
+        Self {
+            instances: vec![],
+            instance_bounds: vec![],
+            batches: vec![],
+            draw_groups: vec![],
+            instance_lods: vec![],
+            lod_levels: vec![],
+            lod_enabled: true,
+            lod_threshold: 1.0,
+            indices: vec![],
+            node_instances: vec![],
+            first_node_instance: 0,
+            transforms_changed: false,
+            instance_staging: vec![],
+            buffering,
+            buffers: None,
+            gpu_driven: true,
+            occlusion_culling: true,
+            occlusion_data,
+            statistics_readback,
+            statistics: CullStatistics::default(),
+
+            instance_descriptor_set_layout,
+            instance_descriptor_set,
+            cull_descriptor_set_layout,
+            cull_descriptor_set,
+            descriptor_set_allocator,
+
+            cull_pipeline_layout,
+            cull_shader,
+            cull_pipeline,
+        }
+    }
+
+    // Appends the scene's node instances; meshes are the scene's uploaded meshes, in scene order.
+    // NB! buffers are rebuilt from scratch; previous ones are released with the global resources
+    pub fn load(
+        &mut self,
+        device_handle: &DeviceHandle,
+        resource_allocator: &mut ResourceAllocator,
+        immediate_manager: &mut ImmediateManager,
+        scene: &Scene,
+        meshes: &[Mesh],
+        // offset of the scene's textures in the texture table
+        first_texture: u32,
+        // per scene node; deformed nodes pull their vertices from here instead of their mesh's
+        skinned_addresses: &[Option<vk::DeviceAddress>],
+    ) {
+        self.drop_instance_staging(device_handle, &mut resource_allocator.handle);
+
+        // scene texture indices are offset into the bindless table
+        let get_texture_index =
+            |texture: Option<usize>| texture.map_or(NO_TEXTURE, |t| first_texture + t as u32);
+
+        let mesh_instances: Vec<Vec<(Instance, MaterialVariant, Bounds, InstanceLods)>> = scene
+            .meshes
+            .iter()
+            .zip(meshes)
+            .map(|(scene_mesh, mesh)| {
+                let base_index = self.indices.len() as u32;
+                self.indices.extend_from_slice(&scene_mesh.indices);
+                scene_mesh
+                    .surfaces
+                    .iter()
+                    .map(|surface| {
+                        let instance = Instance {
+                            bounding_sphere: surface.bounds.sphere,
+                            vertex_buffer_address: mesh.vertex_buffer_address,
+                            first_index: base_index + surface.start_index,
+                            index_count: surface.count,
+                            quantization_origin: Vec4::from((mesh.quantization_origin, 0.0)),
+                            quantization_extent: Vec4::from((mesh.quantization_extent, 0.0)),
+                            vertex_format: UVec4::new(mesh.vertex_format as u32, 0, 0, 0),
+                            textures: UVec4::new(
+                                get_texture_index(surface.normal_texture),
+                                get_texture_index(surface.base_color_texture),
+                                NO_TEXTURE,
+                                NO_TEXTURE,
+                            ),
+                            base_color: surface.material.base_color,
+                            factors: Vec4::new(
+                                surface.normal_scale,
+                                surface.material.metallic,
+                                surface.material.roughness,
+                                match surface.material.alpha_mode {
+                                    AlphaMode::Mask => surface.material.alpha_cutoff,
+                                    _ => 0.0,
+                                },
+                            ),
+                            ..Default::default()
+                        };
+                        let variant = MaterialVariant {
+                            alpha_mode: surface.material.alpha_mode,
+                            double_sided: surface.material.double_sided,
+                        };
+                        (
+                            instance,
+                            variant,
+                            surface.bounds,
+                            get_instance_lods(surface, base_index),
+                        )
+                    })
+                    .collect()
+            })
+            .collect();
+
+        self.first_node_instance = self.instances.len();
+        self.node_instances.clear();
+        for (node_index, node) in scene.nodes.iter().enumerate() {
+            let Some(mesh_index) = node.mesh else {
+                continue;
+            };
+            let local_transforms = match node.instances.is_empty() {
+                true => &[Mat4::IDENTITY][..],
+                false => node.instances.as_slice(),
+            };
+
+            for (instance, variant, bounds, lods) in &mesh_instances[mesh_index] {
+                self.batches.push(DrawBatch {
+                    first_instance: self.instances.len() as u32,
+                    instance_count: local_transforms.len() as u32,
+                    variant: *variant,
+                    draw_group: 0,
+                });
+                let instance = match skinned_addresses[node_index] {
+                    Some(address) => Instance {
+                        vertex_buffer_address: address,
+                        vertex_format: instance.vertex_format.with_y(1),
+                        ..*instance
+                    },
+                    None => *instance,
+                };
+                for &local_transform in local_transforms {
+                    let transform = node.world_transform * local_transform;
+                    self.instances.push(Instance {
+                        transform,
+                        previous_transform: transform,
+                        ..instance
+                    });
+                    self.node_instances.push(NodeInstance {
+                        node: node_index,
+                        transform: local_transform,
+                        bounds: *bounds,
+                    });
+                    self.instance_bounds.push(bounds.transform(&transform));
+                    self.instance_lods.push(*lods);
+                    self.lod_levels.push(0);
+                }
+            }
+        }
+
+        if self.instances.is_empty() {
+            return;
+        }
+        self.update_draw_groups();
+
+        let instances = upload(
+            device_handle,
+            resource_allocator,
+            immediate_manager,
+            &self.instances,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            "scene_instances",
+        );
+        let indices = upload(
+            device_handle,
+            resource_allocator,
+            immediate_manager,
+            &self.indices,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            "scene_indices",
+        );
+        let lods = upload(
+            device_handle,
+            resource_allocator,
+            immediate_manager,
+            &self.instance_lods,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            "scene_lods",
+        );
+        let lod_levels = upload(
+            device_handle,
+            resource_allocator,
+            immediate_manager,
+            &self.lod_levels,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            "scene_lod_levels",
+        );
+        let draw_commands = Buffer::new(
+            device_handle,
+            &mut resource_allocator.handle,
+            &mut resource_allocator.global_resources,
+            self.instances.len() as u64 * DRAW_INDEXED_INDIRECT_COMMAND_SIZE,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::INDIRECT_BUFFER,
+            "scene_draw_commands",
+            MemoryLocation::GpuOnly,
+        );
+        let draw_count = Buffer::new(
+            device_handle,
+            &mut resource_allocator.handle,
+            &mut resource_allocator.global_resources,
+            COUNTERS_SIZE + self.draw_groups.len() as u64 * size_of::<u32>() as u64,
+            vk::BufferUsageFlags::STORAGE_BUFFER
+                | vk::BufferUsageFlags::INDIRECT_BUFFER
+                | vk::BufferUsageFlags::TRANSFER_SRC
+                | vk::BufferUsageFlags::TRANSFER_DST,
+            "scene_draw_count",
+            MemoryLocation::GpuOnly,
+        );
+
+        let buffer_info = |buffer: &Buffer| {
+            [vk::DescriptorBufferInfo::default()
+                .buffer(buffer.handle)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)]
+        };
+        let instances_info = buffer_info(&instances);
+        let draw_commands_info = buffer_info(&draw_commands);
+        let draw_count_info = buffer_info(&draw_count);
+        let lods_info = buffer_info(&lods);
+        let lod_levels_info = buffer_info(&lod_levels);
+        let descriptor_writes = [
+            (self.instance_descriptor_set, 0, &instances_info),
+            (self.cull_descriptor_set, 0, &instances_info),
+            (self.cull_descriptor_set, 1, &draw_commands_info),
+            (self.cull_descriptor_set, 2, &draw_count_info),
+            (self.cull_descriptor_set, 5, &lods_info),
+            (self.cull_descriptor_set, 6, &lod_levels_info),
+        ]
+        .map(|(dst_set, binding, buffer_info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_binding(binding)
+                .dst_set(dst_set)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(buffer_info)
+        });
+        unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+
+        self.buffers = Some(SceneBuffers {
+            instances,
+            indices,
+            draw_commands,
+            draw_count,
+            lods,
+            lod_levels,
+        });
+
+        if self.node_instances.is_empty() {
+            return;
+        }
+        let staging_size = self.node_instances.len() as u64 * INSTANCE_SIZE;
+        self.instance_staging = (0..self.buffering)
+            .map(|_| {
+                Buffer::create(
+                    device_handle,
+                    &mut resource_allocator.handle,
+                    staging_size,
+                    vk::BufferUsageFlags::TRANSFER_SRC,
+                    "scene_instance_staging",
+                    MemoryLocation::CpuToGpu,
+                )
+            })
+            .collect();
+    }
+
+    // Groups every batch by material variant and points each instance at its group's range of draw
+    // commands and draw count.
+    fn update_draw_groups(&mut self) {
+        let mut variants: Vec<MaterialVariant> =
+            self.batches.iter().map(|batch| batch.variant).collect();
+        variants.sort();
+        variants.dedup();
+        self.draw_groups = variants
+            .iter()
+            .map(|&variant| DrawGroup {
+                variant,
+                first_draw_command: 0,
+                instance_count: 0,
+                visible_runs: vec![],
+            })
+            .collect();
+
+        for batch in &mut self.batches {
+            batch.draw_group = variants.binary_search(&batch.variant).unwrap();
+            self.draw_groups[batch.draw_group].instance_count += batch.instance_count;
+        }
+        let mut first_draw_command = 0;
+        for draw_group in &mut self.draw_groups {
+            draw_group.first_draw_command = first_draw_command;
+            first_draw_command += draw_group.instance_count;
+        }
+
+        // NB! blended groups aren't compacted, so they draw in a stable order rather than in
+        // whichever order the cull pass finishes; culled instances leave an empty command
+        let mut group_offsets = vec![0; self.draw_groups.len()];
+        for batch in &self.batches {
+            let draw_group = &self.draw_groups[batch.draw_group];
+            let is_ordered = draw_group.variant.alpha_mode == AlphaMode::Blend;
+            let first_instance = batch.first_instance as usize;
+            for instance in
+                &mut self.instances[first_instance..first_instance + batch.instance_count as usize]
+            {
+                let group_offset = &mut group_offsets[batch.draw_group];
+                instance.draw_group = UVec4::new(
+                    draw_group.first_draw_command + if is_ordered { *group_offset } else { 0 },
+                    FIRST_DRAW_COUNT + batch.draw_group as u32,
+                    is_ordered as u32,
+                    0,
+                );
+                *group_offset += 1;
+            }
+        }
+    }
+
+    // Moves the last loaded scene's instances along with their nodes, keeping last frame's
+    // transforms for motion vectors; call once per frame, with the scene's world transforms up to
+    // date. NB! instances of earlier scenes keep their load-time transforms
+    pub fn update_transforms(&mut self, scene: &Scene) {
+        for (index, node_instance) in self.node_instances.iter().enumerate() {
+            let transform =
+                scene.nodes[node_instance.node].world_transform * node_instance.transform;
+            let instance = &mut self.instances[self.first_node_instance + index];
+            // an instance that just stopped still needs its previous transform caught up
+            if instance.transform == transform && instance.previous_transform == transform {
+                continue;
+            }
+            instance.previous_transform = instance.transform;
+            instance.transform = transform;
+            self.instance_bounds[self.first_node_instance + index] =
+                node_instance.bounds.transform(&transform);
+            self.transforms_changed = true;
+        }
+    }
+
+    // Patches moved instances into the instances buffer; record before anything reads it this frame.
+    pub fn upload_transforms(
+        &mut self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+    ) {
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+        if !self.transforms_changed || self.instance_staging.is_empty() {
+            return;
+        }
+
+        let (staging_buffer, staging_allocation) = &mut self.instance_staging[frame_index];
+        staging_buffer.upload(
+            &self.instances[self.first_node_instance..],
+            staging_allocation,
+            0,
+        );
+
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_READ,
+            ),
+            (
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        );
+        unsafe {
+            device_handle.cmd_copy_buffer(
+                command_buffer,
+                staging_buffer.handle,
+                buffers.instances.handle,
+                &[vk::BufferCopy::default()
+                    .dst_offset(self.first_node_instance as u64 * INSTANCE_SIZE)
+                    .size(self.node_instances.len() as u64 * INSTANCE_SIZE)],
+            )
+        };
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::VERTEX_SHADER | vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_READ,
+            ),
+        );
+
+        self.transforms_changed = false;
+    }
+
+    // Frustum- and occlusion-culls every instance into the compacted draw command buffer, testing
+    // against the pyramid built last frame; record outside rendering.
+    pub fn cull(
+        &self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+        cull_view: &CullView,
+        hiz: &HiZ,
+        frame_index: usize,
+    ) {
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+        let instance_count = self.instances.len() as u32;
+        let occlusion_data = OcclusionData::default()
+            .view_projection(hiz.view_projection)
+            .pyramid(
+                hiz.pyramid.extent_2d.width,
+                hiz.pyramid.extent_2d.height,
+                hiz.pyramid.mip_levels,
+                self.occlusion_culling && hiz.valid,
+            );
+
+        // previous frame's indirect and copy reads must finish before the counters are reset
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::DRAW_INDIRECT
+                    | vk::PipelineStageFlags2::COMPUTE_SHADER
+                    | vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::INDIRECT_COMMAND_READ
+                    | vk::AccessFlags2::SHADER_STORAGE_READ
+                    | vk::AccessFlags2::TRANSFER_READ,
+            ),
+            (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+        );
+        unsafe {
+            device_handle.cmd_fill_buffer(
+                command_buffer,
+                buffers.draw_count.handle,
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            device_handle.cmd_update_buffer(
+                command_buffer,
+                self.occlusion_data.handle,
+                0,
+                bytes_of(&occlusion_data),
+            );
+        };
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::ALL_TRANSFER,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+        );
+
+        let push_constants = CullPushConstants::default()
+            .frustum(cull_view.view_projection)
+            .instance_count(instance_count)
+            .lod(cull_view.camera_position, cull_view.lod_scale);
+        unsafe {
+            device_handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.cull_pipeline,
+            );
+            device_handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.cull_pipeline_layout,
+                0,
+                &[self.cull_descriptor_set],
+                &[],
+            );
+            pipeline::push_constants(
+                device_handle,
+                command_buffer,
+                self.cull_pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                &push_constants,
+            );
+            device_handle.cmd_dispatch(
+                command_buffer,
+                instance_count.div_ceil(CULL_GROUP_SIZE),
+                1,
+                1,
+            );
+        }
+
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::DRAW_INDIRECT | vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::INDIRECT_COMMAND_READ | vk::AccessFlags2::TRANSFER_READ,
+            ),
+        );
+
+        let (readback, _) = &self.statistics_readback[frame_index];
+        unsafe {
+            device_handle.cmd_copy_buffer(
+                command_buffer,
+                buffers.draw_count.handle,
+                readback.handle,
+                &[vk::BufferCopy::default().size(COUNTERS_SIZE)],
+            )
+        };
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            ),
+            (vk::PipelineStageFlags2::HOST, vk::AccessFlags2::HOST_READ),
+        );
+    }
+
+    // Expects the frame's render fence to have been waited on.
+    pub fn read_statistics(&mut self, frame_index: usize) {
+        let (_, allocation) = &self.statistics_readback[frame_index];
+        let counters = allocation
+            .mapped_slice()
+            .expect("koi::ren::vk::gpu_scene - failed to map Statistics Readback Buffer");
+        let [visible, frustum_culled, occlusion_culled, _] =
+            pod_read_unaligned::<[u32; 4]>(&counters[..COUNTERS_SIZE as usize]);
+        self.statistics = CullStatistics {
+            visible,
+            frustum_culled,
+            occlusion_culled,
+        };
+    }
+
+    pub fn get_cull_view(&self, view: Mat4, projection: Mat4, viewport_height: f32) -> CullView {
+        CullView {
+            view_projection: projection * view,
+            camera_position: view.inverse().col(3).xyz(),
+            // NB! projection.y_axis.y is 1 / tan(fov / 2)
+            lod_scale: match self.lod_enabled && self.lod_threshold > 0.0 {
+                true => projection.y_axis.y * viewport_height * 0.5 / self.lod_threshold,
+                false => 0.0,
+            },
+        }
+    }
+
+    // CPU path: frustum-culls every instance's world bounds and selects its LOD; visible instances
+    // of a batch sharing a level are merged into runs, each drawn with one instanced call.
+    pub fn cull_cpu(&mut self, cull_view: &CullView) {
+        let frustum = Frustum::from_view_projection(cull_view.view_projection);
+        self.draw_groups
+            .iter_mut()
+            .for_each(|draw_group| draw_group.visible_runs.clear());
+
+        let mut visible = 0;
+        for batch in &self.batches {
+            let visible_runs = &mut self.draw_groups[batch.draw_group].visible_runs;
+            let mut run: Option<DrawRun> = None;
+            let end = batch.first_instance + batch.instance_count;
+            for index in batch.first_instance..end {
+                let bounds = &self.instance_bounds[index as usize];
+                let is_deformed = self.instances[index as usize].vertex_format.y != 0;
+                if !is_deformed && !frustum.intersects(bounds) {
+                    visible_runs.extend(run.take());
+                    continue;
+                }
+                visible += 1;
+
+                let index = index as usize;
+                let lods = &self.instance_lods[index];
+                let level = koi_gpu::select_lod(
+                    lods,
+                    get_error_scale(&self.instances[index], bounds, cull_view),
+                    self.lod_levels[index],
+                );
+                self.lod_levels[index] = level;
+                let (first_index, index_count) = (
+                    lods.first_indices[level as usize],
+                    lods.index_counts[level as usize],
+                );
+
+                run = match run {
+                    Some(run) if run.first_index == first_index => Some(DrawRun {
+                        instance_count: run.instance_count + 1,
+                        ..run
+                    }),
+                    _ => {
+                        visible_runs.extend(run);
+                        Some(DrawRun {
+                            first_instance: index as u32,
+                            instance_count: 1,
+                            first_index,
+                            index_count,
+                        })
+                    }
+                };
+            }
+            visible_runs.extend(run);
+        }
+
+        self.statistics = CullStatistics {
+            visible,
+            frustum_culled: self.instances.len() as u32 - visible,
+            occlusion_culled: 0,
+        };
+    }
+
+    // Records the scene draws, binding each draw group's pipeline from pipelines, parallel to
+    // draw_groups; expects the mesh layout's other sets bound. Instances go to set 1.
+    // NB! blended instances draw in instance order, not sorted back to front
+    pub fn draw(
+        &self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+        pipelines: &[vk::Pipeline],
+    ) {
+        let Some(buffers) = &self.buffers else {
+            return;
+        };
+
+        unsafe {
+            device_handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                1,
+                &[self.instance_descriptor_set],
+                &[],
+            );
+            device_handle.cmd_bind_index_buffer(
+                command_buffer,
+                buffers.indices.handle,
+                0,
+                vk::IndexType::UINT32,
+            );
+
+            for (index, (draw_group, &pipeline)) in
+                self.draw_groups.iter().zip(pipelines).enumerate()
+            {
+                device_handle.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline,
+                );
+                if self.gpu_driven {
+                    device_handle.cmd_draw_indexed_indirect_count(
+                        command_buffer,
+                        buffers.draw_commands.handle,
+                        draw_group.first_draw_command as u64 * DRAW_INDEXED_INDIRECT_COMMAND_SIZE,
+                        buffers.draw_count.handle,
+                        (FIRST_DRAW_COUNT as u64 + index as u64) * size_of::<u32>() as u64,
+                        draw_group.instance_count,
+                        DRAW_INDEXED_INDIRECT_COMMAND_SIZE as u32,
+                    );
+                    continue;
+                }
+
+                // NB! first_instance carries the instance index, as in the indirect commands
+                for run in &draw_group.visible_runs {
+                    device_handle.cmd_draw_indexed(
+                        command_buffer,
+                        run.index_count,
+                        run.instance_count,
+                        run.first_index,
+                        0,
+                        run.first_instance,
+                    );
+                }
+            }
+        }
+    }
+
+    fn drop_instance_staging(
+        &mut self,
+        device_handle: &DeviceHandle,
+        allocator: &mut vka::Allocator,
+    ) {
+        self.instance_staging
+            .drain(..)
+            .for_each(|(buffer, allocation)| {
+                unsafe { device_handle.destroy_buffer(buffer.handle, None) };
+                allocator
+                    .free(allocation)
+                    .expect("koi::ren::vk::gpu_scene - failed to Free Instance Staging allocation");
+            });
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle, allocator: &mut vka::Allocator) {
+        self.drop_instance_staging(device_handle, allocator);
+        self.statistics_readback
+            .drain(..)
+            .for_each(|(buffer, allocation)| {
+                unsafe { device_handle.destroy_buffer(buffer.handle, None) };
+                allocator.free(allocation).expect(
+                    "koi::ren::vk::gpu_scene - failed to Free Statistics Readback allocation",
+                );
+            });
+        self.descriptor_set_allocator.drop(device_handle);
+        unsafe {
+            device_handle.destroy_pipeline(self.cull_pipeline, None);
+            device_handle.destroy_shader_module(self.cull_shader, None);
+            device_handle.destroy_pipeline_layout(self.cull_pipeline_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.cull_descriptor_set_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.instance_descriptor_set_layout, None);
+        }
+    }
+}
+
+// Level 0 is the full-detail surface; ranges are offset into the shared scene index buffer.
+fn get_instance_lods(surface: &Surface, base_index: u32) -> InstanceLods {
+    let mut lods = InstanceLods {
+        first_indices: UVec4::splat(base_index + surface.start_index),
+        index_counts: UVec4::splat(surface.count),
+        errors: Vec4::ZERO,
+        counts: UVec4::ONE,
+    };
+    for (level, lod) in surface.lods[..surface.lod_count as usize]
+        .iter()
+        .enumerate()
+    {
+        lods.first_indices[level] = base_index + lod.start_index;
+        lods.index_counts[level] = lod.count;
+        lods.errors[level] = lod.error;
+    }
+    lods.counts.x = surface.lod_count.max(1);
+    lods
+}
+
+// Mirrors the cull shader: object-space error to threshold units at the instance's distance.
+fn get_error_scale(instance: &Instance, bounds: &Bounds, cull_view: &CullView) -> f32 {
+    let transform = instance.transform;
+    let scale = transform
+        .x_axis
+        .xyz()
+        .length()
+        .max(transform.y_axis.xyz().length())
+        .max(transform.z_axis.xyz().length());
+    let distance =
+        (bounds.center().distance(cull_view.camera_position) - bounds.radius()).max(1e-3);
+    scale * cull_view.lod_scale / distance
+}
+
+pub fn upload<T: Copy>(
+    device_handle: &DeviceHandle,
+    resource_allocator: &mut ResourceAllocator,
+    immediate_manager: &mut ImmediateManager,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+    name: &str,
+) -> Buffer {
+    let size = size_of_val(data) as u64;
+    let buffer = Buffer::new(
+        device_handle,
+        &mut resource_allocator.handle,
+        &mut resource_allocator.global_resources,
+        size,
+        usage | vk::BufferUsageFlags::TRANSFER_DST,
+        name,
+        MemoryLocation::GpuOnly,
+    );
+
+    let (mut staging_buffer, mut staging_allocation) = Buffer::create(
+        device_handle,
+        &mut resource_allocator.handle,
+        size,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        "scene_staging",
+        MemoryLocation::CpuToGpu,
+    );
+    staging_buffer.upload(data, &mut staging_allocation, 0);
+
+    immediate_manager.submit(device_handle, &|command_buffer: vk::CommandBuffer| unsafe {
+        device_handle.cmd_copy_buffer(command_buffer, staging_buffer.handle, buffer.handle, &[
+            vk::BufferCopy::default().size(size),
+        ]);
+    });
+
+    unsafe { device_handle.destroy_buffer(staging_buffer.handle, None) };
+    resource_allocator
+        .handle
+        .free(staging_allocation)
+        .expect("koi::ren::vk::gpu_scene - failed to Free Staging Buffer allocation");
+
+    buffer
+}
+
+pub fn barrier(
+    device_handle: &DeviceHandle,
+    command_buffer: vk::CommandBuffer,
+    (src_stage_mask, src_access_mask): (vk::PipelineStageFlags2, vk::AccessFlags2),
+    (dst_stage_mask, dst_access_mask): (vk::PipelineStageFlags2, vk::AccessFlags2),
+) {
+    let memory_barriers = [vk::MemoryBarrier2::default()
+        .src_stage_mask(src_stage_mask)
+        .src_access_mask(src_access_mask)
+        .dst_stage_mask(dst_stage_mask)
+        .dst_access_mask(dst_access_mask)];
+    let dependency_info = vk::DependencyInfo::default().memory_barriers(&memory_barriers);
+
+    unsafe { device_handle.cmd_pipeline_barrier2(command_buffer, &dependency_info) };
+}
@@ -0,0 +1,237 @@
+use super::{
+    descriptor::{DescriptorSetAllocator, DescriptorSetLayoutBuilder, DescriptorSetPoolSizeRatio},
+    image::{self, Image},
+    pipeline,
+    reflect::ShaderReflection,
+    resource_allocator::ResourceAllocator,
+};
+
+use ash::{Device as DeviceHandle, vk};
+use spirv_std::glam::Mat4;
+
+pub const PYRAMID_FORMAT: vk::Format = vk::Format::R32_SFLOAT;
+// NB! checked against the threads() declared by hiz.spv
+const HIZ_GROUP_SIZE: u32 = 8;
+
+// Hierarchical-Z depth pyramid: mip 0 is the depth image at half resolution, every further mip
+// halves again. Texels keep the farthest depth they cover (the minimum, under reverse-Z), so an
+// instance behind a texel's depth is hidden by everything beneath it. Lives in GENERAL.
+pub struct HiZ {
+    pub pyramid: Image,
+    pub mip_views: Vec<vk::ImageView>,
+    // view-projection of the depth the pyramid was last built from
+    pub view_projection: Mat4,
+    pub valid: bool,
+
+    // 0 source (depth image or mip N-1), 1 destination mip N
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_sets: Vec<vk::DescriptorSet>,
+    pub descriptor_set_allocator: DescriptorSetAllocator,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub shader: vk::ShaderModule,
+    pub pipeline: vk::Pipeline,
+}
+
+impl HiZ {
+    pub fn new(
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+        resource_allocator: &mut ResourceAllocator,
+        depth_image: &Image,
+    ) -> Self {
+        let extent = image::get_mip_extent(depth_image.extent_2d, 1);
+        let mip_levels = image::get_mip_levels(extent);
+        let (pyramid, allocation) = Image::create_mipmapped(
+            device_handle,
+            &mut resource_allocator.handle,
+            PYRAMID_FORMAT,
+            vk::Extent3D::default()
+                .width(extent.width)
+                .height(extent.height)
+                .depth(1),
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            mip_levels,
+            vk::ImageCreateFlags::empty(),
+        );
+        resource_allocator
+            .global_resources
+            .add_image(pyramid.handle, pyramid.view, allocation);
+
+        let mip_views: Vec<_> = (0..mip_levels)
+            .map(|mip_level| {
+                image::create_view(
+                    device_handle,
+                    &pyramid,
+                    vk::ImageViewType::TYPE_2D,
+                    PYRAMID_FORMAT,
+                    mip_level,
+                    1,
+                )
+            })
+            .collect();
+
+        let shader_code = include_bytes!(env!("hiz.spv"));
+        let reflection = ShaderReflection::new(shader_code);
+        let entry_point = reflection.get_entry_point(c"main_cs");
+        entry_point.check_workgroup_size([HIZ_GROUP_SIZE, HIZ_GROUP_SIZE, 1]);
+
+        let descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(&[entry_point], 0)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+        let mut descriptor_set_allocator =
+            DescriptorSetAllocator::new(device_handle, mip_levels, &[
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::STORAGE_IMAGE, 1.0),
+            ]);
+
+        let descriptor_sets = (0..mip_levels as usize)
+            .map(|mip_level| {
+                let descriptor_set =
+                    descriptor_set_allocator.allocate(device_handle, &[descriptor_set_layout]);
+                let src_info = [match mip_level {
+                    0 => vk::DescriptorImageInfo::default()
+                        .image_layout(vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL)
+                        .image_view(depth_image.view),
+                    _ => vk::DescriptorImageInfo::default()
+                        .image_layout(vk::ImageLayout::GENERAL)
+                        .image_view(mip_views[mip_level - 1]),
+                }];
+                let dst_info = [vk::DescriptorImageInfo::default()
+                    .image_layout(vk::ImageLayout::GENERAL)
+                    .image_view(mip_views[mip_level])];
+                let descriptor_writes = [
+                    vk::WriteDescriptorSet::default()
+                        .dst_binding(0)
+                        .dst_set(descriptor_set)
+                        .descriptor_count(1)
+                        .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                        .image_info(&src_info),
+                    vk::WriteDescriptorSet::default()
+                        .dst_binding(1)
+                        .dst_set(descriptor_set)
+                        .descriptor_count(1)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(&dst_info),
+                ];
+                unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+                descriptor_set
+            })
+            .collect();
+
+        let pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            device_handle,
+            &[entry_point],
+            &[descriptor_set_layout],
+            &[],
+        );
+        let shader = pipeline::load_shader_module(device_handle, shader_code, None);
+        let pipeline = pipeline::create_compute_pipeline(
+            device_handle,
+            pipeline_cache,
+            shader,
+            pipeline_layout,
+        );
+
+        Self {
+            pyramid,
+            mip_views,
+            view_projection: Mat4::IDENTITY,
+            valid: false,
+
+            descriptor_set_layout,
+            descriptor_sets,
+            descriptor_set_allocator,
+            pipeline_layout,
+            shader,
+            pipeline,
+        }
+    }
+
+    // Expects the depth image in DEPTH_ATTACHMENT_OPTIMAL, as left by the mesh pass; leaves it in
+    // DEPTH_READ_ONLY_OPTIMAL.
+    pub fn build(
+        &mut self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+        depth_image: &Image,
+        view_projection: Mat4,
+    ) {
+        image::transition(
+            device_handle,
+            command_buffer,
+            depth_image.handle,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+        );
+        // NB! contents are fully rewritten; the old layout only matters for the very first build
+        image::transition(
+            device_handle,
+            command_buffer,
+            self.pyramid.handle,
+            match self.valid {
+                true => vk::ImageLayout::GENERAL,
+                false => vk::ImageLayout::UNDEFINED,
+            },
+            vk::ImageLayout::GENERAL,
+        );
+
+        unsafe {
+            device_handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            )
+        };
+
+        for mip_level in 0..self.pyramid.mip_levels {
+            let extent = image::get_mip_extent(self.pyramid.extent_2d, mip_level);
+            unsafe {
+                device_handle.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline_layout,
+                    0,
+                    &[self.descriptor_sets[mip_level as usize]],
+                    &[],
+                );
+                device_handle.cmd_dispatch(
+                    command_buffer,
+                    extent.width.div_ceil(HIZ_GROUP_SIZE),
+                    extent.height.div_ceil(HIZ_GROUP_SIZE),
+                    1,
+                );
+            }
+            // make level N visible before it is read to produce N+1, or by the next cull pass
+            image::transition_mips(
+                device_handle,
+                command_buffer,
+                self.pyramid.handle,
+                mip_level,
+                1,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::GENERAL,
+            );
+        }
+
+        self.view_projection = view_projection;
+        self.valid = true;
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        self.descriptor_set_allocator.drop(device_handle);
+        unsafe {
+            self.mip_views
+                .iter()
+                .for_each(|&view| device_handle.destroy_image_view(view, None));
+            device_handle.destroy_pipeline(self.pipeline, None);
+            device_handle.destroy_shader_module(self.shader, None);
+            device_handle.destroy_pipeline_layout(self.pipeline_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
@@ -0,0 +1,702 @@
+use crate::scene::environment::Environment;
+
+use super::{
+    ImmediateManager,
+    buffer::Buffer,
+    descriptor::{DescriptorSetAllocator, DescriptorSetLayoutBuilder, DescriptorSetPoolSizeRatio},
+    image::{self, Image},
+    pipeline,
+    reflect::{EntryPoint, ShaderReflection},
+    resource_allocator::ResourceAllocator,
+    sampler::{SamplerCache, SamplerKey},
+};
+
+use ash::{Device as DeviceHandle, vk};
+use gpu_allocator::MemoryLocation;
+use koi_gpu::{ComputePushConstants, GpuLayout};
+use spirv_std::glam::Vec4;
+
+pub const ENVIRONMENT_SIZE: u32 = 512;
+pub const IRRADIANCE_SIZE: u32 = 32;
+pub const PREFILTERED_SIZE: u32 = 128;
+pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+pub const BRDF_LUT_SIZE: u32 = 256;
+
+const PREFILTER_SAMPLE_COUNT: f32 = 512.0;
+const BRDF_LUT_SAMPLE_COUNT: f32 = 1024.0;
+// NB! irradiance convolution samples a blurred mip; hides the coarse angular step
+const IRRADIANCE_SOURCE_LOD: f32 = 4.0;
+// one set per bake dispatch; every set reserves all five bindings: equirect, irradiance, prefilter mips, brdf lut
+const IBL_MAX_SETS: u32 = 3 + PREFILTERED_MIP_LEVELS;
+// NB! checked against the threads() declared by each entry point in ibl.spv: equirect and the
+// BRDF LUT run 16x16 groups, the convolutions 8x8
+const WIDE_GROUP_SIZE: u32 = 16;
+const CONVOLUTION_GROUP_SIZE: u32 = 8;
+
+pub const CUBE_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+pub const BRDF_LUT_FORMAT: vk::Format = vk::Format::R16G16_SFLOAT;
+const EQUIRECT_FORMAT: vk::Format = vk::Format::R32G32B32A32_SFLOAT;
+
+// Image-based lighting baked from an equirectangular environment map: the environment cubemap,
+// a diffuse irradiance cubemap, a prefiltered specular cubemap (roughness per mip) and the
+// split-sum BRDF lookup table.
+pub struct Ibl {
+    pub environment: Image,
+    pub irradiance: Image,
+    pub prefiltered: Image,
+    pub brdf_lut: Image,
+    pub brdf_lut_baked: bool,
+    pub sampler: vk::Sampler,
+    pub intensity: f32,
+
+    // consumed by the mesh pass: 0 irradiance, 1 prefiltered, 2 brdf lut, 3 sampler
+    pub lighting_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub lighting_descriptor_set: vk::DescriptorSet,
+    // consumed by the skybox compute effect: 0 environment, 1 sampler
+    pub skybox_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub skybox_descriptor_set: vk::DescriptorSet,
+
+    pub bake_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub bake_descriptor_set_allocator: DescriptorSetAllocator,
+    pub bake_pipeline_layout: vk::PipelineLayout,
+    pub shader: vk::ShaderModule,
+    pub equirect_pipeline: vk::Pipeline,
+    pub irradiance_pipeline: vk::Pipeline,
+    pub prefilter_pipeline: vk::Pipeline,
+    pub brdf_lut_pipeline: vk::Pipeline,
+}
+
+impl Ibl {
+    pub fn new(
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+        resource_allocator: &mut ResourceAllocator,
+        descriptor_set_allocator: &mut DescriptorSetAllocator,
+        sampler_cache: &mut SamplerCache,
+        mesh_entry_points: &[&EntryPoint],
+        skybox_entry_point: &EntryPoint,
+    ) -> Self {
+        let cube_usage = vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE;
+        let environment = new_cube(
+            device_handle,
+            resource_allocator,
+            ENVIRONMENT_SIZE,
+            cube_usage | vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::TRANSFER_DST,
+            image::get_mip_levels(
+                vk::Extent2D::default()
+                    .width(ENVIRONMENT_SIZE)
+                    .height(ENVIRONMENT_SIZE),
+            ),
+        );
+        let irradiance = new_cube(
+            device_handle,
+            resource_allocator,
+            IRRADIANCE_SIZE,
+            cube_usage,
+            1,
+        );
+        let prefiltered = new_cube(
+            device_handle,
+            resource_allocator,
+            PREFILTERED_SIZE,
+            cube_usage,
+            PREFILTERED_MIP_LEVELS,
+        );
+        let brdf_lut = Image::new(
+            device_handle,
+            &mut resource_allocator.handle,
+            &mut resource_allocator.global_resources,
+            BRDF_LUT_FORMAT,
+            vk::Extent3D::default()
+                .width(BRDF_LUT_SIZE)
+                .height(BRDF_LUT_SIZE)
+                .depth(1),
+            vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::STORAGE,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let sampler = sampler_cache.get(
+            device_handle,
+            SamplerKey::default().address_mode(
+                vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ),
+        );
+
+        // irradiance, prefiltered, brdf lut and their sampler
+        let lighting_descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(mesh_entry_points, 0)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+        let lighting_descriptor_set =
+            descriptor_set_allocator.allocate(device_handle, &[lighting_descriptor_set_layout]);
+        update_sampled_set(
+            device_handle,
+            lighting_descriptor_set,
+            &[irradiance.view, prefiltered.view, brdf_lut.view],
+            sampler,
+        );
+
+        // environment and its sampler
+        let skybox_descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(&[skybox_entry_point], 1)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+        let skybox_descriptor_set =
+            descriptor_set_allocator.allocate(device_handle, &[skybox_descriptor_set_layout]);
+        update_sampled_set(
+            device_handle,
+            skybox_descriptor_set,
+            &[environment.view],
+            sampler,
+        );
+
+        let shader_code = include_bytes!(env!("ibl.spv"));
+        let reflection = ShaderReflection::new(shader_code);
+        let entry_points = [
+            (c"equirect_cs", WIDE_GROUP_SIZE),
+            (c"irradiance_cs", CONVOLUTION_GROUP_SIZE),
+            (c"prefilter_cs", CONVOLUTION_GROUP_SIZE),
+            (c"brdf_lut_cs", WIDE_GROUP_SIZE),
+        ]
+        .map(|(name, group_size)| {
+            let entry_point = reflection.get_entry_point(name);
+            entry_point.check_workgroup_size([group_size, group_size, 1]);
+            entry_point
+        });
+
+        // NB! the four bake entry points share one set layout and pipeline layout
+        let bake_descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(&entry_points, 0)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+        let bake_descriptor_set_allocator =
+            DescriptorSetAllocator::new(device_handle, IBL_MAX_SETS, &[
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::STORAGE_IMAGE, 3.0),
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::SAMPLER, 1.0),
+            ]);
+
+        let bake_pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            device_handle,
+            &entry_points,
+            &[bake_descriptor_set_layout],
+            ComputePushConstants::MEMBERS,
+        );
+
+        let shader = pipeline::load_shader_module(device_handle, shader_code, None);
+        let [
+            equirect_pipeline,
+            irradiance_pipeline,
+            prefilter_pipeline,
+            brdf_lut_pipeline,
+        ] = [
+            c"equirect_cs",
+            c"irradiance_cs",
+            c"prefilter_cs",
+            c"brdf_lut_cs",
+        ]
+        .map(|entry_point| {
+            pipeline::create_compute_pipeline_with_entry_point(
+                device_handle,
+                pipeline_cache,
+                shader,
+                bake_pipeline_layout,
+                entry_point,
+            )
+        });
+
+        Self {
+            environment,
+            irradiance,
+            prefiltered,
+            brdf_lut,
+            brdf_lut_baked: false,
+            sampler,
+            intensity: 1.0,
+
+            lighting_descriptor_set_layout,
+            lighting_descriptor_set,
+            skybox_descriptor_set_layout,
+            skybox_descriptor_set,
+
+            bake_descriptor_set_layout,
+            bake_descriptor_set_allocator,
+            bake_pipeline_layout,
+            shader,
+            equirect_pipeline,
+            irradiance_pipeline,
+            prefilter_pipeline,
+            brdf_lut_pipeline,
+        }
+    }
+
+    // Uploads the equirectangular map and bakes every IBL image from it; blocks until done.
+    // Leaves every image in SHADER_READ_ONLY_OPTIMAL.
+    pub fn load(
+        &mut self,
+        device_handle: &DeviceHandle,
+        resource_allocator: &mut ResourceAllocator,
+        immediate_manager: &mut ImmediateManager,
+        environment: &Environment,
+    ) {
+        let (equirect, equirect_allocation) = Image::create(
+            device_handle,
+            &mut resource_allocator.handle,
+            EQUIRECT_FORMAT,
+            vk::Extent3D::default()
+                .width(environment.width)
+                .height(environment.height)
+                .depth(1),
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_DST,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let (mut staging_buffer, mut staging_allocation) = Buffer::create(
+            device_handle,
+            &mut resource_allocator.handle,
+            size_of_val(environment.pixels.as_slice()) as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            "environment_staging",
+            MemoryLocation::CpuToGpu,
+        );
+        staging_buffer.upload(&environment.pixels, &mut staging_allocation, 0);
+
+        let environment_view = image::create_view(
+            device_handle,
+            &self.environment,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            CUBE_FORMAT,
+            0,
+            1,
+        );
+        let irradiance_view = image::create_view(
+            device_handle,
+            &self.irradiance,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            CUBE_FORMAT,
+            0,
+            1,
+        );
+        let prefiltered_views: Vec<_> = (0..PREFILTERED_MIP_LEVELS)
+            .map(|mip_level| {
+                image::create_view(
+                    device_handle,
+                    &self.prefiltered,
+                    vk::ImageViewType::TYPE_2D_ARRAY,
+                    CUBE_FORMAT,
+                    mip_level,
+                    1,
+                )
+            })
+            .collect();
+
+        let equirect_set = self.allocate_bake_set(device_handle, BakeBindings {
+            equirect: Some(equirect.view),
+            output: Some(environment_view),
+            ..Default::default()
+        });
+        let irradiance_set = self.allocate_bake_set(device_handle, BakeBindings {
+            environment: Some(self.environment.view),
+            output: Some(irradiance_view),
+            ..Default::default()
+        });
+        let prefilter_sets: Vec<_> = prefiltered_views
+            .iter()
+            .map(|&prefiltered_view| {
+                self.allocate_bake_set(device_handle, BakeBindings {
+                    environment: Some(self.environment.view),
+                    output: Some(prefiltered_view),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let brdf_lut_set = self.allocate_bake_set(device_handle, BakeBindings {
+            lut: Some(self.brdf_lut.view),
+            ..Default::default()
+        });
+
+        immediate_manager.submit(device_handle, &|command_buffer: vk::CommandBuffer| {
+            // equirect upload
+            image::transition(
+                device_handle,
+                command_buffer,
+                equirect.handle,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+            let regions = [vk::BufferImageCopy::default()
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(0)
+                        .layer_count(1),
+                )
+                .image_extent(equirect.extent_3d)];
+            unsafe {
+                device_handle.cmd_copy_buffer_to_image(
+                    command_buffer,
+                    staging_buffer.handle,
+                    equirect.handle,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &regions,
+                )
+            };
+            image::transition(
+                device_handle,
+                command_buffer,
+                equirect.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::GENERAL,
+            );
+
+            // equirect -> environment cube, then its mip chain for filtered sampling
+            image::transition(
+                device_handle,
+                command_buffer,
+                self.environment.handle,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            );
+            self.dispatch(
+                device_handle,
+                command_buffer,
+                self.equirect_pipeline,
+                equirect_set,
+                ComputePushConstants::default(),
+                ENVIRONMENT_SIZE,
+            );
+            image::transition(
+                device_handle,
+                command_buffer,
+                self.environment.handle,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            );
+            image::generate_mipmaps(device_handle, command_buffer, &self.environment);
+
+            // diffuse irradiance
+            image::transition(
+                device_handle,
+                command_buffer,
+                self.irradiance.handle,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            );
+            self.dispatch(
+                device_handle,
+                command_buffer,
+                self.irradiance_pipeline,
+                irradiance_set,
+                ComputePushConstants::default().data_0(Vec4::new(
+                    0.0,
+                    0.0,
+                    ENVIRONMENT_SIZE as f32,
+                    IRRADIANCE_SOURCE_LOD,
+                )),
+                IRRADIANCE_SIZE,
+            );
+            image::transition(
+                device_handle,
+                command_buffer,
+                self.irradiance.handle,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+
+            // prefiltered specular; roughness increases linearly with mip level
+            image::transition(
+                device_handle,
+                command_buffer,
+                self.prefiltered.handle,
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::GENERAL,
+            );
+            for (mip_level, &prefilter_set) in prefilter_sets.iter().enumerate() {
+                let roughness = mip_level as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+                self.dispatch(
+                    device_handle,
+                    command_buffer,
+                    self.prefilter_pipeline,
+                    prefilter_set,
+                    ComputePushConstants::default().data_0(Vec4::new(
+                        roughness,
+                        PREFILTER_SAMPLE_COUNT,
+                        ENVIRONMENT_SIZE as f32,
+                        0.0,
+                    )),
+                    (PREFILTERED_SIZE >> mip_level).max(1),
+                );
+            }
+            image::transition(
+                device_handle,
+                command_buffer,
+                self.prefiltered.handle,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+
+            // environment independent; baked once
+            if !self.brdf_lut_baked {
+                image::transition(
+                    device_handle,
+                    command_buffer,
+                    self.brdf_lut.handle,
+                    vk::ImageLayout::UNDEFINED,
+                    vk::ImageLayout::GENERAL,
+                );
+                self.dispatch(
+                    device_handle,
+                    command_buffer,
+                    self.brdf_lut_pipeline,
+                    brdf_lut_set,
+                    ComputePushConstants::default().data_0(Vec4::new(
+                        0.0,
+                        BRDF_LUT_SAMPLE_COUNT,
+                        0.0,
+                        0.0,
+                    )),
+                    BRDF_LUT_SIZE,
+                );
+                image::transition(
+                    device_handle,
+                    command_buffer,
+                    self.brdf_lut.handle,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                );
+            }
+        });
+        self.brdf_lut_baked = true;
+
+        // submit waits on its fence; bake resources are no longer in use
+        self.bake_descriptor_set_allocator.reset_pool(device_handle);
+        unsafe {
+            [environment_view, irradiance_view]
+                .into_iter()
+                .chain(prefiltered_views)
+                .for_each(|view| device_handle.destroy_image_view(view, None));
+            device_handle.destroy_image_view(equirect.view, None);
+            device_handle.destroy_image(equirect.handle, None);
+            device_handle.destroy_buffer(staging_buffer.handle, None);
+        }
+        resource_allocator
+            .handle
+            .free(equirect_allocation)
+            .expect("koi::ren::vk::ibl - failed to Free Equirect Image allocation");
+        resource_allocator
+            .handle
+            .free(staging_allocation)
+            .expect("koi::ren::vk::ibl - failed to Free Staging Buffer allocation");
+    }
+
+    fn allocate_bake_set(
+        &mut self,
+        device_handle: &DeviceHandle,
+        bindings: BakeBindings,
+    ) -> vk::DescriptorSet {
+        let descriptor_set = self
+            .bake_descriptor_set_allocator
+            .allocate(device_handle, &[self.bake_descriptor_set_layout]);
+
+        let storage_info = |image_view| {
+            [vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::GENERAL)
+                .image_view(image_view)]
+        };
+        let equirect_info = bindings.equirect.map(storage_info);
+        let output_info = bindings.output.map(storage_info);
+        let lut_info = bindings.lut.map(storage_info);
+        let environment_info = bindings.environment.map(|image_view| {
+            [vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(image_view)]
+        });
+        let sampler_info = [vk::DescriptorImageInfo::default().sampler(self.sampler)];
+
+        // NB! only the bindings a pass statically uses need valid descriptors
+        let mut descriptor_writes = vec![];
+        for (binding, descriptor_type, image_info) in [
+            (0, vk::DescriptorType::STORAGE_IMAGE, equirect_info.as_ref()),
+            (
+                1,
+                vk::DescriptorType::SAMPLED_IMAGE,
+                environment_info.as_ref(),
+            ),
+            (3, vk::DescriptorType::STORAGE_IMAGE, output_info.as_ref()),
+            (4, vk::DescriptorType::STORAGE_IMAGE, lut_info.as_ref()),
+        ] {
+            if let Some(image_info) = image_info {
+                descriptor_writes.push(
+                    vk::WriteDescriptorSet::default()
+                        .dst_binding(binding)
+                        .dst_set(descriptor_set)
+                        .descriptor_count(1)
+                        .descriptor_type(descriptor_type)
+                        .image_info(image_info),
+                );
+            }
+        }
+        if environment_info.is_some() {
+            descriptor_writes.push(
+                vk::WriteDescriptorSet::default()
+                    .dst_binding(2)
+                    .dst_set(descriptor_set)
+                    .descriptor_count(1)
+                    .descriptor_type(vk::DescriptorType::SAMPLER)
+                    .image_info(&sampler_info),
+            );
+        }
+
+        unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+        descriptor_set
+    }
+
+    // Dispatches over a square output; z covers the six cube faces unless writing the LUT.
+    fn dispatch(
+        &self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+        pipeline: vk::Pipeline,
+        descriptor_set: vk::DescriptorSet,
+        push_constants: ComputePushConstants,
+        size: u32,
+    ) {
+        let layer_count = match pipeline == self.brdf_lut_pipeline {
+            true => 1,
+            false => image::CUBE_FACE_COUNT,
+        };
+        let group_size =
+            match pipeline == self.equirect_pipeline || pipeline == self.brdf_lut_pipeline {
+                true => WIDE_GROUP_SIZE,
+                false => CONVOLUTION_GROUP_SIZE,
+            };
+        let group_count = size.div_ceil(group_size);
+
+        unsafe {
+            device_handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                pipeline,
+            );
+            device_handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.bake_pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            pipeline::push_constants(
+                device_handle,
+                command_buffer,
+                self.bake_pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                &push_constants,
+            );
+            device_handle.cmd_dispatch(command_buffer, group_count, group_count, layer_count);
+        }
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        self.bake_descriptor_set_allocator.drop(device_handle);
+        unsafe {
+            [
+                self.equirect_pipeline,
+                self.irradiance_pipeline,
+                self.prefilter_pipeline,
+                self.brdf_lut_pipeline,
+            ]
+            .into_iter()
+            .for_each(|pipeline| device_handle.destroy_pipeline(pipeline, None));
+            device_handle.destroy_shader_module(self.shader, None);
+            device_handle.destroy_pipeline_layout(self.bake_pipeline_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.bake_descriptor_set_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.skybox_descriptor_set_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.lighting_descriptor_set_layout, None);
+        }
+    }
+}
+
+#[derive(Default)]
+struct BakeBindings {
+    equirect: Option<vk::ImageView>,
+    environment: Option<vk::ImageView>,
+    output: Option<vk::ImageView>,
+    lut: Option<vk::ImageView>,
+}
+
+fn new_cube(
+    device_handle: &DeviceHandle,
+    resource_allocator: &mut ResourceAllocator,
+    size: u32,
+    usage: vk::ImageUsageFlags,
+    mip_levels: u32,
+) -> Image {
+    let (image, allocation) = Image::create_cube(
+        device_handle,
+        &mut resource_allocator.handle,
+        CUBE_FORMAT,
+        size,
+        usage,
+        mip_levels,
+    );
+    resource_allocator
+        .global_resources
+        .add_image(image.handle, image.view, allocation);
+    image
+}
+
+// Writes sampled images to bindings 0..n, followed by the sampler.
+fn update_sampled_set(
+    device_handle: &DeviceHandle,
+    dst_set: vk::DescriptorSet,
+    image_views: &[vk::ImageView],
+    sampler: vk::Sampler,
+) {
+    let image_infos: Vec<_> = image_views
+        .iter()
+        .map(|&image_view| {
+            [vk::DescriptorImageInfo::default()
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                .image_view(image_view)]
+        })
+        .collect();
+    let sampler_info = [vk::DescriptorImageInfo::default().sampler(sampler)];
+
+    let mut descriptor_writes: Vec<_> = image_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, image_info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_binding(binding as u32)
+                .dst_set(dst_set)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(image_info)
+        })
+        .collect();
+    descriptor_writes.push(
+        vk::WriteDescriptorSet::default()
+            .dst_binding(image_views.len() as u32)
+            .dst_set(dst_set)
+            .descriptor_count(1)
+            .descriptor_type(vk::DescriptorType::SAMPLER)
+            .image_info(&sampler_info),
+    );
+
+    unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+}
@@ -3,6 +3,8 @@ use super::resource_allocator::AllocatedResources;
 use ash::{Device as DeviceHandle, vk};
 use gpu_allocator::{MemoryLocation, vulkan as vka};
 
+pub const CUBE_FACE_COUNT: u32 = 6;
+
 #[allow(unused)]
 pub struct Image {
     pub handle: vk::Image,
@@ -10,6 +12,8 @@ pub struct Image {
     pub extent_3d: vk::Extent3D,
     pub extent_2d: vk::Extent2D,
     pub format: vk::Format,
+    pub mip_levels: u32,
+    pub array_layers: u32,
 }
 
 impl Image {
@@ -21,7 +25,7 @@ impl Image {
         usage: vk::ImageUsageFlags,
         aspect_mask: vk::ImageAspectFlags,
     ) -> (Self, vka::Allocation) {
-        let image_create_info = vk::ImageCreateInfo::default()
+        let create_info = vk::ImageCreateInfo::default()
             .image_type(vk::ImageType::TYPE_2D)
             .format(format)
             .extent(extent)
@@ -30,10 +34,89 @@ impl Image {
             .samples(vk::SampleCountFlags::TYPE_1)
             .tiling(vk::ImageTiling::OPTIMAL)
             .usage(usage);
+        Self::create_from_info(
+            device_handle,
+            allocator,
+            &create_info,
+            vk::ImageViewType::TYPE_2D,
+            aspect_mask,
+        )
+    }
+
+    pub fn create_mipmapped(
+        device_handle: &DeviceHandle,
+        allocator: &mut vka::Allocator,
+        format: vk::Format,
+        extent: vk::Extent3D,
+        usage: vk::ImageUsageFlags,
+        mip_levels: u32,
+        flags: vk::ImageCreateFlags,
+    ) -> (Self, vka::Allocation) {
+        let create_info = vk::ImageCreateInfo::default()
+            .flags(flags)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(extent)
+            .mip_levels(mip_levels)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage);
+        Self::create_from_info(
+            device_handle,
+            allocator,
+            &create_info,
+            vk::ImageViewType::TYPE_2D,
+            vk::ImageAspectFlags::COLOR,
+        )
+    }
+
+    // Six-layer, cube-compatible image; the default view is a CUBE view over every level.
+    pub fn create_cube(
+        device_handle: &DeviceHandle,
+        allocator: &mut vka::Allocator,
+        format: vk::Format,
+        size: u32,
+        usage: vk::ImageUsageFlags,
+        mip_levels: u32,
+    ) -> (Self, vka::Allocation) {
+        let create_info = vk::ImageCreateInfo::default()
+            .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D::default().width(size).height(size).depth(1))
+            .mip_levels(mip_levels)
+            .array_layers(CUBE_FACE_COUNT)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage);
+        Self::create_from_info(
+            device_handle,
+            allocator,
+            &create_info,
+            vk::ImageViewType::CUBE,
+            vk::ImageAspectFlags::COLOR,
+        )
+    }
+
+    fn create_from_info(
+        device_handle: &DeviceHandle,
+        allocator: &mut vka::Allocator,
+        image_create_info: &vk::ImageCreateInfo,
+        view_type: vk::ImageViewType,
+        aspect_mask: vk::ImageAspectFlags,
+    ) -> (Self, vka::Allocation) {
+        let vk::ImageCreateInfo {
+            format,
+            extent,
+            mip_levels,
+            array_layers,
+            ..
+        } = *image_create_info;
 
         let image = unsafe {
             device_handle
-                .create_image(&image_create_info, None)
+                .create_image(image_create_info, None)
                 .expect("koi::vk::Image - failed to create Image")
         };
         let requirements = unsafe { device_handle.get_image_memory_requirements(image) };
@@ -55,15 +138,15 @@ impl Image {
         }
 
         let view_create_info = vk::ImageViewCreateInfo::default()
-            .view_type(vk::ImageViewType::TYPE_2D)
+            .view_type(view_type)
             .image(image)
             .format(format)
             .subresource_range(
                 vk::ImageSubresourceRange::default()
                     .base_mip_level(0)
-                    .level_count(1)
+                    .level_count(mip_levels)
                     .base_array_layer(0)
-                    .layer_count(1)
+                    .layer_count(array_layers)
                     .aspect_mask(aspect_mask),
             );
 
@@ -84,6 +167,8 @@ impl Image {
                 extent_3d: extent,
                 extent_2d,
                 format,
+                mip_levels,
+                array_layers,
             },
             allocation,
         )
@@ -105,6 +190,42 @@ impl Image {
     }
 }
 
+pub fn get_mip_levels(extent: vk::Extent2D) -> u32 {
+    extent.width.max(extent.height).max(1).ilog2() + 1
+}
+
+pub fn get_mip_extent(extent: vk::Extent2D, mip_level: u32) -> vk::Extent2D {
+    vk::Extent2D::default()
+        .width((extent.width >> mip_level).max(1))
+        .height((extent.height >> mip_level).max(1))
+}
+
+// Creates an additional view over a subset of the image's mip levels, covering every layer.
+pub fn create_view(
+    device_handle: &DeviceHandle,
+    image: &Image,
+    view_type: vk::ImageViewType,
+    format: vk::Format,
+    base_mip_level: u32,
+    level_count: u32,
+) -> vk::ImageView {
+    let create_info = vk::ImageViewCreateInfo::default()
+        .view_type(view_type)
+        .image(image.handle)
+        .format(format)
+        .subresource_range(
+            get_subresource_range(vk::ImageAspectFlags::COLOR)
+                .base_mip_level(base_mip_level)
+                .level_count(level_count),
+        );
+
+    unsafe {
+        device_handle
+            .create_image_view(&create_info, None)
+            .expect("koi::vk::Image - failed to create Image View")
+    }
+}
+
 pub fn get_subresource_range(aspect_mask: vk::ImageAspectFlags) -> vk::ImageSubresourceRange {
     vk::ImageSubresourceRange::default()
         .aspect_mask(aspect_mask)
@@ -121,12 +242,12 @@ pub fn transition(
     old_layout: vk::ImageLayout,
     new_layout: vk::ImageLayout,
 ) {
-    let subresource_range =
-        get_subresource_range(if new_layout == vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL {
+    let subresource_range = get_subresource_range(match new_layout {
+        vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL => {
             vk::ImageAspectFlags::DEPTH
-        } else {
-            vk::ImageAspectFlags::COLOR
-        });
+        }
+        _ => vk::ImageAspectFlags::COLOR,
+    });
     let image_barriers = [vk::ImageMemoryBarrier2::default()
         .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
         .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
@@ -190,3 +311,112 @@ pub fn copy(
 
     unsafe { device_handle.cmd_blit_image2(cmd, &blit_image_info) };
 }
+
+pub fn transition_mips(
+    device_handle: &DeviceHandle,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    base_mip_level: u32,
+    level_count: u32,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+) {
+    let subresource_range = get_subresource_range(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(base_mip_level)
+        .level_count(level_count);
+    let image_barriers = [vk::ImageMemoryBarrier2::default()
+        .src_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .src_access_mask(vk::AccessFlags2::MEMORY_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags2::ALL_COMMANDS)
+        .dst_access_mask(vk::AccessFlags2::MEMORY_WRITE | vk::AccessFlags2::MEMORY_READ)
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .subresource_range(subresource_range)
+        .image(image)];
+
+    let dependency_info = vk::DependencyInfo::default().image_memory_barriers(&image_barriers);
+
+    unsafe { device_handle.cmd_pipeline_barrier2(command_buffer, &dependency_info) };
+}
+
+// Downsamples mip 0 into the rest of the chain with successive blits. Expects every level in
+// TRANSFER_DST_OPTIMAL; leaves every level in SHADER_READ_ONLY_OPTIMAL.
+pub fn generate_mipmaps(
+    device_handle: &DeviceHandle,
+    command_buffer: vk::CommandBuffer,
+    image: &Image,
+) {
+    for mip_level in 1..image.mip_levels {
+        transition_mips(
+            device_handle,
+            command_buffer,
+            image.handle,
+            mip_level - 1,
+            1,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+
+        let src_extent = get_mip_extent(image.extent_2d, mip_level - 1);
+        let dst_extent = get_mip_extent(image.extent_2d, mip_level);
+        let regions = [vk::ImageBlit2::default()
+            .src_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D::default()
+                    .x(src_extent.width as i32)
+                    .y(src_extent.height as i32)
+                    .z(1),
+            ])
+            .dst_offsets([
+                vk::Offset3D::default(),
+                vk::Offset3D::default()
+                    .x(dst_extent.width as i32)
+                    .y(dst_extent.height as i32)
+                    .z(1),
+            ])
+            .src_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_array_layer(0)
+                    .layer_count(image.array_layers)
+                    .mip_level(mip_level - 1),
+            )
+            .dst_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .base_array_layer(0)
+                    .layer_count(image.array_layers)
+                    .mip_level(mip_level),
+            )];
+
+        let blit_image_info = vk::BlitImageInfo2::default()
+            .src_image(image.handle)
+            .src_image_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+            .dst_image(image.handle)
+            .dst_image_layout(vk::ImageLayout::TRANSFER_DST_OPTIMAL)
+            .filter(vk::Filter::LINEAR)
+            .regions(&regions);
+
+        unsafe { device_handle.cmd_blit_image2(command_buffer, &blit_image_info) };
+
+        transition_mips(
+            device_handle,
+            command_buffer,
+            image.handle,
+            mip_level - 1,
+            1,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    }
+
+    transition_mips(
+        device_handle,
+        command_buffer,
+        image.handle,
+        image.mip_levels - 1,
+        1,
+        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+    );
+}
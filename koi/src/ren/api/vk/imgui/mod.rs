@@ -1,12 +1,15 @@
 use crate::ren::api::vk::{
-    buffer::Buffer, device::config::QueueFamilyType, image::Image, pipeline,
+    buffer::Buffer,
+    device::config::QueueFamilyType,
+    image::Image,
+    pipeline,
+    reflect::{self, ShaderReflection},
 };
 
 use ash::{
     Device as DeviceHandle,
     vk::{self, Handle},
 };
-use bytemuck::cast_slice;
 use glam::Vec2;
 use gpu_allocator::{MemoryLocation, vulkan as vka};
 use imgui::{DrawData, internal::RawWrapper};
@@ -394,12 +397,16 @@ fn initialize_vulkan_structures(
             .expect("koi::ren::vk::imgui - failed to create ImGui Texture Sampler")
     };
 
+    // Shader Reflection
+    let shader = include_bytes!(env!("imgui.spv"));
+    let reflection = ShaderReflection::new(shader);
+    let entry_points = [
+        reflection.get_entry_point(c"main_vs"),
+        reflection.get_entry_point(c"main_fs"),
+    ];
+
     // Descriptor Set Layout
-    let bindings = [vk::DescriptorSetLayoutBinding::default()
-        .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-        .binding(0)
-        .descriptor_count(1)
-        .stage_flags(vk::ShaderStageFlags::FRAGMENT)];
+    let bindings = reflect::get_set_bindings(&entry_points, 0);
 
     let create_info = vk::DescriptorSetLayoutCreateInfo::default().bindings(&bindings);
 
@@ -474,19 +481,16 @@ fn initialize_vulkan_structures(
 
     // Pipeline Layout
     let set_layouts = [descriptor_set_layout];
-    let push_constant_ranges = [vk::PushConstantRange::default()
-        .stage_flags(vk::ShaderStageFlags::VERTEX)
-        .offset(0)
-        .size(F32_SIZE as u32 * 4)];
-    let pipeline_layout = pipeline::create_pipeline_layout(
+    // scale and translate
+    let pipeline_layout = pipeline::create_reflected_pipeline_layout(
         &api.device.handle,
+        &entry_points,
         &set_layouts,
-        Some(&push_constant_ranges),
+        &[(0, 2 * F32_SIZE), (2 * F32_SIZE, 2 * F32_SIZE)],
     );
 
     // Pipeline
-    let shader_module =
-        pipeline::load_shader_module(&api.device.handle, include_bytes!(env!("imgui.spv")), None);
+    let shader_module = pipeline::load_shader_module(&api.device.handle, shader, None);
 
     let stages = [
         vk::PipelineShaderStageCreateInfo::default()
@@ -582,7 +586,7 @@ fn initialize_vulkan_structures(
     let pipeline = unsafe {
         api.device
             .handle
-            .create_graphics_pipelines(vk::PipelineCache::null(), &create_infos, None)
+            .create_graphics_pipelines(api.pipeline_cache.handle, &create_infos, None)
             .expect("koi::ren::vk::imgui - failed to create ImGui Graphics Pipeline")[0]
     };
 
@@ -851,20 +855,12 @@ fn setup_render_state(
         -1.0 - draw_data.display_pos[1] * scale[1],
     );
 
-    unsafe {
-        api.device.handle.cmd_push_constants(
-            command_buffer,
-            pipeline_layout,
-            vk::ShaderStageFlags::VERTEX,
-            F32_SIZE as u32 * 0,
-            cast_slice::<f32, u8>(&scale.to_array()),
-        );
-        api.device.handle.cmd_push_constants(
-            command_buffer,
-            pipeline_layout,
-            vk::ShaderStageFlags::VERTEX,
-            F32_SIZE as u32 * 2,
-            cast_slice::<f32, u8>(&translate.to_array()),
-        );
-    }
+    // scale, then translate, as the imgui shader's PushConstants
+    pipeline::push_constants(
+        &api.device.handle,
+        command_buffer,
+        pipeline_layout,
+        vk::ShaderStageFlags::VERTEX,
+        &[scale.to_array(), translate.to_array()],
+    );
 }
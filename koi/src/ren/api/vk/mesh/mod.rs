@@ -4,12 +4,20 @@ use super::{ImmediateManager, buffer::Buffer, resource_allocator::AllocatedResou
 
 use ash::{Device as DeviceHandle, vk};
 use gpu_allocator::{MemoryLocation, vulkan as vka};
-use koi_gpu::{VERTEX_SIZE, Vertex};
+use koi_gpu::{CompactVertex, MORPH_DELTA_SIZE, MorphDelta, Vertex, VertexFormat};
+use spirv_std::glam::{Vec3, Vec4Swizzles};
 
 pub struct Mesh {
     pub index_buffer: Buffer,
     pub vertex_buffer: Buffer,
     pub vertex_buffer_address: vk::DeviceAddress,
+    pub vertex_format: VertexFormat,
+    // morph target deltas, target-major; None unless the mesh has targets
+    pub target_buffer: Option<Buffer>,
+    pub target_count: u32,
+    // object-space box compact positions are quantized within; tight around the vertices
+    pub quantization_origin: Vec3,
+    pub quantization_extent: Vec3,
     pub surfaces: Vec<Surface>,
 }
 
@@ -23,8 +31,32 @@ impl Mesh {
         immediate_manager: &mut ImmediateManager,
         indices: &[u32],
         vertices: &[Vertex],
+        vertex_format: VertexFormat,
+        // one list per target, each parallel to vertices
+        morph_targets: &[Vec<MorphDelta>],
         surfaces: Vec<Surface>,
     ) -> Self {
+        let (min, max) = vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), vertex| {
+                let position = vertex.position_uv_x.xyz();
+                (min.min(position), max.max(position))
+            },
+        );
+        let (quantization_origin, quantization_extent) = match vertices.is_empty() {
+            true => (Vec3::ZERO, Vec3::ONE),
+            false => (min, max - min),
+        };
+        let compact_vertices: Vec<CompactVertex> = match vertex_format {
+            VertexFormat::Full => vec![],
+            VertexFormat::Compact => vertices
+                .iter()
+                .map(|vertex| {
+                    CompactVertex::encode(vertex, quantization_origin, quantization_extent)
+                })
+                .collect(),
+        };
+
         let index_buffer_size = indices.len() as u64 * INDEX_SIZE;
         let index_buffer = Buffer::new(
             device_handle,
@@ -36,7 +68,7 @@ impl Mesh {
             MemoryLocation::GpuOnly,
         );
 
-        let vertex_buffer_size = vertices.len() as u64 * VERTEX_SIZE;
+        let vertex_buffer_size = vertices.len() as u64 * vertex_format.get_size();
         let vertex_buffer = Buffer::new(
             device_handle,
             allocator,
@@ -55,21 +87,55 @@ impl Mesh {
             )
         };
 
+        let deltas = morph_targets.concat();
+        let target_buffer_size = deltas.len() as u64 * MORPH_DELTA_SIZE;
+        let target_buffer = (!deltas.is_empty()).then(|| {
+            Buffer::new(
+                device_handle,
+                allocator,
+                resources,
+                target_buffer_size,
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
+                "mesh_targets",
+                MemoryLocation::GpuOnly,
+            )
+        });
+
         let (mut staging_buffer, mut staging_allocation) = Buffer::create(
             device_handle,
             allocator,
-            index_buffer_size + vertex_buffer_size,
+            index_buffer_size + vertex_buffer_size + target_buffer_size,
             vk::BufferUsageFlags::TRANSFER_SRC,
             "mesh_staging",
             MemoryLocation::CpuToGpu,
         );
+        // NB! every region starts at, and is padded to, the buffer alignment or the alignment of
+        // Vertex, the largest of the uploaded types; grow to fit once it's known
+        let alignment = (staging_buffer.min_alignment as vk::DeviceSize)
+            .max(align_of::<Vertex>() as vk::DeviceSize);
+        let targets_offset = vertex_buffer_size.next_multiple_of(alignment);
+        let indices_offset = targets_offset + target_buffer_size.next_multiple_of(alignment);
+        let padded_size = indices_offset + index_buffer_size.next_multiple_of(alignment);
+        if padded_size > staging_buffer.size {
+            staging_allocation = staging_buffer.resize(
+                device_handle,
+                allocator,
+                staging_allocation,
+                padded_size,
+                "mesh_staging",
+            );
+        }
 
-        let vertices_record = staging_buffer.upload(&vertices, &mut staging_allocation, 0);
-        staging_buffer.upload(
-            indices,
-            &mut staging_allocation,
-            vertices_record.copy_end_offset_padded,
-        );
+        match vertex_format {
+            VertexFormat::Full => staging_buffer.upload(vertices, &mut staging_allocation, 0),
+            VertexFormat::Compact => {
+                staging_buffer.upload(&compact_vertices, &mut staging_allocation, 0)
+            }
+        };
+        if !deltas.is_empty() {
+            staging_buffer.upload(&deltas, &mut staging_allocation, targets_offset as usize);
+        }
+        staging_buffer.upload(indices, &mut staging_allocation, indices_offset as usize);
 
         immediate_manager.submit(device_handle, &|command_buffer: vk::CommandBuffer| unsafe {
             device_handle.cmd_copy_buffer(
@@ -87,10 +153,22 @@ impl Mesh {
                 staging_buffer.handle,
                 index_buffer.handle,
                 &[vk::BufferCopy::default()
-                    .src_offset(vertices_record.copy_end_offset_padded as u64)
+                    .src_offset(indices_offset)
                     .dst_offset(0)
                     .size(index_buffer_size)],
             );
+
+            if let Some(target_buffer) = &target_buffer {
+                device_handle.cmd_copy_buffer(
+                    command_buffer,
+                    staging_buffer.handle,
+                    target_buffer.handle,
+                    &[vk::BufferCopy::default()
+                        .src_offset(targets_offset)
+                        .dst_offset(0)
+                        .size(target_buffer_size)],
+                );
+            }
         });
 
         allocator
@@ -101,6 +179,11 @@ impl Mesh {
             index_buffer,
             vertex_buffer,
             vertex_buffer_address,
+            vertex_format,
+            target_buffer,
+            target_count: morph_targets.len() as u32,
+            quantization_origin,
+            quantization_extent,
             surfaces,
         }
     }
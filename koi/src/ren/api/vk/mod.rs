@@ -2,111 +2,145 @@ pub mod buffer;
 pub mod descriptor;
 pub mod device;
 pub mod frame;
+pub mod frame_allocator;
+pub mod gpu_scene;
+pub mod hiz;
+pub mod ibl;
 pub mod image;
 pub mod imgui;
 pub mod instance;
 pub mod mesh;
 pub mod pipeline;
+pub mod pipeline_cache;
+pub mod post;
+pub mod reflect;
 pub mod resource_allocator;
+pub mod sampler;
+pub mod skinning;
 pub mod surface;
 pub mod swapchain;
+pub mod texture;
 
 use crate::{
     imgui::ImGui,
     ren::{Info, Renderer as RendererTrait, Settings, Window, settings::Resolution},
-    scene::Scene,
+    scene::{AlphaMode, Scene, environment::Environment, texture::ImageData},
     traits::Drop,
 };
 use descriptor::{DescriptorSetAllocator, DescriptorSetLayoutBuilder, DescriptorSetPoolSizeRatio};
 use device::{Device, config::QueueFamilyType};
 use frame::Frame;
+use frame_allocator::FrameAllocator;
+use gpu_scene::{GpuScene, MaterialVariant};
+use hiz::HiZ;
+use ibl::Ibl;
 use image::Image;
 use instance::Instance;
 use mesh::Mesh;
+use pipeline::{BlendMode, PipelineStateCache, PipelineStateKey};
+use pipeline_cache::PipelineCache;
+use post::PostProcessing;
+use reflect::ShaderReflection;
 use resource_allocator::ResourceAllocator;
+use sampler::SamplerCache;
+use skinning::Skinning;
 use surface::Surface;
 use swapchain::{SurfaceSupport, Swapchain};
+use texture::{MipGenerator, Texture, TextureTable};
+
+#[cfg(feature = "hot-reload")]
+use crate::ren::shader_watcher::ShaderWatcher;
 
 use ash::{Device as DeviceHandle, Entry, vk};
-use bytemuck::cast;
-use koi_gpu::{PUSH_CONSTANTS_SIZE, PushConstants};
-use spirv_std::glam::{Mat4, Vec4};
-
-#[derive(Default)]
-pub struct ComputePushConstants {
-    pub data_0: Vec4,
-    pub data_1: Vec4,
-    pub data_2: Vec4,
-    pub data_3: Vec4,
-}
+use gpu_allocator::vulkan as vka;
+use koi_gpu::{ComputePushConstants, FrameData, GpuLayout, PushConstants};
+use spirv_std::glam::{Mat4, Vec2, Vec3, Vec4, Vec4Swizzles};
+#[cfg(feature = "hot-reload")]
+use std::path::Path;
 
-impl ComputePushConstants {
-    #[inline]
-    pub fn data_0(mut self, data_0: Vec4) -> Self {
-        self.data_0 = data_0;
-        self
-    }
-    #[inline]
-    pub fn data_1(mut self, data_1: Vec4) -> Self {
-        self.data_1 = data_1;
-        self
-    }
-    #[inline]
-    pub fn data_2(mut self, data_2: Vec4) -> Self {
-        self.data_2 = data_2;
-        self
-    }
-    pub fn data_3(mut self, data_3: Vec4) -> Self {
-        self.data_3 = data_3;
-        self
-    }
-    pub fn as_buffer(&self) -> [u8; 64] {
-        let data_0_buffer = cast::<[f32; 4], [u8; 16]>(self.data_0.to_array());
-        let data_1_buffer = cast::<[f32; 4], [u8; 16]>(self.data_1.to_array());
-        let data_2_buffer = cast::<[f32; 4], [u8; 16]>(self.data_2.to_array());
-        let data_3_buffer = cast::<[f32; 4], [u8; 16]>(self.data_3.to_array());
-        [data_0_buffer, data_1_buffer, data_2_buffer, data_3_buffer]
-            .as_flattened()
-            .try_into()
-            .unwrap()
-    }
-}
+// NB! checked against the threads() declared by gradient.spv, sky.spv and skybox.spv
+const BACKGROUND_GROUP_SIZE: u32 = 16;
 
 pub struct ComputePipeline {
     pub name: String,
     pub shader: vk::ShaderModule,
     pub handle: vk::Pipeline,
     pub pipeline_layout: vk::PipelineLayout,
+    // NB! bound at set 1; set 0 is always the color image
+    pub descriptor_set: Option<vk::DescriptorSet>,
     pub push_constants: ComputePushConstants,
 }
 
+impl ComputePipeline {
+    #[cfg(feature = "hot-reload")]
+    pub fn reload(
+        &mut self,
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+        code: &[u32],
+    ) -> ash::prelude::VkResult<()> {
+        pipeline::reload_compute_shader(
+            device_handle,
+            pipeline_cache,
+            code,
+            &mut self.shader,
+            &mut [(&mut self.handle, self.pipeline_layout, c"main_cs")],
+        )
+    }
+}
+
 pub struct DrawManager {
     pub buffering: u32,
     pub frames: Vec<Frame>,
+    // host-written data that lives for one frame
+    pub frame_allocator: FrameAllocator,
     pub color_image: Image,
     pub depth_image: Image,
+    pub velocity_image: Image,
     pub color_image_descriptor_set_layout: vk::DescriptorSetLayout,
     pub color_image_descriptor: vk::DescriptorSet,
     pub frame_count: u32,
 
-    pub compute_pipelines: [ComputePipeline; 2],
+    pub compute_pipelines: [ComputePipeline; 3],
     pub compute_pipeline_index: usize,
 
     pub graphics_pipeline_layout: vk::PipelineLayout,
-    pub graphics_pipeline: vk::Pipeline,
+    // one mesh pipeline per material variant drawn so far
+    pub pipeline_states: PipelineStateCache,
+    // draws every variant with lines; only honoured when the device supports it
+    pub wireframe: bool,
+    pub wireframe_supported: bool,
     pub vertex_shader_module: vk::ShaderModule,
     pub fragment_shader_module: vk::ShaderModule,
     pub meshes: Vec<Mesh>,
+    pub gpu_scene: GpuScene,
+    pub hiz: HiZ,
+    pub skinning: Skinning,
+    pub images: Vec<Image>,
+    pub textures: Vec<Texture>,
+    pub texture_table: TextureTable,
+    pub sampler_cache: SamplerCache,
+    pub mip_generator: MipGenerator,
+
+    pub ibl: Ibl,
+
+    pub post_processing: PostProcessing,
+    pub previous_view_projection: Mat4,
+    pub previous_jitter: Vec2,
+    // hours in [0, 24); drives the sun direction of the sky effect
+    pub time_of_day: f32,
 }
 
 impl<'a> DrawManager {
     pub fn new(
         device: &Device,
+        pipeline_cache: vk::PipelineCache,
         resource_allocator: &mut ResourceAllocator,
         descriptor_set_allocator: &mut DescriptorSetAllocator,
         settings: &Settings,
     ) -> Self {
         let frames = Frame::generator(&device, settings.buffering);
+        let frame_allocator = FrameAllocator::new(device, resource_allocator);
         let Resolution { width, height } = settings.resolution;
         let color_image = Image::new(
             &device.handle,
@@ -126,16 +160,40 @@ impl<'a> DrawManager {
             &mut resource_allocator.global_resources,
             vk::Format::D32_SFLOAT,
             vk::Extent3D::default().width(width).height(height).depth(1),
-            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
             vk::ImageAspectFlags::DEPTH,
         );
+        let velocity_image = Image::new(
+            &device.handle,
+            &mut resource_allocator.handle,
+            &mut resource_allocator.global_resources,
+            vk::Format::R16G16_SFLOAT,
+            vk::Extent3D::default().width(width).height(height).depth(1),
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            vk::ImageAspectFlags::COLOR,
+        );
+
+        let gradient_shader = include_bytes!(env!("gradient.spv"));
+        let sky_shader = include_bytes!(env!("sky.spv"));
+        let skybox_shader = include_bytes!(env!("skybox.spv"));
+        let gradient_reflection = ShaderReflection::new(gradient_shader);
+        let sky_reflection = ShaderReflection::new(sky_shader);
+        let skybox_reflection = ShaderReflection::new(skybox_shader);
+        let gradient_entry_point = gradient_reflection.get_entry_point(c"main_cs");
+        let sky_entry_point = sky_reflection.get_entry_point(c"main_cs");
+        let skybox_entry_point = skybox_reflection.get_entry_point(c"main_cs");
+        for entry_point in [gradient_entry_point, sky_entry_point, skybox_entry_point] {
+            entry_point.check_workgroup_size([BACKGROUND_GROUP_SIZE, BACKGROUND_GROUP_SIZE, 1]);
+        }
 
-        let mut descriptor_set_layout_builder =
-            DescriptorSetLayoutBuilder::default().add_binding(0, vk::DescriptorType::STORAGE_IMAGE);
-        let color_image_descriptor_set_layout = descriptor_set_layout_builder
-            .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+        // NB! the background passes share the color image set
+        let color_image_descriptor_set_layout = DescriptorSetLayoutBuilder::from_entry_points(
+            &[gradient_entry_point, sky_entry_point, skybox_entry_point],
+            0,
+        )
+        .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
             &device.handle,
-            vk::ShaderStageFlags::COMPUTE,
+            vk::ShaderStageFlags::empty(),
             None,
             None,
         );
@@ -144,20 +202,15 @@ impl<'a> DrawManager {
             descriptor_set_allocator.allocate(&device.handle, &color_image_descriptor_set_layouts);
         Self::update_sets(&device.handle, color_image.view, color_image_descriptor);
 
-        let gradient_shader = include_bytes!(env!("gradient.spv"));
         let gradient_shader_module =
             pipeline::load_shader_module(&device.handle, gradient_shader, None);
-        let sky_shader = include_bytes!(env!("sky.spv"));
         let sky_shader_module = pipeline::load_shader_module(&device.handle, sky_shader, None);
 
-        let push_constant_ranges = [vk::PushConstantRange::default()
-            .offset(0)
-            .size(size_of::<ComputePushConstants>() as u32)
-            .stage_flags(vk::ShaderStageFlags::COMPUTE)];
-        let compute_pipeline_layout = pipeline::create_pipeline_layout(
+        let compute_pipeline_layout = pipeline::create_reflected_pipeline_layout(
             &device.handle,
+            &[gradient_entry_point, sky_entry_point],
             &color_image_descriptor_set_layouts,
-            Some(&push_constant_ranges),
+            ComputePushConstants::MEMBERS,
         );
 
         let gradient_pipeline = ComputePipeline {
@@ -165,10 +218,12 @@ impl<'a> DrawManager {
             shader: gradient_shader_module,
             handle: pipeline::create_compute_pipeline(
                 &device.handle,
+                pipeline_cache,
                 gradient_shader_module,
                 compute_pipeline_layout,
             ),
             pipeline_layout: compute_pipeline_layout,
+            descriptor_set: None,
             push_constants: ComputePushConstants::default()
                 .data_0(Vec4::new(0.14, 0.44, 0.86, 1.0))
                 .data_1(Vec4::new(0.5, 0.54, 0.38, 1.0)),
@@ -179,79 +234,397 @@ impl<'a> DrawManager {
             shader: sky_shader_module,
             handle: pipeline::create_compute_pipeline(
                 &device.handle,
+                pipeline_cache,
                 sky_shader_module,
                 compute_pipeline_layout,
             ),
             pipeline_layout: compute_pipeline_layout,
-            push_constants: ComputePushConstants::default()
-                .data_0(Vec4::new(0.14, 0.17, 0.36, 1.0))
-                .data_1(Vec4::new(0.0, 0.0, 0.0, 0.98)),
+            descriptor_set: None,
+            // data_0..data_3: inverse view-projection and data_4.xyz: sun direction, written every frame
+            push_constants: ComputePushConstants::default().data_4(Vec4::new(0.0, 1.0, 0.0, 0.998)),
         };
 
-        let vertex_shader = include_bytes!("../../../../../shaders/glsl/vertex.spv");
+        let vertex_shader = include_bytes!(env!("vertex.spv"));
         let fragment_shader = include_bytes!(env!("fragment.spv"));
         let vertex_shader_module =
             pipeline::load_shader_module(&device.handle, vertex_shader, None);
         let fragment_shader_module =
             pipeline::load_shader_module(&device.handle, fragment_shader, None);
+        let vertex_reflection = ShaderReflection::new(vertex_shader);
+        let fragment_reflection = ShaderReflection::new(fragment_shader);
+        let graphics_entry_points = [
+            vertex_reflection.get_entry_point(c"main_vs"),
+            fragment_reflection.get_entry_point(c"main_fs"),
+            fragment_reflection.get_entry_point(c"main_masked_fs"),
+        ];
+
+        // NB! the mesh and skybox set layouts are reflected from the shaders that bind them
+        let mut sampler_cache = SamplerCache::new(device.get_max_sampler_anisotropy());
+        let ibl = Ibl::new(
+            &device.handle,
+            pipeline_cache,
+            resource_allocator,
+            descriptor_set_allocator,
+            &mut sampler_cache,
+            &graphics_entry_points,
+            skybox_entry_point,
+        );
+
+        let skybox_shader_module =
+            pipeline::load_shader_module(&device.handle, skybox_shader, None);
+        let skybox_pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            &device.handle,
+            &[skybox_entry_point],
+            &[
+                color_image_descriptor_set_layout,
+                ibl.skybox_descriptor_set_layout,
+            ],
+            ComputePushConstants::MEMBERS,
+        );
+        // data_0..data_3: inverse view-projection, written every frame
+        let skybox_pipeline = ComputePipeline {
+            name: String::from("skybox"),
+            shader: skybox_shader_module,
+            handle: pipeline::create_compute_pipeline(
+                &device.handle,
+                pipeline_cache,
+                skybox_shader_module,
+                skybox_pipeline_layout,
+            ),
+            pipeline_layout: skybox_pipeline_layout,
+            descriptor_set: Some(ibl.skybox_descriptor_set),
+            push_constants: ComputePushConstants::default(),
+        };
+
+        let hiz = HiZ::new(
+            &device.handle,
+            pipeline_cache,
+            resource_allocator,
+            &depth_image,
+        );
+        let gpu_scene = GpuScene::new(
+            &device.handle,
+            pipeline_cache,
+            resource_allocator,
+            &hiz,
+            settings.buffering,
+            &graphics_entry_points,
+        );
+        let texture_table = TextureTable::new(&device.handle, &graphics_entry_points);
+        let graphics_pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            &device.handle,
+            &graphics_entry_points,
+            &[
+                ibl.lighting_descriptor_set_layout,
+                gpu_scene.instance_descriptor_set_layout,
+                texture_table.descriptor_set_layout,
+            ],
+            PushConstants::MEMBERS,
+        );
+        let pipeline_states = PipelineStateCache::new(graphics_pipeline_layout, pipeline_cache);
 
-        let push_constant_ranges = [vk::PushConstantRange::default()
-            .offset(0)
-            .size(PUSH_CONSTANTS_SIZE as u32)
-            .stage_flags(vk::ShaderStageFlags::VERTEX)];
-        let graphics_pipeline_layout =
-            pipeline::create_pipeline_layout(&device.handle, &[], Some(&push_constant_ranges));
-        let graphics_pipeline = pipeline::PipelineBuilder::default()
-            .pipeline_layout(graphics_pipeline_layout)
-            .shaders(vertex_shader_module, Some(fragment_shader_module))
-            .input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-            .polygon_mode(vk::PolygonMode::FILL)
-            .cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
-            .multisampling()
-            .blending_alpha_blend()
-            .depth_stencil_state(true, vk::CompareOp::GREATER_OR_EQUAL)
-            .color_attachment_formats(&[color_image.format])
-            .depth_attachment_format(depth_image.format)
-            .build(&device.handle);
+        let post_processing = PostProcessing::new(
+            &device.handle,
+            pipeline_cache,
+            resource_allocator,
+            descriptor_set_allocator,
+            &color_image,
+            &velocity_image,
+        );
 
         Self {
             buffering: settings.buffering,
             frames,
+            frame_allocator,
             color_image,
             depth_image,
+            velocity_image,
             color_image_descriptor_set_layout,
             color_image_descriptor,
             frame_count: 0,
 
-            compute_pipelines: [sky_pipeline, gradient_pipeline],
+            compute_pipelines: [sky_pipeline, gradient_pipeline, skybox_pipeline],
             compute_pipeline_index: 0,
 
-            graphics_pipeline,
             graphics_pipeline_layout,
+            pipeline_states,
+            wireframe: false,
+            wireframe_supported: device.fill_mode_non_solid,
             vertex_shader_module,
             fragment_shader_module,
             meshes: vec![],
+            gpu_scene,
+            hiz,
+            skinning: Skinning::new(&device.handle, pipeline_cache, settings.buffering),
+            images: vec![],
+            textures: vec![],
+            texture_table,
+            sampler_cache,
+            mip_generator: MipGenerator::new(&device.handle, pipeline_cache),
+
+            ibl,
+
+            post_processing,
+            previous_view_projection: Mat4::IDENTITY,
+            previous_jitter: Vec2::ZERO,
+            time_of_day: 15.0,
         }
     }
 
     pub fn load_scene(
         &mut self,
-        device_handle: &DeviceHandle,
+        device: &Device,
         resource_allocator: &mut ResourceAllocator,
         immediate_manager: &mut ImmediateManager,
         scene: &Scene,
     ) {
+        let first_mesh = self.meshes.len();
+        let first_texture = self.textures.len();
         for mesh in &scene.meshes {
             self.meshes.push(Mesh::new(
-                device_handle,
+                &device.handle,
                 &mut resource_allocator.handle,
                 &mut resource_allocator.global_resources,
                 immediate_manager,
                 &mesh.indices,
                 &mesh.vertices,
+                mesh.vertex_format,
+                &mesh.morph_targets,
                 mesh.surfaces.clone(),
             ));
         }
+        let skinned_addresses = self.skinning.load(
+            &device.handle,
+            resource_allocator,
+            immediate_manager,
+            scene,
+            &self.meshes[first_mesh..],
+        );
+        self.gpu_scene.load(
+            &device.handle,
+            resource_allocator,
+            immediate_manager,
+            scene,
+            &self.meshes[first_mesh..],
+            first_texture as u32,
+            &skinned_addresses,
+        );
+
+        for image in &scene.images {
+            let extent = vk::Extent2D::default()
+                .width(image.width)
+                .height(image.height);
+            let format = texture::get_format(image.role);
+
+            self.images.push(match &image.data {
+                ImageData::Rgba8(pixels) => texture::upload(
+                    device,
+                    resource_allocator,
+                    immediate_manager,
+                    &mut self.mip_generator,
+                    pixels,
+                    extent,
+                    format,
+                ),
+                ImageData::Compressed(compressed_image) => {
+                    let compressed_format = texture::get_compressed_format(compressed_image);
+                    match texture::supports_compressed_format(device, compressed_format) {
+                        true => texture::upload_compressed(
+                            device,
+                            resource_allocator,
+                            immediate_manager,
+                            compressed_image,
+                            compressed_format,
+                        ),
+                        false => texture::upload(
+                            device,
+                            resource_allocator,
+                            immediate_manager,
+                            &mut self.mip_generator,
+                            &compressed_image.decode_rgba8(),
+                            extent,
+                            format,
+                        ),
+                    }
+                }
+            });
+        }
+
+        for scene_texture in &scene.textures {
+            self.textures.push(Texture {
+                image: scene_texture.image,
+                sampler: self.sampler_cache.get(
+                    &device.handle,
+                    texture::get_sampler_key(&scene_texture.sampler),
+                ),
+            });
+        }
+        self.texture_table
+            .update(&device.handle, &self.images, &self.textures, first_texture);
+    }
+
+    pub fn load_environment(
+        &mut self,
+        device: &Device,
+        resource_allocator: &mut ResourceAllocator,
+        immediate_manager: &mut ImmediateManager,
+        environment: &Environment,
+    ) {
+        self.ibl.load(
+            &device.handle,
+            resource_allocator,
+            immediate_manager,
+            environment,
+        );
+    }
+
+    fn get_pipeline_state_key(&self, variant: MaterialVariant) -> PipelineStateKey {
+        let blended = variant.alpha_mode == AlphaMode::Blend;
+        PipelineStateKey::new(
+            self.vertex_shader_module,
+            self.fragment_shader_module,
+            &[self.color_image.format, self.velocity_image.format],
+            self.depth_image.format,
+        )
+        // NB! only masked surfaces discard, so the others keep early depth testing
+        .fragment_entry_point(match variant.alpha_mode {
+            AlphaMode::Mask => c"main_masked_fs",
+            _ => c"main_fs",
+        })
+        .blend_mode(match blended {
+            true => BlendMode::Alpha,
+            false => BlendMode::Disabled,
+        })
+        .cull_mode(
+            match variant.double_sided {
+                true => vk::CullModeFlags::NONE,
+                false => vk::CullModeFlags::BACK,
+            },
+            vk::FrontFace::COUNTER_CLOCKWISE,
+        )
+        .polygon_mode(match self.wireframe && self.wireframe_supported {
+            true => vk::PolygonMode::LINE,
+            false => vk::PolygonMode::FILL,
+        })
+        // NB! blended surfaces are tested against, but don't occlude, what's drawn after them
+        .depth(!blended, vk::CompareOp::GREATER_OR_EQUAL)
+    }
+
+    // Rebuilds the pipelines using the named shader from freshly compiled code; names match the
+    // build.rs outputs without their extension. Expects the device to be idle.
+    #[cfg(feature = "hot-reload")]
+    pub fn reload_shader(
+        &mut self,
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+        name: &str,
+        code: &[u32],
+    ) -> Result<(), String> {
+        let result = match name {
+            "gradient" | "sky" | "skybox" => self
+                .compute_pipelines
+                .iter_mut()
+                .find(|compute_pipeline| compute_pipeline.name == name)
+                .map_or(Ok(()), |compute_pipeline| {
+                    compute_pipeline.reload(device_handle, pipeline_cache, code)
+                }),
+            "taa" => self
+                .post_processing
+                .taa_pipeline
+                .reload(device_handle, pipeline_cache, code),
+            "fxaa" => {
+                self.post_processing
+                    .fxaa_pipeline
+                    .reload(device_handle, pipeline_cache, code)
+            }
+            "hiz" => pipeline::reload_compute_shader(
+                device_handle,
+                pipeline_cache,
+                code,
+                &mut self.hiz.shader,
+                &mut [(&mut self.hiz.pipeline, self.hiz.pipeline_layout, c"main_cs")],
+            ),
+            "cull" => {
+                let gpu_scene = &mut self.gpu_scene;
+                pipeline::reload_compute_shader(
+                    device_handle,
+                    pipeline_cache,
+                    code,
+                    &mut gpu_scene.cull_shader,
+                    &mut [(
+                        &mut gpu_scene.cull_pipeline,
+                        gpu_scene.cull_pipeline_layout,
+                        c"main_cs",
+                    )],
+                )
+            }
+            "skinning" => {
+                let skinning = &mut self.skinning;
+                pipeline::reload_compute_shader(
+                    device_handle,
+                    pipeline_cache,
+                    code,
+                    &mut skinning.shader,
+                    &mut [(&mut skinning.pipeline, skinning.pipeline_layout, c"main_cs")],
+                )
+            }
+            "mipmap" => {
+                let mip_generator = &mut self.mip_generator;
+                pipeline::reload_compute_shader(
+                    device_handle,
+                    pipeline_cache,
+                    code,
+                    &mut mip_generator.shader,
+                    &mut [(
+                        &mut mip_generator.pipeline,
+                        mip_generator.pipeline_layout,
+                        c"main_cs",
+                    )],
+                )
+            }
+            // NB! only affects environments baked from now on
+            "ibl" => {
+                let ibl = &mut self.ibl;
+                let layout = ibl.bake_pipeline_layout;
+                pipeline::reload_compute_shader(
+                    device_handle,
+                    pipeline_cache,
+                    code,
+                    &mut ibl.shader,
+                    &mut [
+                        (&mut ibl.equirect_pipeline, layout, c"equirect_cs"),
+                        (&mut ibl.irradiance_pipeline, layout, c"irradiance_cs"),
+                        (&mut ibl.prefilter_pipeline, layout, c"prefilter_cs"),
+                        (&mut ibl.brdf_lut_pipeline, layout, c"brdf_lut_cs"),
+                    ],
+                )
+            }
+            "vertex" | "fragment" => self.reload_graphics_shader(device_handle, name, code),
+            _ => return Err(format!("{name} can't be hot-reloaded; restart to apply it")),
+        };
+        result.map_err(|result| format!("failed to rebuild {name} pipelines: {result}"))
+    }
+
+    #[cfg(feature = "hot-reload")]
+    fn reload_graphics_shader(
+        &mut self,
+        device_handle: &DeviceHandle,
+        name: &str,
+        code: &[u32],
+    ) -> ash::prelude::VkResult<()> {
+        let create_info = vk::ShaderModuleCreateInfo::default().code(code);
+        let shader_module = unsafe { device_handle.create_shader_module(&create_info, None)? };
+        let replaced_shader_module = match name {
+            "vertex" => &mut self.vertex_shader_module,
+            _ => &mut self.fragment_shader_module,
+        };
+        self.pipeline_states
+            .replace_shader(device_handle, *replaced_shader_module, shader_module)
+            .inspect_err(|_| unsafe { device_handle.destroy_shader_module(shader_module, None) })?;
+
+        unsafe { device_handle.destroy_shader_module(*replaced_shader_module, None) };
+        *replaced_shader_module = shader_module;
+        Ok(())
     }
 
     fn update_sets(
@@ -288,11 +661,44 @@ impl<'a> DrawManager {
         self.frame_count += 1;
     }
 
+    // Fixed camera; returns the unjittered view and projection matrices.
+    fn get_view_projection(&self) -> (Mat4, Mat4) {
+        let aspect_ratio =
+            self.color_image.extent_2d.width as f32 / self.color_image.extent_2d.height as f32;
+        let mut view = Mat4::IDENTITY;
+        *view.col_mut(3) = Vec4::new(0.0, 0.0, -5.0, 1.0);
+        let projection = Mat4::perspective_rh(70.0, aspect_ratio, 10000.0, 0.1);
+        (view, projection)
+    }
+
+    // Sun rises in +X at 06:00, peaks at 12:00 and sets in -X at 18:00; tilted slightly south.
+    pub fn get_sun_direction(&self) -> Vec3 {
+        let angle = (self.time_of_day - 6.0) / 12.0 * std::f32::consts::PI;
+        Vec3::new(angle.cos(), angle.sin(), 0.3).normalize()
+    }
+
     pub fn draw_compute(
         &mut self,
         device_handle: &DeviceHandle,
         command_buffer: vk::CommandBuffer,
     ) {
+        let (view, projection) = self.get_view_projection();
+        let inverse_view_projection = (projection * view).inverse();
+        let sun_direction = self.get_sun_direction();
+
+        let compute_pipeline = &mut self.compute_pipelines[self.compute_pipeline_index];
+        let push_constants = &mut compute_pipeline.push_constants;
+        if compute_pipeline.name == "sky" || compute_pipeline.name == "skybox" {
+            push_constants.data_0 = inverse_view_projection.col(0);
+            push_constants.data_1 = inverse_view_projection.col(1);
+            push_constants.data_2 = inverse_view_projection.col(2);
+            push_constants.data_3 = inverse_view_projection.col(3);
+        }
+        if compute_pipeline.name == "sky" {
+            push_constants.data_4 = Vec4::from((sun_direction, push_constants.data_4.w));
+        }
+
+        let extent = self.color_image.extent_2d;
         unsafe {
             let compute_pipeline = &self.compute_pipelines[self.compute_pipeline_index];
             device_handle.cmd_bind_pipeline(
@@ -308,17 +714,27 @@ impl<'a> DrawManager {
                 &[self.color_image_descriptor],
                 &[],
             );
-            device_handle.cmd_push_constants(
+            if let Some(descriptor_set) = compute_pipeline.descriptor_set {
+                device_handle.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    compute_pipeline.pipeline_layout,
+                    1,
+                    &[descriptor_set],
+                    &[],
+                );
+            }
+            pipeline::push_constants(
+                device_handle,
                 command_buffer,
                 compute_pipeline.pipeline_layout,
                 vk::ShaderStageFlags::COMPUTE,
-                0,
-                &compute_pipeline.push_constants.as_buffer(),
+                &compute_pipeline.push_constants,
             );
             device_handle.cmd_dispatch(
                 command_buffer,
-                (self.color_image.extent_2d.width as f32 / 16.0).ceil() as u32,
-                (self.color_image.extent_2d.height as f32 / 16.0).ceil() as u32,
+                extent.width.div_ceil(BACKGROUND_GROUP_SIZE),
+                extent.height.div_ceil(BACKGROUND_GROUP_SIZE),
                 1,
             );
         };
@@ -327,13 +743,21 @@ impl<'a> DrawManager {
     pub fn draw_graphics(
         &mut self,
         device_handle: &DeviceHandle,
+        resource_allocator: &mut ResourceAllocator,
         command_buffer: vk::CommandBuffer,
     ) {
-        let color_attachments = [pipeline::get_attachment_info(
-            self.color_image.view,
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            None,
-        )];
+        let color_attachments = [
+            pipeline::get_attachment_info(
+                self.color_image.view,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                None,
+            ),
+            pipeline::get_attachment_info(
+                self.velocity_image.view,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                Some(vk::ClearValue::default()),
+            ),
+        ];
         let mut depth_clear_value = vk::ClearValue::default();
         depth_clear_value.depth_stencil = vk::ClearDepthStencilValue::default().depth(0.0);
         let depth_attachment = pipeline::get_attachment_info(
@@ -347,42 +771,90 @@ impl<'a> DrawManager {
             Some(&depth_attachment),
         );
 
-        unsafe {
-            device_handle.cmd_begin_rendering(command_buffer, &rendering_info);
-            device_handle.cmd_bind_pipeline(
+        let (view, projection) = self.get_view_projection();
+        let frame_index = self.get_current_frame_index();
+        self.gpu_scene
+            .upload_transforms(device_handle, command_buffer, frame_index);
+        self.skinning
+            .dispatch(device_handle, command_buffer, frame_index);
+        let cull_view = self.gpu_scene.get_cull_view(
+            view,
+            projection,
+            self.color_image.extent_2d.height as f32,
+        );
+        if self.gpu_scene.gpu_driven {
+            self.gpu_scene.cull(
+                device_handle,
                 command_buffer,
-                vk::PipelineBindPoint::GRAPHICS,
-                self.graphics_pipeline,
-            )
-        };
+                &cull_view,
+                &self.hiz,
+                frame_index,
+            );
+        } else {
+            self.gpu_scene.cull_cpu(&cull_view);
+        }
 
-        let aspect_ratio =
-            self.color_image.extent_2d.width as f32 / self.color_image.extent_2d.height as f32;
-        let mut view = Mat4::IDENTITY;
-        *view.col_mut(3) = Vec4::new(0.0, 0.0, -5.0, 1.0);
-        let projection = Mat4::perspective_rh(70.0, aspect_ratio, 10000.0, 0.1);
-        let world_transform = projection * view;
+        // NB! built on first use; a variant appearing mid-session stalls that frame
+        let pipeline_state_keys: Vec<PipelineStateKey> = self
+            .gpu_scene
+            .draw_groups
+            .iter()
+            .map(|draw_group| self.get_pipeline_state_key(draw_group.variant))
+            .collect();
+        let pipelines: Vec<vk::Pipeline> = pipeline_state_keys
+            .iter()
+            .map(|key| self.pipeline_states.get(device_handle, key))
+            .collect();
 
-        let test_mesh = &self.meshes[2];
         unsafe {
-            device_handle.cmd_push_constants(
+            device_handle.cmd_begin_rendering(command_buffer, &rendering_info);
+            device_handle.cmd_bind_descriptor_sets(
                 command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
                 self.graphics_pipeline_layout,
-                vk::ShaderStageFlags::VERTEX,
                 0,
-                &PushConstants::default()
-                    .vertex_buffer_address(test_mesh.vertex_buffer_address)
-                    .world_transform(world_transform)
-                    .as_buffer(),
+                &[self.ibl.lighting_descriptor_set],
+                &[],
             );
-            device_handle.cmd_bind_index_buffer(
+            device_handle.cmd_bind_descriptor_sets(
                 command_buffer,
-                test_mesh.index_buffer.handle,
-                0,
-                vk::IndexType::UINT32,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.graphics_pipeline_layout,
+                2,
+                &[self.texture_table.descriptor_set],
+                &[],
             );
         };
 
+        let camera_position = view.inverse().col(3).xyz();
+        let jitter = self
+            .post_processing
+            .get_jitter(self.frame_count, self.color_image.extent_2d);
+        let jittered_projection =
+            Mat4::from_translation(Vec3::new(jitter.x, jitter.y, 0.0)) * projection;
+        let view_projection = jittered_projection * view;
+
+        let frame_data = [FrameData::default()
+            .view_projection(view_projection)
+            .previous_view_projection(self.previous_view_projection)
+            .jitter(jitter, self.previous_jitter)
+            .camera_position(camera_position)
+            .environment(self.ibl.intensity, (ibl::PREFILTERED_MIP_LEVELS - 1) as f32)];
+        let frame_data = self.frame_allocator.allocate(
+            device_handle,
+            resource_allocator,
+            frame_index,
+            &frame_data,
+        );
+        let push_constants = PushConstants::default().frame_data_address(frame_data.device_address);
+        pipeline::push_constants(
+            device_handle,
+            command_buffer,
+            self.graphics_pipeline_layout,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            &push_constants,
+        );
+
         let image_extent_height = self.color_image.extent_2d.height as f32;
         let viewports = [vk::Viewport::default()
             .x(0.0)
@@ -404,24 +876,58 @@ impl<'a> DrawManager {
 
         unsafe {
             device_handle.cmd_set_scissor(command_buffer, 0, &scissors);
-            device_handle.cmd_draw_indexed(
+        }
+        self.gpu_scene.draw(
+            device_handle,
+            command_buffer,
+            self.graphics_pipeline_layout,
+            &pipelines,
+        );
+        unsafe { device_handle.cmd_end_rendering(command_buffer) };
+
+        // consumed by next frame's cull pass; a pyramid skipped for a frame is stale, not reused
+        match self.gpu_scene.gpu_driven && self.gpu_scene.occlusion_culling {
+            true => self.hiz.build(
+                device_handle,
                 command_buffer,
-                test_mesh.surfaces[0].count,
-                1,
-                test_mesh.surfaces[0].start_index,
-                0,
-                0,
-            );
-            device_handle.cmd_end_rendering(command_buffer);
+                &self.depth_image,
+                projection * view,
+            ),
+            false => self.hiz.valid = false,
         }
+
+        self.previous_view_projection = view_projection;
+        self.previous_jitter = jitter;
     }
 
-    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+    pub fn draw_post_processing(
+        &mut self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+    ) -> vk::Image {
+        self.post_processing.draw(
+            device_handle,
+            command_buffer,
+            &self.color_image,
+            &self.velocity_image,
+        )
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle, allocator: &mut vka::Allocator) {
+        self.frame_allocator.drop(device_handle, allocator);
+        self.post_processing.drop(device_handle);
+        self.gpu_scene.drop(device_handle, allocator);
+        self.hiz.drop(device_handle);
+        self.skinning.drop(device_handle, allocator);
+        self.ibl.drop(device_handle);
+        self.mip_generator.drop(device_handle);
+        self.texture_table.drop(device_handle);
+        self.sampler_cache.drop(device_handle);
+        self.pipeline_states.drop(device_handle);
         unsafe {
             device_handle.destroy_shader_module(self.fragment_shader_module, None);
             device_handle.destroy_shader_module(self.vertex_shader_module, None);
             device_handle.destroy_pipeline_layout(self.graphics_pipeline_layout, None);
-            device_handle.destroy_pipeline(self.graphics_pipeline, None);
             self.compute_pipelines.iter().for_each(|effect| {
                 device_handle.destroy_shader_module(effect.shader, None);
                 device_handle.destroy_pipeline_layout(effect.pipeline_layout, None);
@@ -551,6 +1057,7 @@ pub struct Renderer {
     pub instance: Instance,
     pub surface: Surface,
     pub device: Device,
+    pub pipeline_cache: PipelineCache,
     pub swapchain: Swapchain,
     pub surface_support: SurfaceSupport,
     pub graphics_queue: vk::Queue,
@@ -560,9 +1067,41 @@ pub struct Renderer {
 
     pub draw_manager: DrawManager,
     pub immediate_manager: ImmediateManager,
+    #[cfg(feature = "hot-reload")]
+    pub shader_watcher: ShaderWatcher,
 }
 
 impl Renderer {
+    // Swaps in the shaders the watcher finished compiling, between frames.
+    #[cfg(feature = "hot-reload")]
+    fn reload_shaders(&mut self) {
+        let shaders = self.shader_watcher.poll();
+        if shaders.is_empty() {
+            return;
+        }
+
+        // NB! in-flight frames may still be using the pipelines being replaced
+        unsafe {
+            self.device
+                .handle
+                .device_wait_idle()
+                .expect("koi::ren::vk - failed to Wait for Device Idle")
+        };
+        for (name, code) in shaders {
+            match self.draw_manager.reload_shader(
+                &self.device.handle,
+                self.pipeline_cache.handle,
+                &name,
+                &code,
+            ) {
+                Ok(()) => log::info!("koi::ren::vk - reloaded {name} shader"),
+                Err(error) => {
+                    self.shader_watcher.errors.insert(name, error);
+                }
+            }
+        }
+    }
+
     fn draw_imgui(
         &mut self,
         imgui: &mut ImGui,
@@ -609,21 +1148,31 @@ impl RendererTrait for Renderer {
             device.get_min_memory_map_alignment(),
         );
 
-        let pool_sizes = vec![DescriptorSetPoolSizeRatio::new(
-            vk::DescriptorType::STORAGE_IMAGE,
-            1.0,
-        )];
+        let pool_sizes = vec![
+            DescriptorSetPoolSizeRatio::new(vk::DescriptorType::STORAGE_IMAGE, 4.0),
+            DescriptorSetPoolSizeRatio::new(vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+            DescriptorSetPoolSizeRatio::new(vk::DescriptorType::SAMPLER, 1.0),
+        ];
         let mut descriptor_set_allocator =
             DescriptorSetAllocator::new(&device.handle, 10, &pool_sizes);
         let graphics_queue = device.get_queue(QueueFamilyType::Graphics);
 
-        let immediate_manager = ImmediateManager::new(&device, graphics_queue);
-        let draw_manager = DrawManager::new(
+        let pipeline_cache = PipelineCache::new(&device, settings.pipeline_cache_path.clone());
+        let mut immediate_manager = ImmediateManager::new(&device, graphics_queue);
+        let mut draw_manager = DrawManager::new(
             &device,
+            pipeline_cache.handle,
             &mut resource_allocator,
             &mut descriptor_set_allocator,
             &settings,
         );
+        // NB! IBL images must hold valid data before the first frame; a flat grey environment
+        draw_manager.load_environment(
+            &device,
+            &mut resource_allocator,
+            &mut immediate_manager,
+            &Environment::default(),
+        );
 
         Self {
             settings,
@@ -633,6 +1182,7 @@ impl RendererTrait for Renderer {
             instance,
             surface,
             device,
+            pipeline_cache,
             swapchain,
             surface_support,
             graphics_queue,
@@ -642,18 +1192,51 @@ impl RendererTrait for Renderer {
 
             draw_manager,
             immediate_manager,
+            #[cfg(feature = "hot-reload")]
+            shader_watcher: ShaderWatcher::new(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("../shaders"),
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("gpu/src"),
+            ),
         }
     }
 
     fn load_scene(&mut self, scene: &Scene) {
+        // NB! scene buffers and descriptors are replaced; in-flight frames may still be using them
+        unsafe {
+            self.device
+                .handle
+                .device_wait_idle()
+                .expect("koi::ren::vk - failed to Wait for Device Idle")
+        };
         self.draw_manager.load_scene(
-            &self.device.handle,
+            &self.device,
             &mut self.resource_allocator,
             &mut self.immediate_manager,
             scene,
         );
     }
 
+    fn load_environment(&mut self, environment: &Environment) {
+        // NB! IBL images are rebaked in place; in-flight frames may still be sampling them
+        unsafe {
+            self.device
+                .handle
+                .device_wait_idle()
+                .expect("koi::ren::vk - failed to Wait for Device Idle")
+        };
+        self.draw_manager.load_environment(
+            &self.device,
+            &mut self.resource_allocator,
+            &mut self.immediate_manager,
+            environment,
+        );
+    }
+
+    fn update_scene(&mut self, scene: &Scene) {
+        self.draw_manager.gpu_scene.update_transforms(scene);
+        self.draw_manager.skinning.update(scene);
+    }
+
     fn handle_resize(&mut self, resolution: &Resolution) {
         self.swapchain.resize(
             &self.instance,
@@ -667,6 +1250,9 @@ impl RendererTrait for Renderer {
     fn draw(&mut self, imgui: &mut ImGui) {
         const SECOND_IN_NS: u64 = 10e9 as u64;
 
+        #[cfg(feature = "hot-reload")]
+        self.reload_shaders();
+
         let device_handle: ash::Device = self.device.handle.clone();
 
         // clone frame data handles
@@ -697,6 +1283,10 @@ impl RendererTrait for Renderer {
         let frame_index = self.draw_manager.get_current_frame_index();
         self.resource_allocator
             .drop_frame_resources(&device_handle, frame_index);
+        self.draw_manager.frame_allocator.reset(frame_index);
+        if self.draw_manager.gpu_scene.gpu_driven {
+            self.draw_manager.gpu_scene.read_statistics(frame_index);
+        }
 
         // request swapchain image
         let mut swapchain_image_index = 0;
@@ -755,7 +1345,7 @@ impl RendererTrait for Renderer {
             vk::ImageLayout::GENERAL,
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         );
-        // transition depth image for graphics pipeline
+        // transition depth and velocity images for graphics pipeline
         image::transition(
             &device_handle,
             command_buffer,
@@ -763,18 +1353,26 @@ impl RendererTrait for Renderer {
             vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
         );
-
-        self.draw_manager
-            .draw_graphics(&device_handle, command_buffer);
-
-        // transition draw image for copy src and swaphain for copy dst; perform ccopy
         image::transition(
             &device_handle,
             command_buffer,
-            color_image,
+            self.draw_manager.velocity_image.handle.clone(),
+            vk::ImageLayout::UNDEFINED,
             vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
-            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
         );
+
+        self.draw_manager.draw_graphics(
+            &device_handle,
+            &mut self.resource_allocator,
+            command_buffer,
+        );
+
+        // resolve anti-aliasing; output image is left in transfer src layout
+        let output_image = self
+            .draw_manager
+            .draw_post_processing(&device_handle, command_buffer);
+
+        // transition swaphain for copy dst; perform copy
         image::transition(
             &device_handle,
             command_buffer,
@@ -785,7 +1383,7 @@ impl RendererTrait for Renderer {
         image::copy(
             &device_handle,
             command_buffer,
-            color_image,
+            output_image,
             swapchain_image,
             self.draw_manager.color_image.extent_2d,
             self.swapchain.extent,
@@ -876,7 +1474,9 @@ impl Drop for Renderer {
                 .expect("koi::ren::vk - failed to Wait for Device Idle")
         };
         // self.immediate_manager.drop(&self.device.handle);
-        self.draw_manager.drop(&self.device.handle);
+        self.draw_manager
+            .drop(&self.device.handle, &mut self.resource_allocator.handle);
+        self.pipeline_cache.drop(&self.device.handle);
         self.descriptor_set_allocator.drop(&self.device.handle);
         self.resource_allocator.drop(&self.device.handle);
         self.swapchain.drop(&self.device.handle);
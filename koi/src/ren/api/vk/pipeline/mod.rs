@@ -1,4 +1,10 @@
-use ash::{Device as DeviceHandle, vk};
+use super::reflect::{self, EntryPoint};
+
+use ash::{Device as DeviceHandle, prelude::VkResult, vk};
+use bytemuck::{Pod, bytes_of};
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::io::Cursor;
 
 #[derive(Default)]
 pub struct PipelineBuilder<'a> {
@@ -49,6 +55,14 @@ impl<'a> PipelineBuilder<'a> {
         self
     }
 
+    // Expects shaders to be set; they default to main_fs.
+    pub fn fragment_entry_point(mut self, name: &'a CStr) -> Self {
+        if let Some(stage) = self.shader_stages.get_mut(1) {
+            *stage = stage.name(name);
+        }
+        self
+    }
+
     pub fn input_topology(mut self, topology: vk::PrimitiveTopology) -> Self {
         self.input_assembly_state = self
             .input_assembly_state
@@ -151,12 +165,33 @@ impl<'a> PipelineBuilder<'a> {
         self
     }
 
-    pub fn build(&mut self, device_handle: &DeviceHandle) -> vk::Pipeline {
+    pub fn build(
+        &mut self,
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+    ) -> vk::Pipeline {
+        self.try_build(device_handle, pipeline_cache)
+            .expect("koi::ren::vk::pipeline - failed to Create Graphics Pipelines")
+    }
+
+    pub fn try_build(
+        &mut self,
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+    ) -> VkResult<vk::Pipeline> {
         let viewport_state = vk::PipelineViewportStateCreateInfo::default()
             .viewport_count(1)
             .scissor_count(1);
 
-        let color_blend_attachments = [self.color_blend_attachment];
+        // NB! only the first attachment blends; secondary targets (e.g. velocity) are written as-is
+        let color_blend_attachments: Vec<_> = (0..self.color_attachment_formats.len().max(1))
+            .map(|index| match index {
+                0 => self.color_blend_attachment,
+                _ => vk::PipelineColorBlendAttachmentState::default()
+                    .color_write_mask(vk::ColorComponentFlags::RGBA)
+                    .blend_enable(false),
+            })
+            .collect();
         let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
             .logic_op_enable(false)
             .logic_op(vk::LogicOp::COPY)
@@ -183,20 +218,201 @@ impl<'a> PipelineBuilder<'a> {
 
         unsafe {
             device_handle
-                .create_graphics_pipelines(vk::PipelineCache::null(), &create_infos, None)
-                .expect("koi::ren::vk::pipeline - failed to Create Graphics Pipelines")[0]
+                .create_graphics_pipelines(pipeline_cache, &create_infos, None)
+                .map(|pipelines| pipelines[0])
+                .map_err(|(_, result)| result)
         }
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum BlendMode {
+    Disabled,
+    Alpha,
+}
+
+// Everything a graphics pipeline is built from but its layout, which the owning cache fixes.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct PipelineStateKey {
+    pub vertex_shader: vk::ShaderModule,
+    pub fragment_shader: vk::ShaderModule,
+    pub fragment_entry_point: &'static CStr,
+    pub blend_mode: BlendMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub polygon_mode: vk::PolygonMode,
+    pub depth_write_enable: bool,
+    pub depth_compare_op: vk::CompareOp,
+    pub color_attachment_formats: Vec<vk::Format>,
+    pub depth_attachment_format: vk::Format,
+}
+
+impl PipelineStateKey {
+    // Opaque, back-face culled triangle lists, depth tested and written with reverse-Z.
+    pub fn new(
+        vertex_shader: vk::ShaderModule,
+        fragment_shader: vk::ShaderModule,
+        color_attachment_formats: &[vk::Format],
+        depth_attachment_format: vk::Format,
+    ) -> Self {
+        Self {
+            vertex_shader,
+            fragment_shader,
+            fragment_entry_point: c"main_fs",
+            blend_mode: BlendMode::Disabled,
+            cull_mode: vk::CullModeFlags::BACK,
+            // NB! the negative viewport height keeps glTF's counter-clockwise front faces
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            polygon_mode: vk::PolygonMode::FILL,
+            depth_write_enable: true,
+            depth_compare_op: vk::CompareOp::GREATER_OR_EQUAL,
+            color_attachment_formats: color_attachment_formats.to_owned(),
+            depth_attachment_format,
+        }
+    }
+
+    // Lets variants of one fragment shader module differ by entry point.
+    pub fn fragment_entry_point(mut self, name: &'static CStr) -> Self {
+        self.fragment_entry_point = name;
+        self
+    }
+
+    pub fn blend_mode(mut self, blend_mode: BlendMode) -> Self {
+        self.blend_mode = blend_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags, front_face: vk::FrontFace) -> Self {
+        self.cull_mode = cull_mode;
+        self.front_face = front_face;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn depth(mut self, depth_write_enable: bool, depth_compare_op: vk::CompareOp) -> Self {
+        self.depth_write_enable = depth_write_enable;
+        self.depth_compare_op = depth_compare_op;
+        self
+    }
+
+    pub fn get_builder(&self, pipeline_layout: vk::PipelineLayout) -> PipelineBuilder<'_> {
+        let builder = PipelineBuilder::default()
+            .pipeline_layout(pipeline_layout)
+            .shaders(self.vertex_shader, Some(self.fragment_shader))
+            .fragment_entry_point(self.fragment_entry_point)
+            .input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .polygon_mode(self.polygon_mode)
+            .cull_mode(self.cull_mode, self.front_face)
+            .multisampling()
+            .depth_stencil_state(self.depth_write_enable, self.depth_compare_op)
+            .color_attachment_formats(&self.color_attachment_formats)
+            .depth_attachment_format(self.depth_attachment_format);
+        match self.blend_mode {
+            BlendMode::Disabled => builder.blending_disabled(),
+            BlendMode::Alpha => builder.blending_alpha_blend(),
+        }
+    }
+}
+
+// Graphics pipelines sharing one layout, each built the first time its state is asked for.
+pub struct PipelineStateCache {
+    pub pipelines: HashMap<PipelineStateKey, vk::Pipeline>,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline_cache: vk::PipelineCache,
+}
+
+impl PipelineStateCache {
+    pub fn new(pipeline_layout: vk::PipelineLayout, pipeline_cache: vk::PipelineCache) -> Self {
+        Self {
+            pipelines: HashMap::new(),
+            pipeline_layout,
+            pipeline_cache,
+        }
+    }
+
+    pub fn get(&mut self, device_handle: &DeviceHandle, key: &PipelineStateKey) -> vk::Pipeline {
+        if let Some(&pipeline) = self.pipelines.get(key) {
+            return pipeline;
+        }
+        let pipeline = key
+            .get_builder(self.pipeline_layout)
+            .build(device_handle, self.pipeline_cache);
+        self.pipelines.insert(key.clone(), pipeline);
+        pipeline
+    }
+
+    // Rebuilds every cached pipeline using the old shader with the new one. The old pipelines are
+    // only destroyed once every new one exists; expects the device to be idle.
+    pub fn replace_shader(
+        &mut self,
+        device_handle: &DeviceHandle,
+        shader_module: vk::ShaderModule,
+        new_shader_module: vk::ShaderModule,
+    ) -> VkResult<()> {
+        let replace = |module: vk::ShaderModule| match module == shader_module {
+            true => new_shader_module,
+            false => module,
+        };
+        let keys: Vec<PipelineStateKey> = self
+            .pipelines
+            .keys()
+            .filter(|key| {
+                key.vertex_shader == shader_module || key.fragment_shader == shader_module
+            })
+            .cloned()
+            .collect();
+
+        let mut new_pipelines = Vec::with_capacity(keys.len());
+        for key in &keys {
+            let new_key = PipelineStateKey {
+                vertex_shader: replace(key.vertex_shader),
+                fragment_shader: replace(key.fragment_shader),
+                ..key.clone()
+            };
+            match new_key
+                .get_builder(self.pipeline_layout)
+                .try_build(device_handle, self.pipeline_cache)
+            {
+                Ok(pipeline) => new_pipelines.push((new_key, pipeline)),
+                Err(result) => unsafe {
+                    new_pipelines
+                        .iter()
+                        .for_each(|&(_, pipeline)| device_handle.destroy_pipeline(pipeline, None));
+                    return Err(result);
+                },
+            }
+        }
+
+        for key in &keys {
+            if let Some(pipeline) = self.pipelines.remove(key) {
+                unsafe { device_handle.destroy_pipeline(pipeline, None) };
+            }
+        }
+        self.pipelines.extend(new_pipelines);
+        Ok(())
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        self.pipelines
+            .drain()
+            .for_each(|(_, pipeline)| unsafe { device_handle.destroy_pipeline(pipeline, None) });
+    }
+}
+
 pub fn load_shader_module(
     device_handle: &DeviceHandle,
     shader: &[u8],
     flags: Option<vk::ShaderModuleCreateFlags>,
 ) -> vk::ShaderModule {
-    let (_, code, _) = unsafe { shader.align_to::<u32>() };
+    // NB! include_bytes! data isn't necessarily 4-byte aligned
+    let code = ash::util::read_spv(&mut Cursor::new(shader))
+        .expect("koi::ren::vk::pipeline - invalid SPIR-V module");
     let create_info = vk::ShaderModuleCreateInfo::default()
-        .code(code)
+        .code(&code)
         .flags(flags.unwrap_or_default());
 
     unsafe {
@@ -222,14 +438,72 @@ pub fn create_pipeline_layout(
     }
 }
 
+// Push constant ranges come from the entry points, checked against and sized by the members of the
+// host struct they're pushed from; set_layouts must cover every set the entry points use.
+pub fn create_reflected_pipeline_layout(
+    device_handle: &DeviceHandle,
+    entry_points: &[&EntryPoint],
+    set_layouts: &[vk::DescriptorSetLayout],
+    push_constant_members: &[(usize, usize)],
+) -> vk::PipelineLayout {
+    for entry_point in entry_points {
+        if entry_point.get_set_count() as usize > set_layouts.len() {
+            panic!(
+                "koi::ren::vk::pipeline - {} uses {} descriptor sets but its layout has {}",
+                entry_point.name,
+                entry_point.get_set_count(),
+                set_layouts.len()
+            );
+        }
+    }
+    let push_constant_ranges =
+        reflect::get_push_constant_ranges(entry_points, push_constant_members);
+    create_pipeline_layout(device_handle, set_layouts, Some(&push_constant_ranges))
+}
+
 pub fn create_compute_pipeline(
     device_handle: &DeviceHandle,
+    pipeline_cache: vk::PipelineCache,
+    shader_module: vk::ShaderModule,
+    layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    create_compute_pipeline_with_entry_point(
+        device_handle,
+        pipeline_cache,
+        shader_module,
+        layout,
+        c"main_cs",
+    )
+}
+
+// For shader crates exposing more than one compute entry point.
+pub fn create_compute_pipeline_with_entry_point(
+    device_handle: &DeviceHandle,
+    pipeline_cache: vk::PipelineCache,
     shader_module: vk::ShaderModule,
     layout: vk::PipelineLayout,
+    entry_point: &CStr,
 ) -> vk::Pipeline {
+    try_create_compute_pipeline(
+        device_handle,
+        pipeline_cache,
+        shader_module,
+        layout,
+        entry_point,
+    )
+    .expect("koi::ren::vk::pipeline - failed to create compute pipeline")
+}
+
+pub fn try_create_compute_pipeline(
+    device_handle: &DeviceHandle,
+    pipeline_cache: vk::PipelineCache,
+    shader_module: vk::ShaderModule,
+    layout: vk::PipelineLayout,
+    entry_point: &CStr,
+) -> VkResult<vk::Pipeline> {
     let stage = vk::PipelineShaderStageCreateInfo::default()
         .stage(vk::ShaderStageFlags::COMPUTE)
-        .name(c"main_cs")
+        .name(entry_point)
         .module(shader_module);
 
     let create_infos = [vk::ComputePipelineCreateInfo::default()
@@ -238,8 +512,72 @@ pub fn create_compute_pipeline(
 
     unsafe {
         device_handle
-            .create_compute_pipelines(vk::PipelineCache::null(), &create_infos, None)
-            .expect("koi::ren::vk::pipeline - failed to create compute pipeline")[0]
+            .create_compute_pipelines(pipeline_cache, &create_infos, None)
+            .map(|pipelines| pipelines[0])
+            .map_err(|(_, result)| result)
+    }
+}
+
+// Rebuilds a compute shader's pipelines, given as (pipeline, layout, entry point), from new code.
+// The old module and pipelines are only destroyed once every new one exists; expects the device
+// to be idle.
+pub fn reload_compute_shader(
+    device_handle: &DeviceHandle,
+    pipeline_cache: vk::PipelineCache,
+    code: &[u32],
+    shader_module: &mut vk::ShaderModule,
+    pipelines: &mut [(&mut vk::Pipeline, vk::PipelineLayout, &CStr)],
+) -> VkResult<()> {
+    let create_info = vk::ShaderModuleCreateInfo::default().code(code);
+    let new_shader_module = unsafe { device_handle.create_shader_module(&create_info, None)? };
+
+    let mut new_pipelines = Vec::with_capacity(pipelines.len());
+    for (_, layout, entry_point) in pipelines.iter() {
+        match try_create_compute_pipeline(
+            device_handle,
+            pipeline_cache,
+            new_shader_module,
+            *layout,
+            entry_point,
+        ) {
+            Ok(pipeline) => new_pipelines.push(pipeline),
+            Err(result) => unsafe {
+                new_pipelines
+                    .iter()
+                    .for_each(|&pipeline| device_handle.destroy_pipeline(pipeline, None));
+                device_handle.destroy_shader_module(new_shader_module, None);
+                return Err(result);
+            },
+        }
+    }
+
+    unsafe {
+        for ((pipeline, _, _), new_pipeline) in pipelines.iter_mut().zip(new_pipelines) {
+            device_handle.destroy_pipeline(**pipeline, None);
+            **pipeline = new_pipeline;
+        }
+        device_handle.destroy_shader_module(*shader_module, None);
+    }
+    *shader_module = new_shader_module;
+    Ok(())
+}
+
+// Pushes the whole struct from offset 0 as raw bytes; its layout is pinned in koi_gpu.
+pub fn push_constants<T: Pod>(
+    device_handle: &DeviceHandle,
+    command_buffer: vk::CommandBuffer,
+    layout: vk::PipelineLayout,
+    stage_flags: vk::ShaderStageFlags,
+    constants: &T,
+) {
+    unsafe {
+        device_handle.cmd_push_constants(
+            command_buffer,
+            layout,
+            stage_flags,
+            0,
+            bytes_of(constants),
+        )
     }
 }
 
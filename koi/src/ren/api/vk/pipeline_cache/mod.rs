@@ -0,0 +1,97 @@
+use super::device::{Device, config::PhysicalDeviceProperties};
+
+use ash::{Device as DeviceHandle, vk};
+use std::fs;
+use std::path::PathBuf;
+
+// VkPipelineCacheHeaderVersionOne: header size, header version, vendor id, device id and the
+// pipeline cache UUID, least significant byte first
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+// Driver-compiled pipelines shared by every pipeline koi creates. Loaded at startup so pipelines
+// compiled by earlier runs are reused, and written back on drop.
+pub struct PipelineCache {
+    pub handle: vk::PipelineCache,
+    path: Option<PathBuf>,
+}
+
+impl PipelineCache {
+    pub fn new(device: &Device, path: Option<PathBuf>) -> Self {
+        let data = path
+            .as_ref()
+            .and_then(|path| fs::read(path).ok())
+            .filter(|data| {
+                let valid = is_valid(data, &device.physical_device_properties);
+                if !valid {
+                    log::info!(
+                        "koi::ren::vk::pipeline_cache - discarding pipeline cache from another \
+                         device or driver"
+                    );
+                }
+                valid
+            })
+            .unwrap_or_default();
+
+        let create_info = vk::PipelineCacheCreateInfo::default().initial_data(&data);
+        let handle = unsafe {
+            device
+                .handle
+                .create_pipeline_cache(&create_info, None)
+                .expect("koi::ren::vk::pipeline_cache - failed to create Pipeline Cache")
+        };
+
+        Self { handle, path }
+    }
+
+    // NB! failing to save only costs the next startup its cache, so it isn't fatal
+    pub fn save(&self, device_handle: &DeviceHandle) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let data = match unsafe { device_handle.get_pipeline_cache_data(self.handle) } {
+            Ok(data) => data,
+            Err(error) => {
+                log::warn!(
+                    "koi::ren::vk::pipeline_cache - failed to get Pipeline Cache data: {error}"
+                );
+                return;
+            }
+        };
+        // written aside and renamed, so an interrupted save can't leave a truncated cache behind
+        let temporary_path = path.with_extension("tmp");
+        if let Err(error) =
+            fs::write(&temporary_path, &data).and_then(|_| fs::rename(&temporary_path, path))
+        {
+            log::warn!(
+                "koi::ren::vk::pipeline_cache - failed to save Pipeline Cache to {}: {error}",
+                path.display()
+            );
+        }
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        self.save(device_handle);
+        unsafe { device_handle.destroy_pipeline_cache(self.handle, None) };
+    }
+}
+
+// Drivers are free to reject data they didn't write; checking the header first means a cache from
+// another GPU or driver version is discarded instead of handed to the driver.
+fn is_valid(data: &[u8], properties: &PhysicalDeviceProperties) -> bool {
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+    let read_u32 = |offset: usize| {
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    };
+    read_u32(0) as usize >= HEADER_SIZE
+        && read_u32(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..HEADER_SIZE] == properties.pipeline_cache_uuid
+}
@@ -0,0 +1,317 @@
+use super::{
+    ComputePipeline,
+    descriptor::{DescriptorSetAllocator, DescriptorSetLayoutBuilder},
+    image::{self, Image},
+    pipeline,
+    reflect::ShaderReflection,
+    resource_allocator::ResourceAllocator,
+};
+
+use ash::{Device as DeviceHandle, vk};
+use koi_gpu::{ComputePushConstants, GpuLayout};
+use spirv_std::glam::{Vec2, Vec4};
+
+pub const TAA_JITTER_SEQUENCE_LENGTH: u32 = 16;
+// NB! checked against the threads() declared by taa.spv and fxaa.spv
+const POST_GROUP_SIZE: u32 = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AntiAliasing {
+    None,
+    Fxaa,
+    Taa,
+}
+
+// Post-processing stage, run in compute after the mesh pass.
+// NB! history images are ping-ponged; binding 2 reads last frame's output, binding 3 is written.
+pub struct PostProcessing {
+    pub anti_aliasing: AntiAliasing,
+    pub history_images: [Image; 2],
+    pub history_layouts: [vk::ImageLayout; 2],
+    pub history_index: usize,
+    pub history_valid: bool,
+
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_sets: [vk::DescriptorSet; 2],
+    pub pipeline_layout: vk::PipelineLayout,
+    pub taa_pipeline: ComputePipeline,
+    pub fxaa_pipeline: ComputePipeline,
+}
+
+impl PostProcessing {
+    pub fn new(
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+        resource_allocator: &mut ResourceAllocator,
+        descriptor_set_allocator: &mut DescriptorSetAllocator,
+        color_image: &Image,
+        velocity_image: &Image,
+    ) -> Self {
+        let history_images = [0, 1].map(|_| {
+            Image::new(
+                device_handle,
+                &mut resource_allocator.handle,
+                &mut resource_allocator.global_resources,
+                color_image.format,
+                color_image.extent_3d,
+                vk::ImageUsageFlags::TRANSFER_SRC | vk::ImageUsageFlags::STORAGE,
+                vk::ImageAspectFlags::COLOR,
+            )
+        });
+
+        let taa_shader = include_bytes!(env!("taa.spv"));
+        let fxaa_shader = include_bytes!(env!("fxaa.spv"));
+        let taa_reflection = ShaderReflection::new(taa_shader);
+        let fxaa_reflection = ShaderReflection::new(fxaa_shader);
+        // NB! both passes share one set layout and pipeline layout
+        let entry_points = [
+            taa_reflection.get_entry_point(c"main_cs"),
+            fxaa_reflection.get_entry_point(c"main_cs"),
+        ];
+        for entry_point in entry_points {
+            entry_point.check_workgroup_size([POST_GROUP_SIZE, POST_GROUP_SIZE, 1]);
+        }
+
+        let descriptor_set_layout = DescriptorSetLayoutBuilder::from_entry_points(&entry_points, 0)
+            .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+        let descriptor_set_layouts = [descriptor_set_layout];
+
+        let descriptor_sets = [0, 1].map(|output| {
+            let descriptor_set =
+                descriptor_set_allocator.allocate(device_handle, &descriptor_set_layouts);
+            update_set(device_handle, descriptor_set, [
+                color_image.view,
+                velocity_image.view,
+                history_images[1 - output].view,
+                history_images[output].view,
+            ]);
+            descriptor_set
+        });
+
+        let pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            device_handle,
+            &entry_points,
+            &descriptor_set_layouts,
+            ComputePushConstants::MEMBERS,
+        );
+
+        let taa_shader_module = pipeline::load_shader_module(device_handle, taa_shader, None);
+        let taa_pipeline = ComputePipeline {
+            name: String::from("taa"),
+            shader: taa_shader_module,
+            handle: pipeline::create_compute_pipeline(
+                device_handle,
+                pipeline_cache,
+                taa_shader_module,
+                pipeline_layout,
+            ),
+            pipeline_layout,
+            descriptor_set: None,
+            // data_0: x = current frame weight, y = history valid flag
+            push_constants: ComputePushConstants::default().data_0(Vec4::new(0.1, 0.0, 0.0, 0.0)),
+        };
+
+        let fxaa_shader_module = pipeline::load_shader_module(device_handle, fxaa_shader, None);
+        let fxaa_pipeline = ComputePipeline {
+            name: String::from("fxaa"),
+            shader: fxaa_shader_module,
+            handle: pipeline::create_compute_pipeline(
+                device_handle,
+                pipeline_cache,
+                fxaa_shader_module,
+                pipeline_layout,
+            ),
+            pipeline_layout,
+            descriptor_set: None,
+            // data_0: z = relative edge threshold, w = absolute edge threshold
+            push_constants: ComputePushConstants::default()
+                .data_0(Vec4::new(0.0, 0.0, 0.125, 0.0312)),
+        };
+
+        Self {
+            anti_aliasing: AntiAliasing::Taa,
+            history_images,
+            history_layouts: [vk::ImageLayout::UNDEFINED; 2],
+            history_index: 0,
+            history_valid: false,
+
+            descriptor_set_layout,
+            descriptor_sets,
+            pipeline_layout,
+            taa_pipeline,
+            fxaa_pipeline,
+        }
+    }
+
+    // Sub-pixel projection offset in NDC units; zero unless TAA is active.
+    pub fn get_jitter(&self, frame_count: u32, extent: vk::Extent2D) -> Vec2 {
+        if self.anti_aliasing != AntiAliasing::Taa {
+            return Vec2::ZERO;
+        }
+        let index = frame_count % TAA_JITTER_SEQUENCE_LENGTH + 1;
+        let offset = Vec2::new(halton(index, 2), halton(index, 3)) - Vec2::splat(0.5);
+        offset * 2.0 / Vec2::new(extent.width as f32, extent.height as f32)
+    }
+
+    // Resolves the frame into a post-processed image; returns the image to present from,
+    // left in TRANSFER_SRC_OPTIMAL layout.
+    pub fn draw(
+        &mut self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+        color_image: &Image,
+        velocity_image: &Image,
+    ) -> vk::Image {
+        if self.anti_aliasing == AntiAliasing::None {
+            self.history_valid = false;
+            image::transition(
+                device_handle,
+                command_buffer,
+                color_image.handle,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            );
+            return color_image.handle;
+        }
+
+        let output = self.history_index;
+        let input = 1 - output;
+        let history_valid = self.anti_aliasing == AntiAliasing::Taa
+            && self.history_valid
+            && self.history_layouts[input] != vk::ImageLayout::UNDEFINED;
+
+        image::transition(
+            device_handle,
+            command_buffer,
+            color_image.handle,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::GENERAL,
+        );
+        image::transition(
+            device_handle,
+            command_buffer,
+            velocity_image.handle,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::GENERAL,
+        );
+        image::transition(
+            device_handle,
+            command_buffer,
+            self.history_images[input].handle,
+            self.history_layouts[input],
+            vk::ImageLayout::GENERAL,
+        );
+        image::transition(
+            device_handle,
+            command_buffer,
+            self.history_images[output].handle,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+        );
+
+        let compute_pipeline = match self.anti_aliasing {
+            AntiAliasing::Fxaa => &mut self.fxaa_pipeline,
+            _ => &mut self.taa_pipeline,
+        };
+        compute_pipeline.push_constants.data_0.y = if history_valid { 1.0 } else { 0.0 };
+
+        unsafe {
+            device_handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                compute_pipeline.handle,
+            );
+            device_handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                compute_pipeline.pipeline_layout,
+                0,
+                &[self.descriptor_sets[output]],
+                &[],
+            );
+            pipeline::push_constants(
+                device_handle,
+                command_buffer,
+                compute_pipeline.pipeline_layout,
+                vk::ShaderStageFlags::COMPUTE,
+                &compute_pipeline.push_constants,
+            );
+            device_handle.cmd_dispatch(
+                command_buffer,
+                color_image.extent_2d.width.div_ceil(POST_GROUP_SIZE),
+                color_image.extent_2d.height.div_ceil(POST_GROUP_SIZE),
+                1,
+            );
+        };
+
+        image::transition(
+            device_handle,
+            command_buffer,
+            self.history_images[output].handle,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        );
+        self.history_layouts[input] = vk::ImageLayout::GENERAL;
+        self.history_layouts[output] = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+        self.history_valid = self.anti_aliasing == AntiAliasing::Taa;
+        self.history_index = input;
+
+        self.history_images[output].handle
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        unsafe {
+            [&self.taa_pipeline, &self.fxaa_pipeline]
+                .iter()
+                .for_each(|effect| {
+                    device_handle.destroy_shader_module(effect.shader, None);
+                    device_handle.destroy_pipeline(effect.handle, None);
+                });
+            device_handle.destroy_pipeline_layout(self.pipeline_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+fn update_set(
+    device_handle: &DeviceHandle,
+    dst_set: vk::DescriptorSet,
+    image_views: [vk::ImageView; 4],
+) {
+    let image_infos = image_views.map(|image_view| {
+        [vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::GENERAL)
+            .image_view(image_view)]
+    });
+
+    let descriptor_writes: Vec<_> = image_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, image_info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_binding(binding as u32)
+                .dst_set(dst_set)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                .image_info(image_info)
+        })
+        .collect();
+
+    unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
@@ -0,0 +1,876 @@
+use ash::vk;
+
+use std::collections::HashMap;
+use std::ffi::CStr;
+use std::io::Cursor;
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+const HEADER_SIZE: usize = 5;
+// SPIR-V 1.4 made entry point interfaces list every global they use, not just inputs and outputs
+const VERSION_1_4: u32 = 0x0001_0400;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_EXECUTION_MODE_ID: u32 = 331;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_CLASS_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_CLASS_UNIFORM: u32 = 2;
+const STORAGE_CLASS_PUSH_CONSTANT: u32 = 9;
+const STORAGE_CLASS_STORAGE_BUFFER: u32 = 12;
+const STORAGE_CLASS_PHYSICAL_STORAGE_BUFFER: u32 = 5349;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+pub struct Binding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    // 0 for runtime arrays, sized by the host
+    pub descriptor_count: u32,
+    // buffers only; offset and size of each member of the struct the buffer holds
+    pub members: Vec<(u32, u32)>,
+}
+
+pub struct EntryPoint {
+    pub name: String,
+    pub stage: vk::ShaderStageFlags,
+    // threads() of compute entry points, [0, 0, 0] otherwise
+    pub workgroup_size: [u32; 3],
+    pub bindings: Vec<Binding>,
+    // size of the push constant block, 0 if the entry point has none
+    pub push_constant_size: u32,
+    // offset and size of each push constant member, in declaration order
+    pub push_constant_members: Vec<(u32, u32)>,
+}
+
+impl EntryPoint {
+    // Dispatches are sized on the host, so its group size has to agree with the shader's.
+    pub fn check_workgroup_size(&self, workgroup_size: [u32; 3]) {
+        if self.workgroup_size != workgroup_size {
+            panic!(
+                "koi::ren::vk::reflect - {} declares threads {:?} but the host dispatches {:?}",
+                self.name, self.workgroup_size, workgroup_size
+            );
+        }
+    }
+
+    pub fn get_set_count(&self) -> u32 {
+        self.bindings
+            .iter()
+            .map(|binding| binding.set + 1)
+            .max()
+            .unwrap_or_default()
+    }
+}
+
+// Interface of a SPIR-V module, per entry point: the descriptors and push constants it uses, its
+// stage and, for compute, its workgroup size.
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPoint>,
+}
+
+#[derive(Clone, Copy)]
+enum Type {
+    Scalar(u32),
+    Vector(u32, u32),
+    Matrix(u32, u32),
+    Image(u32, u32),
+    Sampler,
+    SampledImage,
+    AccelerationStructure,
+    Array(u32, u32),
+    RuntimeArray(u32),
+    Struct,
+    Pointer(u32),
+    // buffer reference; a 64-bit address wherever it's stored
+    DeviceAddress,
+}
+
+#[derive(Default)]
+struct Decorations {
+    block: bool,
+    buffer_block: bool,
+    set: Option<u32>,
+    binding: Option<u32>,
+    array_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct Member {
+    offset: u32,
+    matrix_stride: Option<u32>,
+}
+
+struct Variable {
+    id: u32,
+    pointer_type: u32,
+    storage_class: u32,
+}
+
+// NB! only reads what koi needs to build layouts; anything it doesn't understand is skipped
+#[derive(Default)]
+struct Module {
+    version: u32,
+    entry_points: Vec<(u32, u32, String, Vec<u32>)>,
+    workgroup_sizes: Vec<(u32, [u32; 3], bool)>,
+    types: HashMap<u32, Type>,
+    struct_members: HashMap<u32, Vec<u32>>,
+    constants: HashMap<u32, u32>,
+    decorations: HashMap<u32, Decorations>,
+    members: HashMap<(u32, u32), Member>,
+    variables: Vec<Variable>,
+}
+
+impl ShaderReflection {
+    // Takes the same bytes as pipeline::load_shader_module.
+    pub fn new(shader: &[u8]) -> Self {
+        // NB! include_bytes! data isn't necessarily 4-byte aligned
+        let code = ash::util::read_spv(&mut Cursor::new(shader))
+            .expect("koi::ren::vk::reflect - invalid SPIR-V module");
+        Self::from_code(&code)
+    }
+
+    pub fn from_code(code: &[u32]) -> Self {
+        if code.len() < HEADER_SIZE || code[0] != MAGIC_NUMBER {
+            panic!("koi::ren::vk::reflect - invalid SPIR-V module");
+        }
+        let module = Module::parse(code);
+        let entry_points = module
+            .entry_points
+            .iter()
+            .map(|(execution_model, id, name, interface)| {
+                module.get_entry_point(*execution_model, *id, name, interface)
+            })
+            .collect();
+        Self { entry_points }
+    }
+
+    pub fn get_entry_point(&self, name: &CStr) -> &EntryPoint {
+        let name = name.to_string_lossy();
+        self.entry_points
+            .iter()
+            .find(|entry_point| entry_point.name == name)
+            .unwrap_or_else(|| panic!("koi::ren::vk::reflect - missing entry point {name}"))
+    }
+}
+
+impl Module {
+    fn parse(code: &[u32]) -> Self {
+        let mut module = Self {
+            version: code[1],
+            ..Default::default()
+        };
+        let mut index = HEADER_SIZE;
+        while index < code.len() {
+            let word_count = (code[index] >> 16) as usize;
+            let opcode = code[index] & 0xffff;
+            if word_count == 0 || index + word_count > code.len() {
+                panic!("koi::ren::vk::reflect - truncated SPIR-V module");
+            }
+            module.parse_instruction(opcode, &code[index + 1..index + word_count]);
+            index += word_count;
+        }
+        module
+    }
+
+    fn parse_instruction(&mut self, opcode: u32, operands: &[u32]) {
+        let operand = |index: usize| operands.get(index).copied().unwrap_or_default();
+        match opcode {
+            OP_ENTRY_POINT => {
+                let (name, name_size) = read_string(&operands[2..]);
+                let interface = operands[2 + name_size..].to_vec();
+                self.entry_points
+                    .push((operand(0), operand(1), name, interface));
+            }
+            OP_EXECUTION_MODE | OP_EXECUTION_MODE_ID => match operand(1) {
+                EXECUTION_MODE_LOCAL_SIZE => self.workgroup_sizes.push((
+                    operand(0),
+                    [operand(2), operand(3), operand(4)],
+                    false,
+                )),
+                EXECUTION_MODE_LOCAL_SIZE_ID => self.workgroup_sizes.push((
+                    operand(0),
+                    [operand(2), operand(3), operand(4)],
+                    true,
+                )),
+                _ => {}
+            },
+            OP_TYPE_INT | OP_TYPE_FLOAT => {
+                self.types.insert(operand(0), Type::Scalar(operand(1) / 8));
+            }
+            OP_TYPE_VECTOR => {
+                self.types
+                    .insert(operand(0), Type::Vector(operand(1), operand(2)));
+            }
+            OP_TYPE_MATRIX => {
+                self.types
+                    .insert(operand(0), Type::Matrix(operand(1), operand(2)));
+            }
+            // sampled is 1 for sampled images and 2 for storage images
+            OP_TYPE_IMAGE => {
+                self.types
+                    .insert(operand(0), Type::Image(operand(2), operand(6)));
+            }
+            OP_TYPE_SAMPLER => {
+                self.types.insert(operand(0), Type::Sampler);
+            }
+            OP_TYPE_SAMPLED_IMAGE => {
+                self.types.insert(operand(0), Type::SampledImage);
+            }
+            OP_TYPE_ACCELERATION_STRUCTURE => {
+                self.types.insert(operand(0), Type::AccelerationStructure);
+            }
+            OP_TYPE_ARRAY => {
+                self.types
+                    .insert(operand(0), Type::Array(operand(1), operand(2)));
+            }
+            OP_TYPE_RUNTIME_ARRAY => {
+                self.types
+                    .insert(operand(0), Type::RuntimeArray(operand(1)));
+            }
+            OP_TYPE_STRUCT => {
+                self.types.insert(operand(0), Type::Struct);
+                self.struct_members
+                    .insert(operand(0), operands[1..].to_vec());
+            }
+            OP_TYPE_POINTER => match operand(1) {
+                STORAGE_CLASS_PHYSICAL_STORAGE_BUFFER => {
+                    self.types.insert(operand(0), Type::DeviceAddress);
+                }
+                _ => {
+                    self.types.insert(operand(0), Type::Pointer(operand(2)));
+                }
+            },
+            // NB! only the low word; array lengths and workgroup sizes fit in 32 bits
+            OP_CONSTANT => {
+                self.constants.insert(operand(1), operand(2));
+            }
+            OP_VARIABLE => self.variables.push(Variable {
+                id: operand(1),
+                pointer_type: operand(0),
+                storage_class: operand(2),
+            }),
+            OP_DECORATE => {
+                let decorations = self.decorations.entry(operand(0)).or_default();
+                match operand(1) {
+                    DECORATION_BLOCK => decorations.block = true,
+                    DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                    DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(operand(2)),
+                    DECORATION_DESCRIPTOR_SET => decorations.set = Some(operand(2)),
+                    DECORATION_BINDING => decorations.binding = Some(operand(2)),
+                    _ => {}
+                }
+            }
+            OP_MEMBER_DECORATE => {
+                let member = self.members.entry((operand(0), operand(1))).or_default();
+                match operand(2) {
+                    DECORATION_OFFSET => member.offset = operand(3),
+                    DECORATION_MATRIX_STRIDE => member.matrix_stride = Some(operand(3)),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn get_entry_point(
+        &self,
+        execution_model: u32,
+        id: u32,
+        name: &str,
+        interface: &[u32],
+    ) -> EntryPoint {
+        let stage = match execution_model {
+            0 => vk::ShaderStageFlags::VERTEX,
+            1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            3 => vk::ShaderStageFlags::GEOMETRY,
+            4 => vk::ShaderStageFlags::FRAGMENT,
+            5 => vk::ShaderStageFlags::COMPUTE,
+            _ => panic!("koi::ren::vk::reflect - unsupported execution model for {name}"),
+        };
+        let workgroup_size = self
+            .workgroup_sizes
+            .iter()
+            .find(|(entry_point, _, _)| *entry_point == id)
+            .map_or([0; 3], |(_, size, is_id)| match is_id {
+                true => size.map(|id| self.constants.get(&id).copied().unwrap_or_default()),
+                false => *size,
+            });
+
+        let mut bindings = vec![];
+        let mut push_constant_size = 0;
+        let mut push_constant_members = vec![];
+        // NB! before 1.4 interfaces only list inputs and outputs, so every global counts
+        let variables = self
+            .variables
+            .iter()
+            .filter(|variable| self.version < VERSION_1_4 || interface.contains(&variable.id));
+        for variable in variables {
+            let Some(&Type::Pointer(pointee)) = self.types.get(&variable.pointer_type) else {
+                continue;
+            };
+            match variable.storage_class {
+                STORAGE_CLASS_PUSH_CONSTANT => {
+                    push_constant_size = push_constant_size.max(self.get_size(pointee, None));
+                    push_constant_members = self.get_members(pointee);
+                }
+                STORAGE_CLASS_UNIFORM_CONSTANT
+                | STORAGE_CLASS_UNIFORM
+                | STORAGE_CLASS_STORAGE_BUFFER => {
+                    let decorations = self.decorations.get(&variable.id);
+                    let (Some(set), Some(binding)) = (
+                        decorations.and_then(|decorations| decorations.set),
+                        decorations.and_then(|decorations| decorations.binding),
+                    ) else {
+                        continue;
+                    };
+                    let (element, descriptor_count) = match self.types.get(&pointee) {
+                        Some(&Type::Array(element, length)) => {
+                            (element, self.constants.get(&length).copied().unwrap_or(1))
+                        }
+                        Some(&Type::RuntimeArray(element)) => (element, 0),
+                        _ => (pointee, 1),
+                    };
+                    bindings.push(Binding {
+                        set,
+                        binding,
+                        descriptor_type: self.get_descriptor_type(
+                            name,
+                            element,
+                            variable.storage_class,
+                        ),
+                        descriptor_count,
+                        members: self.get_members(element),
+                    });
+                }
+                _ => {}
+            }
+        }
+        bindings.sort_by_key(|binding| (binding.set, binding.binding));
+
+        EntryPoint {
+            name: name.to_owned(),
+            stage,
+            workgroup_size,
+            bindings,
+            push_constant_size,
+            push_constant_members,
+        }
+    }
+
+    fn get_descriptor_type(&self, name: &str, id: u32, storage_class: u32) -> vk::DescriptorType {
+        let decorations = self.decorations.get(&id);
+        let is_buffer_block = decorations.is_some_and(|decorations| decorations.buffer_block);
+        let is_block = decorations.is_some_and(|decorations| decorations.block);
+        match (storage_class, self.types.get(&id)) {
+            (STORAGE_CLASS_STORAGE_BUFFER, _) => vk::DescriptorType::STORAGE_BUFFER,
+            (STORAGE_CLASS_UNIFORM, _) if is_buffer_block => vk::DescriptorType::STORAGE_BUFFER,
+            (STORAGE_CLASS_UNIFORM, _) if is_block => vk::DescriptorType::UNIFORM_BUFFER,
+            (_, Some(Type::Sampler)) => vk::DescriptorType::SAMPLER,
+            (_, Some(Type::SampledImage)) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (_, Some(Type::AccelerationStructure)) => {
+                vk::DescriptorType::ACCELERATION_STRUCTURE_KHR
+            }
+            (_, Some(&Type::Image(DIM_SUBPASS_DATA, _))) => vk::DescriptorType::INPUT_ATTACHMENT,
+            (_, Some(&Type::Image(DIM_BUFFER, 2))) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            (_, Some(&Type::Image(DIM_BUFFER, _))) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (_, Some(&Type::Image(_, 2))) => vk::DescriptorType::STORAGE_IMAGE,
+            (_, Some(&Type::Image(..))) => vk::DescriptorType::SAMPLED_IMAGE,
+            _ => panic!("koi::ren::vk::reflect - unsupported descriptor type in {name}"),
+        }
+    }
+
+    // Size as laid out by the Offset, ArrayStride and MatrixStride decorations.
+    fn get_size(&self, id: u32, matrix_stride: Option<u32>) -> u32 {
+        match self.types.get(&id) {
+            Some(&Type::Scalar(size)) => size,
+            Some(Type::DeviceAddress) => 8,
+            Some(&Type::Vector(component, count)) => self.get_size(component, None) * count,
+            Some(&Type::Matrix(column, count)) => {
+                matrix_stride.unwrap_or_else(|| self.get_size(column, None)) * count
+            }
+            Some(&Type::Array(element, length)) => {
+                let length = self.constants.get(&length).copied().unwrap_or_default();
+                self.get_array_stride(id, element) * length
+            }
+            Some(Type::Struct) => self
+                .get_struct_layout(id)
+                .into_iter()
+                .map(|(offset, size)| offset + size)
+                .max()
+                .unwrap_or_default(),
+            // NB! runtime arrays and opaque types take no space in a block
+            _ => 0,
+        }
+    }
+
+    // Offset and size of each member of a struct.
+    fn get_struct_layout(&self, id: u32) -> Vec<(u32, u32)> {
+        self.struct_members
+            .get(&id)
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, &member_type)| {
+                let member = self.members.get(&(id, index as u32));
+                let offset = member.map_or(0, |member| member.offset);
+                let matrix_stride = member.and_then(|member| member.matrix_stride);
+                (offset, self.get_size(member_type, matrix_stride))
+            })
+            .collect()
+    }
+
+    // Layout of the struct a block holds, looking through arrays and the single-member blocks
+    // rust-gpu wraps interface types in; empty when it holds no struct.
+    fn get_members(&self, id: u32) -> Vec<(u32, u32)> {
+        match self.types.get(&id) {
+            Some(&Type::Array(element, _) | &Type::RuntimeArray(element)) => {
+                self.get_members(element)
+            }
+            Some(Type::Struct) => match self.struct_members[&id].as_slice() {
+                [member]
+                    if matches!(
+                        self.types.get(member),
+                        Some(Type::Struct | Type::Array(..) | Type::RuntimeArray(_))
+                    ) =>
+                {
+                    self.get_members(*member)
+                }
+                _ => self.get_struct_layout(id),
+            },
+            _ => vec![],
+        }
+    }
+
+    fn get_array_stride(&self, id: u32, element: u32) -> u32 {
+        self.decorations
+            .get(&id)
+            .and_then(|decorations| decorations.array_stride)
+            .unwrap_or_else(|| self.get_size(element, None))
+    }
+}
+
+// Literal strings are nul-terminated and padded to a whole word; returns the string and the
+// number of words it took.
+fn read_string(words: &[u32]) -> (String, usize) {
+    let bytes: Vec<u8> = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+    let length = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    let name = String::from_utf8_lossy(&bytes[..length]).into_owned();
+    (name, length / 4 + 1)
+}
+
+// One range covering every stage that uses push constants, the way koi pushes a single struct
+// per pipeline. host_members are the offset and size of each member of the host struct, in
+// declaration order, as GpuLayout::MEMBERS lists them; every block has to declare a prefix of them.
+pub fn get_push_constant_ranges(
+    entry_points: &[&EntryPoint],
+    host_members: &[(usize, usize)],
+) -> Vec<vk::PushConstantRange> {
+    let host_size = host_members
+        .last()
+        .map(|(offset, size)| offset + size)
+        .unwrap_or_default();
+    let mut stage_flags = vk::ShaderStageFlags::empty();
+    for entry_point in entry_points {
+        if entry_point.push_constant_size as usize > host_size {
+            panic!(
+                "koi::ren::vk::reflect - {} reads {} bytes of push constants but the host struct \
+                 is {host_size} bytes",
+                entry_point.name, entry_point.push_constant_size
+            );
+        }
+        let host_prefix = host_members
+            .iter()
+            .take(entry_point.push_constant_members.len())
+            .map(|&(offset, size)| (offset as u32, size as u32));
+        if !host_prefix.eq(entry_point.push_constant_members.iter().copied()) {
+            panic!(
+                "koi::ren::vk::reflect - {} declares push constant members {:?} but the host \
+                 struct has {host_members:?}",
+                entry_point.name, entry_point.push_constant_members
+            );
+        }
+        if entry_point.push_constant_size > 0 {
+            stage_flags |= entry_point.stage;
+        }
+    }
+    if stage_flags.is_empty() {
+        return vec![];
+    }
+    vec![
+        vk::PushConstantRange::default()
+            .offset(0)
+            .size(host_size as u32)
+            .stage_flags(stage_flags),
+    ]
+}
+
+// Merges the bindings the entry points declare in one set; each keeps the stages that use it.
+// Runtime arrays keep 0 descriptors until the host sizes them.
+pub fn get_set_bindings<'a>(
+    entry_points: &[&EntryPoint],
+    set: u32,
+) -> Vec<vk::DescriptorSetLayoutBinding<'a>> {
+    let mut bindings: Vec<vk::DescriptorSetLayoutBinding> = vec![];
+    for entry_point in entry_points {
+        for reflected in entry_point
+            .bindings
+            .iter()
+            .filter(|binding| binding.set == set)
+        {
+            match bindings
+                .iter_mut()
+                .find(|binding| binding.binding == reflected.binding)
+            {
+                Some(binding) if binding.descriptor_type != reflected.descriptor_type => panic!(
+                    "koi::ren::vk::reflect - {} set {set} binding {} is a {:?}, other stages use a \
+                     {:?}",
+                    entry_point.name,
+                    reflected.binding,
+                    reflected.descriptor_type,
+                    binding.descriptor_type
+                ),
+                Some(binding) => {
+                    binding.stage_flags |= entry_point.stage;
+                    binding.descriptor_count =
+                        binding.descriptor_count.max(reflected.descriptor_count);
+                }
+                None => bindings.push(
+                    vk::DescriptorSetLayoutBinding::default()
+                        .binding(reflected.binding)
+                        .descriptor_type(reflected.descriptor_type)
+                        .descriptor_count(reflected.descriptor_count)
+                        .stage_flags(entry_point.stage),
+                ),
+            }
+        }
+    }
+    bindings.sort_by_key(|binding| binding.binding);
+    bindings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use koi_gpu::{
+        ComputePushConstants, CullPushConstants, DrawIndexedIndirectCommand, GpuLayout, Instance,
+        InstanceLods, MorphDelta, OcclusionData, PushConstants, SkinVertex, SkinningPushConstants,
+        Vertex,
+    };
+
+    fn instruction(module: &mut Vec<u32>, opcode: u32, operands: &[u32]) {
+        module.push(((operands.len() as u32 + 1) << 16) | opcode);
+        module.extend_from_slice(operands);
+    }
+
+    fn string(value: &str) -> Vec<u32> {
+        let mut bytes = value.as_bytes().to_vec();
+        bytes.resize((bytes.len() / 4 + 1) * 4, 0);
+        bytes
+            .chunks(4)
+            .map(|chunk| u32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect()
+    }
+
+    // A compute entry point with threads(8, 4, 1), a storage image at set 0 binding 1, a storage
+    // buffer at set 1 binding 0 and a push constant block of a vec4 followed by a float.
+    fn compute_module(version: u32) -> Vec<u32> {
+        let mut module = vec![MAGIC_NUMBER, version, 0, 100, 0];
+        let mut entry_point = vec![5, 1];
+        entry_point.extend(string("main_cs"));
+        entry_point.extend([10, 20, 30]);
+        instruction(&mut module, OP_ENTRY_POINT, &entry_point);
+        instruction(&mut module, OP_EXECUTION_MODE, &[
+            1,
+            EXECUTION_MODE_LOCAL_SIZE,
+            8,
+            4,
+            1,
+        ]);
+        instruction(&mut module, OP_DECORATE, &[
+            10,
+            DECORATION_DESCRIPTOR_SET,
+            0,
+        ]);
+        instruction(&mut module, OP_DECORATE, &[10, DECORATION_BINDING, 1]);
+        instruction(&mut module, OP_DECORATE, &[
+            20,
+            DECORATION_DESCRIPTOR_SET,
+            1,
+        ]);
+        instruction(&mut module, OP_DECORATE, &[20, DECORATION_BINDING, 0]);
+        instruction(&mut module, OP_DECORATE, &[42, DECORATION_BLOCK]);
+        instruction(&mut module, OP_DECORATE, &[52, DECORATION_BLOCK]);
+        instruction(&mut module, OP_MEMBER_DECORATE, &[
+            52,
+            0,
+            DECORATION_OFFSET,
+            0,
+        ]);
+        instruction(&mut module, OP_MEMBER_DECORATE, &[
+            52,
+            1,
+            DECORATION_OFFSET,
+            16,
+        ]);
+        instruction(&mut module, OP_TYPE_FLOAT, &[2, 32]);
+        instruction(&mut module, OP_TYPE_VECTOR, &[3, 2, 4]);
+        instruction(&mut module, OP_TYPE_IMAGE, &[4, 2, 1, 0, 0, 0, 2, 1]);
+        instruction(&mut module, OP_TYPE_POINTER, &[
+            5,
+            STORAGE_CLASS_UNIFORM_CONSTANT,
+            4,
+        ]);
+        instruction(&mut module, OP_TYPE_RUNTIME_ARRAY, &[41, 2]);
+        instruction(&mut module, OP_TYPE_STRUCT, &[42, 41]);
+        instruction(&mut module, OP_TYPE_POINTER, &[
+            43,
+            STORAGE_CLASS_STORAGE_BUFFER,
+            42,
+        ]);
+        instruction(&mut module, OP_TYPE_STRUCT, &[52, 3, 2]);
+        instruction(&mut module, OP_TYPE_POINTER, &[
+            53,
+            STORAGE_CLASS_PUSH_CONSTANT,
+            52,
+        ]);
+        instruction(&mut module, OP_VARIABLE, &[
+            5,
+            10,
+            STORAGE_CLASS_UNIFORM_CONSTANT,
+        ]);
+        instruction(&mut module, OP_VARIABLE, &[
+            43,
+            20,
+            STORAGE_CLASS_STORAGE_BUFFER,
+        ]);
+        instruction(&mut module, OP_VARIABLE, &[
+            53,
+            30,
+            STORAGE_CLASS_PUSH_CONSTANT,
+        ]);
+        // declared but not used by the entry point
+        instruction(&mut module, OP_VARIABLE, &[
+            53,
+            31,
+            STORAGE_CLASS_PUSH_CONSTANT,
+        ]);
+        module
+    }
+
+    #[test]
+    fn reflects_compute_entry_point() {
+        let reflection = ShaderReflection::from_code(&compute_module(0x0001_0500));
+        let entry_point = reflection.get_entry_point(c"main_cs");
+        assert_eq!(entry_point.stage, vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(entry_point.workgroup_size, [8, 4, 1]);
+        assert_eq!(entry_point.push_constant_size, 20);
+        assert_eq!(entry_point.push_constant_members, vec![(0, 16), (16, 4)]);
+        assert_eq!(entry_point.get_set_count(), 2);
+
+        let bindings: Vec<_> = entry_point
+            .bindings
+            .iter()
+            .map(|binding| (binding.set, binding.binding, binding.descriptor_type))
+            .collect();
+        assert_eq!(bindings, vec![
+            (0, 1, vk::DescriptorType::STORAGE_IMAGE),
+            (1, 0, vk::DescriptorType::STORAGE_BUFFER),
+        ]);
+    }
+
+    #[test]
+    fn builds_layouts_from_entry_points() {
+        let reflection = ShaderReflection::from_code(&compute_module(0x0001_0500));
+        let entry_point = reflection.get_entry_point(c"main_cs");
+        let ranges = get_push_constant_ranges(&[entry_point], &[(0, 16), (16, 4), (20, 12)]);
+        assert_eq!(ranges.len(), 1);
+        assert_eq!(ranges[0].size, 32);
+        assert_eq!(ranges[0].stage_flags, vk::ShaderStageFlags::COMPUTE);
+
+        let bindings = get_set_bindings(&[entry_point], 0);
+        assert_eq!(bindings.len(), 1);
+        assert_eq!(bindings[0].binding, 1);
+        assert_eq!(bindings[0].descriptor_count, 1);
+    }
+
+    fn check_layout<T: GpuLayout>(name: &str, reflected: &[(u32, u32)]) {
+        let host: Vec<_> = T::MEMBERS
+            .iter()
+            .map(|&(offset, size)| (offset as u32, size as u32))
+            .collect();
+        // NB! shaders may declare a prefix of a host struct
+        assert!(!reflected.is_empty(), "{name} has no reflected members");
+        assert_eq!(
+            reflected,
+            &host[..reflected.len().min(host.len())],
+            "{name}"
+        );
+    }
+
+    fn get_binding(entry_point: &EntryPoint, set: u32, binding: u32) -> &[(u32, u32)] {
+        &entry_point
+            .bindings
+            .iter()
+            .find(|reflected| reflected.set == set && reflected.binding == binding)
+            .unwrap_or_else(|| panic!("{} has no set {set} binding {binding}", entry_point.name))
+            .members
+    }
+
+    // The shared koi_gpu structs against the shaders build.rs compiled. Push constant members are
+    // also checked when each pipeline layout is created; buffer members only here.
+    #[test]
+    fn matches_host_layouts() {
+        let vertex = ShaderReflection::new(include_bytes!(env!("vertex.spv")));
+        let vertex = vertex.get_entry_point(c"main_vs");
+        check_layout::<PushConstants>("vertex", &vertex.push_constant_members);
+        check_layout::<Instance>("vertex instances", get_binding(vertex, 1, 0));
+
+        let fragment = ShaderReflection::new(include_bytes!(env!("fragment.spv")));
+        for name in [c"main_fs", c"main_masked_fs"] {
+            let entry_point = fragment.get_entry_point(name);
+            check_layout::<PushConstants>("fragment", &entry_point.push_constant_members);
+        }
+
+        let cull = ShaderReflection::new(include_bytes!(env!("cull.spv")));
+        let cull = cull.get_entry_point(c"main_cs");
+        check_layout::<CullPushConstants>("cull", &cull.push_constant_members);
+        check_layout::<Instance>("cull instances", get_binding(cull, 0, 0));
+        check_layout::<DrawIndexedIndirectCommand>("cull draws", get_binding(cull, 0, 1));
+        check_layout::<OcclusionData>("cull occlusion", get_binding(cull, 0, 4));
+        check_layout::<InstanceLods>("cull lods", get_binding(cull, 0, 5));
+
+        let skinning = ShaderReflection::new(include_bytes!(env!("skinning.spv")));
+        let skinning = skinning.get_entry_point(c"main_cs");
+        check_layout::<SkinningPushConstants>("skinning", &skinning.push_constant_members);
+        check_layout::<Vertex>("skinning sources", get_binding(skinning, 0, 0));
+        check_layout::<SkinVertex>("skinning joints", get_binding(skinning, 0, 1));
+        check_layout::<Vertex>("skinning output", get_binding(skinning, 0, 2));
+        check_layout::<MorphDelta>("skinning morphs", get_binding(skinning, 2, 0));
+
+        for (name, shader, entry_point_names) in [
+            (
+                "gradient",
+                include_bytes!(env!("gradient.spv")).as_slice(),
+                [c"main_cs"].as_slice(),
+            ),
+            ("sky", include_bytes!(env!("sky.spv")), &[c"main_cs"]),
+            ("skybox", include_bytes!(env!("skybox.spv")), &[c"main_cs"]),
+            ("taa", include_bytes!(env!("taa.spv")), &[c"main_cs"]),
+            ("fxaa", include_bytes!(env!("fxaa.spv")), &[c"main_cs"]),
+            ("mipmap", include_bytes!(env!("mipmap.spv")), &[c"main_cs"]),
+            // NB! equirect_cs pushes nothing
+            ("ibl", include_bytes!(env!("ibl.spv")), &[
+                c"irradiance_cs",
+                c"prefilter_cs",
+                c"brdf_lut_cs",
+            ]),
+        ] {
+            let reflection = ShaderReflection::new(shader);
+            for &entry_point_name in entry_point_names {
+                let entry_point = reflection.get_entry_point(entry_point_name);
+                check_layout::<ComputePushConstants>(name, &entry_point.push_constant_members);
+            }
+        }
+
+        // NB! the host creates the hiz layout without push constants
+        let hiz = ShaderReflection::new(include_bytes!(env!("hiz.spv")));
+        let hiz = hiz.get_entry_point(c"main_cs");
+        assert!(hiz.push_constant_members.is_empty(), "hiz");
+    }
+
+    // The glslangValidator outputs build.rs compiled from the GLSL and HLSL test shaders, which
+    // declare the same interface.
+    #[test]
+    fn reflects_glsl_and_hlsl_shaders() {
+        for (name, shader) in [
+            (
+                "glsl_test",
+                include_bytes!(env!("glsl_test.spv")).as_slice(),
+            ),
+            ("hlsl_test", include_bytes!(env!("hlsl_test.spv"))),
+        ] {
+            let reflection = ShaderReflection::new(shader);
+            let entry_point = reflection.get_entry_point(c"main_cs");
+            assert_eq!(entry_point.workgroup_size, [8, 8, 1], "{name}");
+            assert_eq!(
+                entry_point.push_constant_members,
+                vec![(0, 16), (16, 4)],
+                "{name}"
+            );
+
+            let bindings: Vec<_> = entry_point
+                .bindings
+                .iter()
+                .map(|binding| (binding.set, binding.binding, binding.descriptor_type))
+                .collect();
+            assert_eq!(
+                bindings,
+                vec![
+                    (0, 0, vk::DescriptorType::STORAGE_IMAGE),
+                    (0, 1, vk::DescriptorType::STORAGE_BUFFER),
+                ],
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "push constants")]
+    fn rejects_small_host_push_constants() {
+        let reflection = ShaderReflection::from_code(&compute_module(0x0001_0500));
+        get_push_constant_ranges(&[reflection.get_entry_point(c"main_cs")], &[(0, 16)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "push constant members")]
+    fn rejects_mismatched_push_constant_members() {
+        let reflection = ShaderReflection::from_code(&compute_module(0x0001_0500));
+        get_push_constant_ranges(&[reflection.get_entry_point(c"main_cs")], &[
+            (0, 16),
+            (16, 8),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "threads")]
+    fn rejects_mismatched_workgroup_size() {
+        let reflection = ShaderReflection::from_code(&compute_module(0x0001_0500));
+        reflection
+            .get_entry_point(c"main_cs")
+            .check_workgroup_size([16, 16, 1]);
+    }
+}
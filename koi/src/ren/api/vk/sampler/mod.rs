@@ -0,0 +1,102 @@
+use ash::{Device as DeviceHandle, vk};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SamplerKey {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    // NB! 0 disables anisotropic filtering; clamped to the device limit on creation
+    pub max_anisotropy: u32,
+}
+
+impl Default for SamplerKey {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            max_anisotropy: 0,
+        }
+    }
+}
+
+impl SamplerKey {
+    pub fn filter(
+        mut self,
+        mag_filter: vk::Filter,
+        min_filter: vk::Filter,
+        mipmap_mode: vk::SamplerMipmapMode,
+    ) -> Self {
+        self.mag_filter = mag_filter;
+        self.min_filter = min_filter;
+        self.mipmap_mode = mipmap_mode;
+        self
+    }
+
+    pub fn address_mode(
+        mut self,
+        address_mode_u: vk::SamplerAddressMode,
+        address_mode_v: vk::SamplerAddressMode,
+        address_mode_w: vk::SamplerAddressMode,
+    ) -> Self {
+        self.address_mode_u = address_mode_u;
+        self.address_mode_v = address_mode_v;
+        self.address_mode_w = address_mode_w;
+        self
+    }
+
+    pub fn anisotropy(mut self, max_anisotropy: u32) -> Self {
+        self.max_anisotropy = max_anisotropy;
+        self
+    }
+}
+
+pub struct SamplerCache {
+    pub samplers: HashMap<SamplerKey, vk::Sampler>,
+    pub max_anisotropy: u32,
+}
+
+impl SamplerCache {
+    pub fn new(max_anisotropy: u32) -> Self {
+        Self {
+            samplers: HashMap::new(),
+            max_anisotropy,
+        }
+    }
+
+    pub fn get(&mut self, device_handle: &DeviceHandle, key: SamplerKey) -> vk::Sampler {
+        let max_anisotropy = key.max_anisotropy.min(self.max_anisotropy);
+        *self.samplers.entry(key).or_insert_with(|| {
+            let create_info = vk::SamplerCreateInfo::default()
+                .mag_filter(key.mag_filter)
+                .min_filter(key.min_filter)
+                .mipmap_mode(key.mipmap_mode)
+                .address_mode_u(key.address_mode_u)
+                .address_mode_v(key.address_mode_v)
+                .address_mode_w(key.address_mode_w)
+                .anisotropy_enable(max_anisotropy > 1)
+                .max_anisotropy(max_anisotropy.max(1) as f32)
+                .min_lod(0.0)
+                .max_lod(vk::LOD_CLAMP_NONE);
+
+            unsafe {
+                device_handle
+                    .create_sampler(&create_info, None)
+                    .expect("koi::ren::vk::sampler - failed to create Sampler")
+            }
+        })
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        self.samplers
+            .drain()
+            .for_each(|(_, sampler)| unsafe { device_handle.destroy_sampler(sampler, None) });
+    }
+}
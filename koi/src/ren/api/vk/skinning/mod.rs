@@ -0,0 +1,526 @@
+use crate::scene::{Scene, skin};
+
+use super::{
+    ImmediateManager,
+    buffer::Buffer,
+    descriptor::{DescriptorSetAllocator, DescriptorSetLayoutBuilder, DescriptorSetPoolSizeRatio},
+    gpu_scene::{barrier, upload},
+    mesh::Mesh,
+    pipeline,
+    reflect::ShaderReflection,
+    resource_allocator::ResourceAllocator,
+};
+
+use ash::{Device as DeviceHandle, vk};
+use gpu_allocator::{MemoryLocation, vulkan as vka};
+use koi_gpu::{GpuLayout, SkinVertex, SkinningPushConstants, VERTEX_SIZE, VertexFormat};
+use spirv_std::glam::Mat4;
+use std::collections::HashMap;
+
+// NB! checked against the threads() declared by skinning.spv
+const SKINNING_GROUP_SIZE: u32 = 64;
+
+// One skinned or morphed node; offsets are in elements of its group's buffers, of the joint
+// matrices and of the morph weights.
+#[derive(Clone, Copy)]
+pub struct SkinningJob {
+    // scene node and skin indices; skin is None for nodes that are only morphed
+    pub node: usize,
+    pub skin: Option<usize>,
+    pub source_offset: u32,
+    pub output_offset: u32,
+    pub vertex_count: u32,
+    pub joint_offset: u32,
+    pub target_count: u32,
+    pub weight_offset: u32,
+    // the mesh's morph targets
+    pub target_descriptor_set: vk::DescriptorSet,
+}
+
+// The deformed nodes of one loaded scene and the buffers their dispatches read and write. Groups
+// are never rebuilt, so output addresses handed to the GPU scene stay valid as more scenes load.
+pub struct SkinningGroup {
+    pub jobs: Vec<SkinningJob>,
+    // bind-pose vertices and their joints and weights, once per deformed mesh
+    pub sources: Buffer,
+    pub skin_vertices: Buffer,
+    // deformed vertices, once per job; read by the mesh pass through its device address
+    pub skinned: Buffer,
+    pub descriptor_set: vk::DescriptorSet,
+    pub descriptor_set_allocator: DescriptorSetAllocator,
+}
+
+// Linear blend skinning and morph targets on the GPU: every frame, deformed nodes' bind-pose
+// vertices are displaced by their weighted morph targets, then skinned by their joint matrices, into
+// vertex buffers the mesh pass pulls from like any other.
+pub struct Skinning {
+    pub groups: Vec<SkinningGroup>,
+    // every group's joints and morph weights; written by update, uploaded by dispatch
+    pub joint_matrices: Vec<Mat4>,
+    pub morph_weights: Vec<f32>,
+    // one host-visible copy of each per frame in flight
+    pub joint_buffers: Vec<(Buffer, vka::Allocation)>,
+    pub weight_buffers: Vec<(Buffer, vka::Allocation)>,
+
+    // 0 sources, 1 skin vertices, 2 skinned vertices
+    pub group_descriptor_set_layout: vk::DescriptorSetLayout,
+    // 0 joint matrices, 1 morph weights; one set per frame in flight
+    pub pose_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub pose_descriptor_sets: Vec<vk::DescriptorSet>,
+    // 0 morph deltas; one set per morphed mesh, allocated by its group
+    pub target_descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_set_allocator: DescriptorSetAllocator,
+
+    pub pipeline_layout: vk::PipelineLayout,
+    pub shader: vk::ShaderModule,
+    pub pipeline: vk::Pipeline,
+}
+
+impl Skinning {
+    pub fn new(
+        device_handle: &DeviceHandle,
+        pipeline_cache: vk::PipelineCache,
+        buffering: u32,
+    ) -> Self {
+        let shader_code = include_bytes!(env!("skinning.spv"));
+        let reflection = ShaderReflection::new(shader_code);
+        let entry_point = reflection.get_entry_point(c"main_cs");
+        entry_point.check_workgroup_size([SKINNING_GROUP_SIZE, 1, 1]);
+
+        let [
+            group_descriptor_set_layout,
+            pose_descriptor_set_layout,
+            target_descriptor_set_layout,
+        ] = [0, 1, 2].map(|set| {
+            DescriptorSetLayoutBuilder::from_entry_points(&[entry_point], set)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            )
+        });
+
+        let mut descriptor_set_allocator =
+            DescriptorSetAllocator::new(device_handle, buffering, &[
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::STORAGE_BUFFER, 2.0),
+            ]);
+        let pose_descriptor_sets = (0..buffering)
+            .map(|_| {
+                descriptor_set_allocator.allocate(device_handle, &[pose_descriptor_set_layout])
+            })
+            .collect();
+
+        let pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            device_handle,
+            &[entry_point],
+            &[
+                group_descriptor_set_layout,
+                pose_descriptor_set_layout,
+                target_descriptor_set_layout,
+            ],
+            SkinningPushConstants::MEMBERS,
+        );
+        let shader = pipeline::load_shader_module(device_handle, shader_code, None);
+        let pipeline = pipeline::create_compute_pipeline(
+            device_handle,
+            pipeline_cache,
+            shader,
+            pipeline_layout,
+        );
+
+        Self {
+            groups: vec![],
+            joint_matrices: vec![],
+            morph_weights: vec![],
+            joint_buffers: vec![],
+            weight_buffers: vec![],
+
+            group_descriptor_set_layout,
+            pose_descriptor_set_layout,
+            pose_descriptor_sets,
+            target_descriptor_set_layout,
+            descriptor_set_allocator,
+
+            pipeline_layout,
+            shader,
+            pipeline,
+        }
+    }
+
+    // Creates a group for the scene's skinned and morphed nodes; meshes are the scene's, as
+    // uploaded. Returns, per scene node, the address of its deformed vertices; the mesh pass reads
+    // those instead of the mesh's own vertex buffer.
+    // NB! expects the device to be idle; joint and weight buffers are replaced
+    pub fn load(
+        &mut self,
+        device_handle: &DeviceHandle,
+        resource_allocator: &mut ResourceAllocator,
+        immediate_manager: &mut ImmediateManager,
+        scene: &Scene,
+        meshes: &[Mesh],
+    ) -> Vec<Option<vk::DeviceAddress>> {
+        let mut addresses = vec![None; scene.nodes.len()];
+        let mut jobs = vec![];
+        let mut sources = vec![];
+        let mut skin_vertices = vec![];
+        // meshes shared by several deformed nodes are uploaded once
+        let mut source_offsets = HashMap::new();
+        let mut output_count = 0;
+
+        for (node_index, node) in scene.nodes.iter().enumerate() {
+            let Some(mesh_index) = node.mesh else {
+                continue;
+            };
+            let mesh = &scene.meshes[mesh_index];
+            let skin = node.skin.filter(|_| !mesh.skin_vertices.is_empty());
+            if skin.is_none() && mesh.morph_targets.is_empty() {
+                continue;
+            }
+            if mesh.vertex_format != VertexFormat::Full {
+                log::warn!(
+                    "koi::ren::vk::skinning - Mesh {} isn't deformed; skinning needs full vertices",
+                    mesh.name
+                );
+                continue;
+            }
+
+            let source_offset = *source_offsets.entry(mesh_index).or_insert_with(|| {
+                let offset = sources.len() as u32;
+                sources.extend_from_slice(&mesh.vertices);
+                // NB! kept parallel to sources; unskinned meshes never read theirs
+                match mesh.skin_vertices.is_empty() {
+                    true => skin_vertices.resize(sources.len(), SkinVertex::default()),
+                    false => skin_vertices.extend_from_slice(&mesh.skin_vertices),
+                }
+                offset
+            });
+            let vertex_count = mesh.vertices.len() as u32;
+            let joint_count = skin.map_or(0, |skin| scene.skins[skin].joints.len());
+            let target_count = mesh.morph_targets.len();
+            jobs.push(SkinningJob {
+                node: node_index,
+                skin,
+                source_offset,
+                output_offset: output_count,
+                vertex_count,
+                joint_offset: self.joint_matrices.len() as u32,
+                target_count: target_count as u32,
+                weight_offset: self.morph_weights.len() as u32,
+                target_descriptor_set: vk::DescriptorSet::null(),
+            });
+            self.joint_matrices
+                .resize(self.joint_matrices.len() + joint_count, Mat4::IDENTITY);
+            self.morph_weights
+                .resize(self.morph_weights.len() + target_count, 0.0);
+            output_count += vertex_count;
+        }
+
+        if jobs.is_empty() {
+            return addresses;
+        }
+
+        let sources = upload(
+            device_handle,
+            resource_allocator,
+            immediate_manager,
+            &sources,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            "skinning_sources",
+        );
+        let skin_vertices = upload(
+            device_handle,
+            resource_allocator,
+            immediate_manager,
+            &skin_vertices,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            "skinning_skin_vertices",
+        );
+        let skinned = Buffer::new(
+            device_handle,
+            &mut resource_allocator.handle,
+            &mut resource_allocator.global_resources,
+            output_count as u64 * VERTEX_SIZE,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            "skinning_skinned",
+            MemoryLocation::GpuOnly,
+        );
+        let skinned_address = unsafe {
+            device_handle.get_buffer_device_address(
+                &vk::BufferDeviceAddressInfo::default().buffer(skinned.handle),
+            )
+        };
+        for job in &jobs {
+            addresses[job.node] = Some(skinned_address + job.output_offset as u64 * VERTEX_SIZE);
+        }
+
+        // the group's set, one per morphed mesh, and one for jobs without targets
+        let mut descriptor_set_allocator =
+            DescriptorSetAllocator::new(device_handle, source_offsets.len() as u32 + 2, &[
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::STORAGE_BUFFER, 3.0),
+            ]);
+        let descriptor_set =
+            descriptor_set_allocator.allocate(device_handle, &[self.group_descriptor_set_layout]);
+        write_buffer_descriptors(device_handle, descriptor_set, &[
+            &sources,
+            &skin_vertices,
+            &skinned,
+        ]);
+
+        // NB! jobs without targets never read set 2, but it must be bound; any buffer will do
+        let mut target_descriptor_sets = HashMap::new();
+        let mut get_target_descriptor_set = |mesh_index: Option<usize>, buffer: &Buffer| {
+            *target_descriptor_sets.entry(mesh_index).or_insert_with(|| {
+                let descriptor_set = descriptor_set_allocator
+                    .allocate(device_handle, &[self.target_descriptor_set_layout]);
+                write_buffer_descriptors(device_handle, descriptor_set, &[buffer]);
+                descriptor_set
+            })
+        };
+        for job in &mut jobs {
+            let mesh_index = scene.nodes[job.node].mesh;
+            job.target_descriptor_set =
+                match mesh_index.and_then(|mesh_index| meshes[mesh_index].target_buffer.as_ref()) {
+                    Some(target_buffer) => get_target_descriptor_set(mesh_index, target_buffer),
+                    None => get_target_descriptor_set(None, &sources),
+                };
+        }
+
+        self.groups.push(SkinningGroup {
+            jobs,
+            sources,
+            skin_vertices,
+            skinned,
+            descriptor_set,
+            descriptor_set_allocator,
+        });
+        self.create_pose_buffers(device_handle, &mut resource_allocator.handle);
+        addresses
+    }
+
+    fn create_pose_buffers(
+        &mut self,
+        device_handle: &DeviceHandle,
+        allocator: &mut vka::Allocator,
+    ) {
+        self.drop_pose_buffers(device_handle, allocator);
+        // NB! at least one element each; empty buffers are invalid
+        let joints_size = size_of_val(self.joint_matrices.as_slice()).max(size_of::<Mat4>());
+        let weights_size = size_of_val(self.morph_weights.as_slice()).max(size_of::<f32>());
+        for &descriptor_set in &self.pose_descriptor_sets {
+            let (joint_buffer, joint_allocation) = Buffer::create(
+                device_handle,
+                allocator,
+                joints_size as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                "skinning_joint_matrices",
+                MemoryLocation::CpuToGpu,
+            );
+            let (weight_buffer, weight_allocation) = Buffer::create(
+                device_handle,
+                allocator,
+                weights_size as u64,
+                vk::BufferUsageFlags::STORAGE_BUFFER,
+                "skinning_morph_weights",
+                MemoryLocation::CpuToGpu,
+            );
+            write_buffer_descriptors(device_handle, descriptor_set, &[
+                &joint_buffer,
+                &weight_buffer,
+            ]);
+            self.joint_buffers.push((joint_buffer, joint_allocation));
+            self.weight_buffers.push((weight_buffer, weight_allocation));
+        }
+    }
+
+    // Poses the last loaded scene's deformed nodes from their skins and weights; expects its world
+    // transforms to be up to date.
+    // NB! earlier scenes keep the pose they were last updated with
+    pub fn update(&mut self, scene: &Scene) {
+        let Some(group) = self.groups.last() else {
+            return;
+        };
+        for job in &group.jobs {
+            let node = &scene.nodes[job.node];
+            if let Some(skin) = job.skin {
+                let skin = &scene.skins[skin];
+                let start = job.joint_offset as usize;
+                skin::write_joint_matrices(
+                    skin,
+                    &scene.nodes,
+                    node,
+                    &mut self.joint_matrices[start..start + skin.joints.len()],
+                );
+            }
+
+            let start = job.weight_offset as usize;
+            let weights = &mut self.morph_weights[start..start + job.target_count as usize];
+            weights.fill(0.0);
+            let count = weights.len().min(node.weights.len());
+            weights[..count].copy_from_slice(&node.weights[..count]);
+        }
+    }
+
+    // Uploads the joint matrices and morph weights and deforms every group; record before the mesh
+    // pass. Expects the frame's render fence to have been waited on.
+    pub fn dispatch(
+        &mut self,
+        device_handle: &DeviceHandle,
+        command_buffer: vk::CommandBuffer,
+        frame_index: usize,
+    ) {
+        if self.groups.is_empty() {
+            return;
+        }
+        let (buffer, allocation) = &mut self.joint_buffers[frame_index];
+        buffer.upload(&self.joint_matrices, allocation, 0);
+        let (buffer, allocation) = &mut self.weight_buffers[frame_index];
+        buffer.upload(&self.morph_weights, allocation, 0);
+
+        // previous frames' vertex reads must finish before the skinned vertices are overwritten
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::VERTEX_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_READ,
+            ),
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+        );
+
+        unsafe {
+            device_handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline,
+            );
+            device_handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.pipeline_layout,
+                1,
+                &[self.pose_descriptor_sets[frame_index]],
+                &[],
+            );
+        }
+        for group in &self.groups {
+            unsafe {
+                device_handle.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline_layout,
+                    0,
+                    &[group.descriptor_set],
+                    &[],
+                );
+            }
+            for job in &group.jobs {
+                let push_constants = SkinningPushConstants::default()
+                    .offsets(
+                        job.source_offset,
+                        job.output_offset,
+                        job.vertex_count,
+                        job.joint_offset,
+                    )
+                    .morph(job.target_count, job.weight_offset, job.skin.is_some());
+                unsafe {
+                    device_handle.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::COMPUTE,
+                        self.pipeline_layout,
+                        2,
+                        &[job.target_descriptor_set],
+                        &[],
+                    );
+                    pipeline::push_constants(
+                        device_handle,
+                        command_buffer,
+                        self.pipeline_layout,
+                        vk::ShaderStageFlags::COMPUTE,
+                        &push_constants,
+                    );
+                    device_handle.cmd_dispatch(
+                        command_buffer,
+                        job.vertex_count.div_ceil(SKINNING_GROUP_SIZE),
+                        1,
+                        1,
+                    );
+                }
+            }
+        }
+
+        barrier(
+            device_handle,
+            command_buffer,
+            (
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            ),
+            (
+                vk::PipelineStageFlags2::VERTEX_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_READ,
+            ),
+        );
+    }
+
+    fn drop_pose_buffers(&mut self, device_handle: &DeviceHandle, allocator: &mut vka::Allocator) {
+        self.joint_buffers
+            .drain(..)
+            .chain(self.weight_buffers.drain(..))
+            .for_each(|(buffer, allocation)| {
+                unsafe { device_handle.destroy_buffer(buffer.handle, None) };
+                allocator
+                    .free(allocation)
+                    .expect("koi::ren::vk::skinning - failed to Free Pose Buffer allocation");
+            });
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle, allocator: &mut vka::Allocator) {
+        self.drop_pose_buffers(device_handle, allocator);
+        self.groups
+            .iter_mut()
+            .for_each(|group| group.descriptor_set_allocator.drop(device_handle));
+        self.descriptor_set_allocator.drop(device_handle);
+        unsafe {
+            device_handle.destroy_pipeline(self.pipeline, None);
+            device_handle.destroy_shader_module(self.shader, None);
+            device_handle.destroy_pipeline_layout(self.pipeline_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.target_descriptor_set_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.pose_descriptor_set_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.group_descriptor_set_layout, None);
+        }
+    }
+}
+
+// Points consecutive storage buffer bindings, from 0, at whole buffers.
+fn write_buffer_descriptors(
+    device_handle: &DeviceHandle,
+    descriptor_set: vk::DescriptorSet,
+    buffers: &[&Buffer],
+) {
+    let buffer_infos: Vec<[vk::DescriptorBufferInfo; 1]> = buffers
+        .iter()
+        .map(|buffer| {
+            [vk::DescriptorBufferInfo::default()
+                .buffer(buffer.handle)
+                .offset(0)
+                .range(vk::WHOLE_SIZE)]
+        })
+        .collect();
+    let descriptor_writes: Vec<vk::WriteDescriptorSet> = buffer_infos
+        .iter()
+        .enumerate()
+        .map(|(binding, buffer_info)| {
+            vk::WriteDescriptorSet::default()
+                .dst_binding(binding as u32)
+                .dst_set(descriptor_set)
+                .descriptor_count(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .buffer_info(buffer_info)
+        })
+        .collect();
+    unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+}
@@ -0,0 +1,608 @@
+use crate::scene::{
+    compressed::{BlockFormat, CompressedImage},
+    texture::{self as scene_texture, TextureRole},
+};
+
+use super::{
+    ImmediateManager,
+    buffer::Buffer,
+    descriptor::{DescriptorSetAllocator, DescriptorSetLayoutBuilder, DescriptorSetPoolSizeRatio},
+    device::Device,
+    image::{self, Image},
+    pipeline,
+    reflect::{EntryPoint, ShaderReflection},
+    resource_allocator::ResourceAllocator,
+    sampler::SamplerKey,
+};
+
+use ash::{Device as DeviceHandle, vk};
+use gpu_allocator::MemoryLocation;
+use koi_gpu::{ComputePushConstants, GpuLayout, MAX_TEXTURES};
+use spirv_std::glam::Vec4;
+
+// NB! one set per downsampled level; 16 levels covers 32k textures
+const MIP_GENERATOR_MAX_SETS: u32 = 16;
+// NB! checked against the threads() declared by mipmap.spv
+const MIPMAP_GROUP_SIZE: u32 = 8;
+const MAX_ANISOTROPY: u32 = 16;
+
+pub struct Texture {
+    pub image: usize,
+    pub sampler: vk::Sampler,
+}
+
+pub fn get_format(role: TextureRole) -> vk::Format {
+    match role {
+        TextureRole::Color => vk::Format::R8G8B8A8_SRGB,
+        TextureRole::Data => vk::Format::R8G8B8A8_UNORM,
+    }
+}
+
+pub fn get_compressed_format(image: &CompressedImage) -> vk::Format {
+    match (image.format, image.srgb, image.signed) {
+        (BlockFormat::Bc1, false, _) => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        (BlockFormat::Bc1, true, _) => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        (BlockFormat::Bc2, false, _) => vk::Format::BC2_UNORM_BLOCK,
+        (BlockFormat::Bc2, true, _) => vk::Format::BC2_SRGB_BLOCK,
+        (BlockFormat::Bc3, false, _) => vk::Format::BC3_UNORM_BLOCK,
+        (BlockFormat::Bc3, true, _) => vk::Format::BC3_SRGB_BLOCK,
+        (BlockFormat::Bc4, _, false) => vk::Format::BC4_UNORM_BLOCK,
+        (BlockFormat::Bc4, _, true) => vk::Format::BC4_SNORM_BLOCK,
+        (BlockFormat::Bc5, _, false) => vk::Format::BC5_UNORM_BLOCK,
+        (BlockFormat::Bc5, _, true) => vk::Format::BC5_SNORM_BLOCK,
+        (BlockFormat::Bc6h, _, false) => vk::Format::BC6H_UFLOAT_BLOCK,
+        (BlockFormat::Bc6h, _, true) => vk::Format::BC6H_SFLOAT_BLOCK,
+        (BlockFormat::Bc7, false, _) => vk::Format::BC7_UNORM_BLOCK,
+        (BlockFormat::Bc7, true, _) => vk::Format::BC7_SRGB_BLOCK,
+    }
+}
+
+pub fn supports_compressed_format(device: &Device, format: vk::Format) -> bool {
+    device.texture_compression_bc
+        && device.supports_format_features(
+            format,
+            vk::FormatFeatureFlags::SAMPLED_IMAGE
+                | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR
+                | vk::FormatFeatureFlags::TRANSFER_DST,
+        )
+}
+
+pub fn get_sampler_key(sampler: &scene_texture::Sampler) -> SamplerKey {
+    let filter = |filter: scene_texture::Filter| match filter {
+        scene_texture::Filter::Nearest => vk::Filter::NEAREST,
+        scene_texture::Filter::Linear => vk::Filter::LINEAR,
+    };
+    let mipmap_mode = match sampler.mipmap_filter {
+        scene_texture::Filter::Nearest => vk::SamplerMipmapMode::NEAREST,
+        scene_texture::Filter::Linear => vk::SamplerMipmapMode::LINEAR,
+    };
+    let address_mode = |address_mode: scene_texture::AddressMode| match address_mode {
+        scene_texture::AddressMode::Repeat => vk::SamplerAddressMode::REPEAT,
+        scene_texture::AddressMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        scene_texture::AddressMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+    };
+
+    SamplerKey::default()
+        .filter(
+            filter(sampler.mag_filter),
+            filter(sampler.min_filter),
+            mipmap_mode,
+        )
+        .address_mode(
+            address_mode(sampler.address_mode_u),
+            address_mode(sampler.address_mode_v),
+            vk::SamplerAddressMode::REPEAT,
+        )
+        .anisotropy(MAX_ANISOTROPY)
+}
+
+// Storage-compatible alias used to write sRGB mips from compute.
+fn get_storage_format(format: vk::Format) -> vk::Format {
+    match format {
+        vk::Format::R8G8B8A8_SRGB => vk::Format::R8G8B8A8_UNORM,
+        vk::Format::B8G8R8A8_SRGB => vk::Format::B8G8R8A8_UNORM,
+        _ => format,
+    }
+}
+
+// Every loaded texture as one partially bound array of combined image samplers, indexed by
+// Instance::textures in the mesh shaders. Slots match DrawManager::textures.
+pub struct TextureTable {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_set: vk::DescriptorSet,
+    pub descriptor_set_allocator: DescriptorSetAllocator,
+}
+
+impl TextureTable {
+    pub fn new(device_handle: &DeviceHandle, mesh_entry_points: &[&EntryPoint]) -> Self {
+        let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND];
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(&binding_flags);
+        let descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(mesh_entry_points, 2)
+                .descriptor_count(0, MAX_TEXTURES)
+                .build(
+                    device_handle,
+                    vk::ShaderStageFlags::empty(),
+                    None,
+                    Some(&mut binding_flags_info),
+                );
+        let mut descriptor_set_allocator =
+            DescriptorSetAllocator::new(device_handle, 1, &[DescriptorSetPoolSizeRatio::new(
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                MAX_TEXTURES as f32,
+            )]);
+        let descriptor_set =
+            descriptor_set_allocator.allocate(device_handle, &[descriptor_set_layout]);
+
+        Self {
+            descriptor_set_layout,
+            descriptor_set,
+            descriptor_set_allocator,
+        }
+    }
+
+    // Writes slots first_texture.. from textures[first_texture..].
+    // NB! the set must not be in use; callers wait for the device to idle
+    pub fn update(
+        &self,
+        device_handle: &DeviceHandle,
+        images: &[Image],
+        textures: &[Texture],
+        first_texture: usize,
+    ) {
+        if textures.len() > MAX_TEXTURES as usize {
+            log::warn!(
+                "koi::ren::vk::texture - {} Textures exceed the table; only {MAX_TEXTURES} are bound",
+                textures.len()
+            );
+        }
+        let image_infos: Vec<_> = textures
+            .iter()
+            .take(MAX_TEXTURES as usize)
+            .skip(first_texture)
+            .map(|texture| {
+                vk::DescriptorImageInfo::default()
+                    .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+                    .image_view(images[texture.image].view)
+                    .sampler(texture.sampler)
+            })
+            .collect();
+        if image_infos.is_empty() {
+            return;
+        }
+
+        let descriptor_writes = [vk::WriteDescriptorSet::default()
+            .dst_binding(0)
+            .dst_set(self.descriptor_set)
+            .dst_array_element(first_texture as u32)
+            .descriptor_count(image_infos.len() as u32)
+            .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
+            .image_info(&image_infos)];
+        unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        self.descriptor_set_allocator.drop(device_handle);
+        unsafe { device_handle.destroy_descriptor_set_layout(self.descriptor_set_layout, None) };
+    }
+}
+
+// Compute fallback for formats that can't be blitted with linear filtering.
+pub struct MipGenerator {
+    pub descriptor_set_layout: vk::DescriptorSetLayout,
+    pub descriptor_set_allocator: DescriptorSetAllocator,
+    pub pipeline_layout: vk::PipelineLayout,
+    pub pipeline: vk::Pipeline,
+    pub shader: vk::ShaderModule,
+}
+
+impl MipGenerator {
+    pub fn new(device_handle: &DeviceHandle, pipeline_cache: vk::PipelineCache) -> Self {
+        let shader_code = include_bytes!(env!("mipmap.spv"));
+        let reflection = ShaderReflection::new(shader_code);
+        let entry_point = reflection.get_entry_point(c"main_cs");
+        entry_point.check_workgroup_size([MIPMAP_GROUP_SIZE, MIPMAP_GROUP_SIZE, 1]);
+
+        let descriptor_set_layout =
+            DescriptorSetLayoutBuilder::from_entry_points(&[entry_point], 0)
+                .build::<vk::DescriptorSetLayoutBindingFlagsCreateInfo>(
+                device_handle,
+                vk::ShaderStageFlags::empty(),
+                None,
+                None,
+            );
+        let descriptor_set_allocator =
+            DescriptorSetAllocator::new(device_handle, MIP_GENERATOR_MAX_SETS, &[
+                DescriptorSetPoolSizeRatio::new(vk::DescriptorType::STORAGE_IMAGE, 2.0),
+            ]);
+
+        let pipeline_layout = pipeline::create_reflected_pipeline_layout(
+            device_handle,
+            &[entry_point],
+            &[descriptor_set_layout],
+            ComputePushConstants::MEMBERS,
+        );
+
+        let shader = pipeline::load_shader_module(device_handle, shader_code, None);
+        let pipeline = pipeline::create_compute_pipeline(
+            device_handle,
+            pipeline_cache,
+            shader,
+            pipeline_layout,
+        );
+
+        Self {
+            descriptor_set_layout,
+            descriptor_set_allocator,
+            pipeline_layout,
+            pipeline,
+            shader,
+        }
+    }
+
+    // Expects every level in TRANSFER_DST_OPTIMAL; leaves every level in SHADER_READ_ONLY_OPTIMAL.
+    pub fn generate(
+        &mut self,
+        device_handle: &DeviceHandle,
+        immediate_manager: &mut ImmediateManager,
+        image: &Image,
+    ) {
+        let storage_format = get_storage_format(image.format);
+        let srgb = storage_format != image.format;
+
+        let views: Vec<_> = (0..image.mip_levels)
+            .map(|mip_level| {
+                image::create_view(
+                    device_handle,
+                    image,
+                    vk::ImageViewType::TYPE_2D,
+                    storage_format,
+                    mip_level,
+                    1,
+                )
+            })
+            .collect();
+
+        let descriptor_sets: Vec<_> = (1..image.mip_levels)
+            .map(|mip_level| {
+                let descriptor_set = self
+                    .descriptor_set_allocator
+                    .allocate(device_handle, &[self.descriptor_set_layout]);
+                let src_info = [vk::DescriptorImageInfo::default()
+                    .image_layout(vk::ImageLayout::GENERAL)
+                    .image_view(views[mip_level as usize - 1])];
+                let dst_info = [vk::DescriptorImageInfo::default()
+                    .image_layout(vk::ImageLayout::GENERAL)
+                    .image_view(views[mip_level as usize])];
+                let descriptor_writes = [
+                    vk::WriteDescriptorSet::default()
+                        .dst_binding(0)
+                        .dst_set(descriptor_set)
+                        .descriptor_count(1)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(&src_info),
+                    vk::WriteDescriptorSet::default()
+                        .dst_binding(1)
+                        .dst_set(descriptor_set)
+                        .descriptor_count(1)
+                        .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                        .image_info(&dst_info),
+                ];
+                unsafe { device_handle.update_descriptor_sets(&descriptor_writes, &[]) };
+                descriptor_set
+            })
+            .collect();
+
+        // data_0: x = average in linear space
+        let push_constants = ComputePushConstants::default().data_0(Vec4::new(
+            if srgb { 1.0 } else { 0.0 },
+            0.0,
+            0.0,
+            0.0,
+        ));
+
+        immediate_manager.submit(device_handle, &|command_buffer: vk::CommandBuffer| {
+            image::transition_mips(
+                device_handle,
+                command_buffer,
+                image.handle,
+                0,
+                image.mip_levels,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                vk::ImageLayout::GENERAL,
+            );
+
+            unsafe {
+                device_handle.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    self.pipeline,
+                );
+                pipeline::push_constants(
+                    device_handle,
+                    command_buffer,
+                    self.pipeline_layout,
+                    vk::ShaderStageFlags::COMPUTE,
+                    &push_constants,
+                );
+            }
+
+            for mip_level in 1..image.mip_levels {
+                let extent = image::get_mip_extent(image.extent_2d, mip_level);
+                unsafe {
+                    device_handle.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::COMPUTE,
+                        self.pipeline_layout,
+                        0,
+                        &[descriptor_sets[mip_level as usize - 1]],
+                        &[],
+                    );
+                    device_handle.cmd_dispatch(
+                        command_buffer,
+                        extent.width.div_ceil(MIPMAP_GROUP_SIZE),
+                        extent.height.div_ceil(MIPMAP_GROUP_SIZE),
+                        1,
+                    );
+                }
+                // make level N visible before it is read to produce N+1
+                image::transition_mips(
+                    device_handle,
+                    command_buffer,
+                    image.handle,
+                    mip_level,
+                    1,
+                    vk::ImageLayout::GENERAL,
+                    vk::ImageLayout::GENERAL,
+                );
+            }
+
+            image::transition_mips(
+                device_handle,
+                command_buffer,
+                image.handle,
+                0,
+                image.mip_levels,
+                vk::ImageLayout::GENERAL,
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            );
+        });
+
+        // submit waits on its fence; the sets and views are no longer in use
+        self.descriptor_set_allocator.reset_pool(device_handle);
+        views
+            .into_iter()
+            .for_each(|view| unsafe { device_handle.destroy_image_view(view, None) });
+    }
+
+    pub fn drop(&mut self, device_handle: &DeviceHandle) {
+        self.descriptor_set_allocator.drop(device_handle);
+        unsafe {
+            device_handle.destroy_shader_module(self.shader, None);
+            device_handle.destroy_pipeline(self.pipeline, None);
+            device_handle.destroy_pipeline_layout(self.pipeline_layout, None);
+            device_handle.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        }
+    }
+}
+
+// Uploads RGBA8 pixels into a fully mipmapped, sampled image.
+pub fn upload(
+    device: &Device,
+    resource_allocator: &mut ResourceAllocator,
+    immediate_manager: &mut ImmediateManager,
+    mip_generator: &mut MipGenerator,
+    pixels: &[u8],
+    extent: vk::Extent2D,
+    format: vk::Format,
+) -> Image {
+    let device_handle = &device.handle;
+    let mip_levels = image::get_mip_levels(extent);
+
+    let blit_features = vk::FormatFeatureFlags::BLIT_SRC
+        | vk::FormatFeatureFlags::BLIT_DST
+        | vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR;
+    let use_blit = device.supports_format_features(format, blit_features);
+
+    let mut usage = vk::ImageUsageFlags::SAMPLED
+        | vk::ImageUsageFlags::TRANSFER_SRC
+        | vk::ImageUsageFlags::TRANSFER_DST;
+    let mut flags = vk::ImageCreateFlags::empty();
+    if !use_blit {
+        usage |= vk::ImageUsageFlags::STORAGE;
+        if get_storage_format(format) != format {
+            flags |= vk::ImageCreateFlags::MUTABLE_FORMAT | vk::ImageCreateFlags::EXTENDED_USAGE;
+        }
+    }
+
+    let (image, allocation) = Image::create_mipmapped(
+        device_handle,
+        &mut resource_allocator.handle,
+        format,
+        vk::Extent3D::default()
+            .width(extent.width)
+            .height(extent.height)
+            .depth(1),
+        usage,
+        mip_levels,
+        flags,
+    );
+    resource_allocator
+        .global_resources
+        .add_image(image.handle, image.view, allocation);
+
+    let (mut staging_buffer, mut staging_allocation) = Buffer::create(
+        device_handle,
+        &mut resource_allocator.handle,
+        pixels.len() as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        "texture_staging",
+        MemoryLocation::CpuToGpu,
+    );
+    staging_buffer.upload(pixels, &mut staging_allocation, 0);
+
+    immediate_manager.submit(device_handle, &|command_buffer: vk::CommandBuffer| {
+        image::transition_mips(
+            device_handle,
+            command_buffer,
+            image.handle,
+            0,
+            mip_levels,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+
+        let regions = [vk::BufferImageCopy::default()
+            .image_subresource(
+                vk::ImageSubresourceLayers::default()
+                    .aspect_mask(vk::ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .layer_count(1),
+            )
+            .image_extent(image.extent_3d)];
+        unsafe {
+            device_handle.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer.handle,
+                image.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            )
+        };
+
+        if use_blit {
+            image::generate_mipmaps(device_handle, command_buffer, &image);
+        }
+    });
+
+    if !use_blit {
+        mip_generator.generate(device_handle, immediate_manager, &image);
+    }
+
+    unsafe { device_handle.destroy_buffer(staging_buffer.handle, None) };
+    resource_allocator
+        .handle
+        .free(staging_allocation)
+        .expect("koi::ren::vk::texture - failed to Free Staging Buffer allocation");
+
+    image
+}
+
+// Uploads pre-built mip levels as-is; block-compressed formats can't be blitted, so no levels are
+// generated here.
+pub fn upload_compressed(
+    device: &Device,
+    resource_allocator: &mut ResourceAllocator,
+    immediate_manager: &mut ImmediateManager,
+    compressed_image: &CompressedImage,
+    format: vk::Format,
+) -> Image {
+    let device_handle = &device.handle;
+    let mip_levels = compressed_image.levels.len() as u32;
+
+    let (image, allocation) = Image::create_mipmapped(
+        device_handle,
+        &mut resource_allocator.handle,
+        format,
+        vk::Extent3D::default()
+            .width(compressed_image.width)
+            .height(compressed_image.height)
+            .depth(1),
+        vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        mip_levels,
+        vk::ImageCreateFlags::empty(),
+    );
+    resource_allocator
+        .global_resources
+        .add_image(image.handle, image.view, allocation);
+
+    let (mut staging_buffer, mut staging_allocation) = Buffer::create(
+        device_handle,
+        &mut resource_allocator.handle,
+        compressed_image.levels.iter().map(Vec::len).sum::<usize>() as vk::DeviceSize,
+        vk::BufferUsageFlags::TRANSFER_SRC,
+        "texture_staging",
+        MemoryLocation::CpuToGpu,
+    );
+    // NB! levels are padded to the buffer alignment; grow to fit once it's known
+    let padded_size: usize = compressed_image
+        .levels
+        .iter()
+        .map(|level| level.len().next_multiple_of(staging_buffer.min_alignment))
+        .sum();
+    if padded_size as vk::DeviceSize > staging_buffer.size {
+        staging_allocation = staging_buffer.resize(
+            device_handle,
+            &mut resource_allocator.handle,
+            staging_allocation,
+            padded_size as vk::DeviceSize,
+            "texture_staging",
+        );
+    }
+
+    let mut offset = 0;
+    let regions: Vec<_> = compressed_image
+        .levels
+        .iter()
+        .enumerate()
+        .map(|(mip_level, level)| {
+            let record = staging_buffer.upload(level, &mut staging_allocation, offset);
+            offset = record.copy_end_offset_padded;
+
+            let extent = image::get_mip_extent(image.extent_2d, mip_level as u32);
+            vk::BufferImageCopy::default()
+                .buffer_offset(record.copy_start_offset as vk::DeviceSize)
+                .image_subresource(
+                    vk::ImageSubresourceLayers::default()
+                        .aspect_mask(vk::ImageAspectFlags::COLOR)
+                        .mip_level(mip_level as u32)
+                        .layer_count(1),
+                )
+                .image_extent(
+                    vk::Extent3D::default()
+                        .width(extent.width)
+                        .height(extent.height)
+                        .depth(1),
+                )
+        })
+        .collect();
+
+    immediate_manager.submit(device_handle, &|command_buffer: vk::CommandBuffer| {
+        image::transition_mips(
+            device_handle,
+            command_buffer,
+            image.handle,
+            0,
+            mip_levels,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        unsafe {
+            device_handle.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer.handle,
+                image.handle,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            )
+        };
+        image::transition_mips(
+            device_handle,
+            command_buffer,
+            image.handle,
+            0,
+            mip_levels,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        );
+    });
+
+    unsafe { device_handle.destroy_buffer(staging_buffer.handle, None) };
+    resource_allocator
+        .handle
+        .free(staging_allocation)
+        .expect("koi::ren::vk::texture - failed to Free Staging Buffer allocation");
+
+    image
+}
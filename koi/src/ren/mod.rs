@@ -1,8 +1,14 @@
 pub mod api;
 pub mod settings;
+#[cfg(feature = "hot-reload")]
+pub mod shader_watcher;
 pub mod window;
 
-use crate::{app::info::Info, imgui::ImGui, scene::Scene};
+use crate::{
+    app::info::Info,
+    imgui::ImGui,
+    scene::{Scene, environment::Environment},
+};
 use settings::{Resolution, Settings};
 use window::Window;
 use winit::window::Window as WindowHandle;
@@ -10,7 +16,11 @@ use winit::window::Window as WindowHandle;
 pub trait Renderer {
     fn new(info: &Info, settings: Settings, window: Window) -> Self;
     fn load_scene(&mut self, scene: &Scene);
+    fn load_environment(&mut self, environment: &Environment);
     fn handle_resize(&mut self, resolution: &Resolution);
+    // Moves instances and poses skinned and morphed meshes; expects the scene's world transforms
+    // to be up to date.
+    fn update_scene(&mut self, scene: &Scene);
     fn draw(&mut self, imgui: &mut ImGui);
 }
 
@@ -27,7 +37,8 @@ pub fn new(info: &Info, window_handle: &WindowHandle) -> Handle {
         Window::new(window_handle).expect("koi::ren::new - failed to create window handle");
     let settings = Settings::default()
         .resolution(Resolution::new(1920, 1080))
-        .buffering(2);
+        .buffering(2)
+        .pipeline_cache_path(std::env::temp_dir().join("koi_pipeline_cache.bin"));
 
     #[cfg(feature = "directx")]
     let api = api::dx::Renderer::new(info, settings, window);
@@ -42,10 +53,18 @@ impl Handle {
         self.api.load_scene(scene);
     }
 
+    pub fn load_environment(&mut self, environment: &Environment) {
+        self.api.load_environment(environment);
+    }
+
     pub fn handle_resize(&mut self, width: u32, height: u32) {
         self.api.handle_resize(&Resolution::new(width, height));
     }
 
+    pub fn update_scene(&mut self, scene: &Scene) {
+        self.api.update_scene(scene);
+    }
+
     pub fn draw(&mut self, imgui: &mut ImGui) {
         self.api.draw(imgui);
     }
@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 #[derive(Clone, Copy, Debug)]
 pub struct Resolution {
     pub width: u32,
@@ -24,6 +26,8 @@ impl Resolution {
 pub struct Settings {
     pub resolution: Resolution,
     pub buffering: u32,
+    // where compiled pipelines persist between runs; None keeps them for this run only
+    pub pipeline_cache_path: Option<PathBuf>,
 }
 
 #[allow(unused)]
@@ -37,4 +41,9 @@ impl Settings {
         self.buffering = buffering;
         self
     }
+
+    pub fn pipeline_cache_path(mut self, path: PathBuf) -> Self {
+        self.pipeline_cache_path = Some(path);
+        self
+    }
 }
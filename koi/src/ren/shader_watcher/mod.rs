@@ -0,0 +1,258 @@
+use spirv_builder::{Capability, MetadataPrintout, SpirvBuilder};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Shader names match the build.rs outputs without their extension: the crate directory name for
+// rust-gpu shaders, the file name up to its first dot for GLSL and HLSL ones.
+pub enum ShaderEvent {
+    Compiling(String),
+    Compiled(String, Vec<u32>),
+    Failed(String, String),
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum ShaderSource {
+    // a shader crate's directory
+    RustGpu(PathBuf),
+    // a .vert, .frag or .comp GLSL file or a .<stage>.hlsl HLSL one; any other file in shaders/glsl
+    // is an include
+    Glsl(PathBuf),
+}
+
+// Development mode: polls the rust-gpu shader crates, the koi_gpu types they share and shaders/glsl
+// for changes, and recompiles changed shaders on a background thread.
+pub struct ShaderWatcher {
+    receiver: Receiver<ShaderEvent>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    // shaders being recompiled
+    pub compiling: BTreeSet<String>,
+    // latest compile or reload error per shader; cleared once it reloads
+    pub errors: BTreeMap<String, String>,
+}
+
+impl ShaderWatcher {
+    // shaders_path holds one directory per rust-gpu crate plus glsl/; shared_path is the source of
+    // the types those crates share with the host.
+    pub fn new(shaders_path: PathBuf, shared_path: PathBuf) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let thread = thread::Builder::new()
+            .name(String::from("koi_shader_watcher"))
+            .spawn(move || watch(&shaders_path, &shared_path, sender, &thread_stop))
+            .expect("koi::ren::shader_watcher - failed to spawn Shader Watcher thread");
+
+        Self {
+            receiver,
+            stop,
+            thread: Some(thread),
+            compiling: BTreeSet::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    // Drains the watcher's events; returns the shaders compiled since the last poll, to be swapped
+    // in by the renderer.
+    pub fn poll(&mut self) -> Vec<(String, Vec<u32>)> {
+        let mut compiled = vec![];
+        for event in self.receiver.try_iter() {
+            match event {
+                ShaderEvent::Compiling(name) => {
+                    self.compiling.insert(name);
+                }
+                ShaderEvent::Compiled(name, code) => {
+                    self.compiling.remove(&name);
+                    self.errors.remove(&name);
+                    compiled.push((name, code));
+                }
+                ShaderEvent::Failed(name, error) => {
+                    log::warn!("koi::ren::shader_watcher - failed to compile {name}:\n{error}");
+                    self.compiling.remove(&name);
+                    self.errors.insert(name, error);
+                }
+            }
+        }
+        compiled
+    }
+}
+
+// NB! blocks until the shader being compiled, if any, is done
+impl Drop for ShaderWatcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::warn!("koi::ren::shader_watcher - Shader Watcher thread panicked");
+            }
+        }
+    }
+}
+
+// Runs until the watcher is dropped.
+fn watch(shaders_path: &Path, shared_path: &Path, sender: Sender<ShaderEvent>, stop: &AtomicBool) {
+    let mut modified = get_modified_times(shaders_path, shared_path);
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        if stop.load(Ordering::Relaxed) {
+            return;
+        }
+        let current = get_modified_times(shaders_path, shared_path);
+        for (source, time) in &current {
+            if modified.get(source) == Some(time) {
+                continue;
+            }
+
+            let name = match source {
+                ShaderSource::RustGpu(path) | ShaderSource::Glsl(path) => get_name(path),
+            };
+            if sender.send(ShaderEvent::Compiling(name.clone())).is_err() {
+                return;
+            }
+            let result = match source {
+                ShaderSource::RustGpu(path) => compile_rust_gpu(path),
+                ShaderSource::Glsl(path) => compile_glsl(path),
+            };
+            let event = match result {
+                Ok(code) => ShaderEvent::Compiled(name, code),
+                Err(error) => ShaderEvent::Failed(name, error),
+            };
+            if sender.send(event).is_err() {
+                return;
+            }
+        }
+        modified = current;
+    }
+}
+
+// Latest modification time per shader; rust-gpu crates also count changes to the shared types and
+// GLSL shaders changes to any include.
+fn get_modified_times(
+    shaders_path: &Path,
+    shared_path: &Path,
+) -> HashMap<ShaderSource, SystemTime> {
+    let shared_time = get_latest_modified_time(shared_path);
+    let mut times = HashMap::new();
+    let Ok(entries) = fs::read_dir(shaders_path) else {
+        return times;
+    };
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        if path.ends_with("glsl") {
+            let Ok(glsl_entries) = fs::read_dir(&path) else {
+                continue;
+            };
+            let (sources, includes): (Vec<PathBuf>, Vec<PathBuf>) = glsl_entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .partition(|glsl_path| get_glsl_stage(glsl_path).is_some());
+            let include_time = includes
+                .iter()
+                .map(|include_path| get_latest_modified_time(include_path))
+                .fold(SystemTime::UNIX_EPOCH, SystemTime::max);
+            for source_path in sources {
+                let time = get_latest_modified_time(&source_path).max(include_time);
+                times.insert(ShaderSource::Glsl(source_path), time);
+            }
+        } else if path.join("Cargo.toml").exists() {
+            let time = get_latest_modified_time(&path.join("src"))
+                .max(get_latest_modified_time(&path.join("Cargo.toml")))
+                .max(shared_time);
+            times.insert(ShaderSource::RustGpu(path), time);
+        }
+    }
+    times
+}
+
+fn get_latest_modified_time(path: &Path) -> SystemTime {
+    let Ok(metadata) = fs::metadata(path) else {
+        return SystemTime::UNIX_EPOCH;
+    };
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    if !metadata.is_dir() {
+        return modified;
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| get_latest_modified_time(&entry.path()))
+        .fold(modified, SystemTime::max)
+}
+
+fn get_name(path: &Path) -> String {
+    path.file_name()
+        .map(|file_name| file_name.to_string_lossy())
+        .and_then(|file_name| file_name.split('.').next().map(String::from))
+        .unwrap_or_default()
+}
+
+// NB! mirrors koi/build.rs; rustc diagnostics go to the console, only the summary is reported
+fn compile_rust_gpu(path: &Path) -> Result<Vec<u32>, String> {
+    let result = SpirvBuilder::new(path, "spirv-unknown-spv1.5")
+        .capability(Capability::ImageQuery)
+        .capability(Capability::StorageImageExtendedFormats)
+        .capability(Capability::Int64)
+        .capability(Capability::RuntimeDescriptorArray)
+        .capability(Capability::PhysicalStorageBufferAddresses)
+        .extension("SPV_KHR_physical_storage_buffer")
+        .print_metadata(MetadataPrintout::None)
+        .build()
+        .map_err(|error| format!("{error} (see the console for compiler output)"))?;
+    read_spirv(result.module.unwrap_single())
+}
+
+// NB! mirrors koi/build.rs
+fn get_glsl_stage(path: &Path) -> Option<(&'static str, &'static str)> {
+    let stage_path = match path.extension().and_then(OsStr::to_str)? {
+        "hlsl" => Path::new(path.file_stem()?),
+        _ => path,
+    };
+    match stage_path.extension().and_then(OsStr::to_str)? {
+        "vert" => Some(("vert", "main_vs")),
+        "frag" => Some(("frag", "main_fs")),
+        "comp" => Some(("comp", "main_cs")),
+        _ => None,
+    }
+}
+
+// NB! mirrors koi/build.rs
+fn compile_glsl(path: &Path) -> Result<Vec<u32>, String> {
+    let (stage, entry_point) =
+        get_glsl_stage(path).ok_or_else(|| format!("unknown stage of {}", path.display()))?;
+    let output_path = std::env::temp_dir().join(format!("koi_{}.spv", get_name(path)));
+    let mut command = Command::new("glslangValidator");
+    if path.extension() == Some(OsStr::new("hlsl")) {
+        command.arg("-D");
+    }
+    let output = command
+        .args(["-V", "--target-env", "vulkan1.3", "-S", stage])
+        .args(["-e", entry_point, "--source-entrypoint", "main"])
+        .arg(format!("-I{}", path.parent().unwrap_or(path).display()))
+        .arg(path)
+        .arg("-o")
+        .arg(&output_path)
+        .output()
+        .map_err(|error| format!("failed to run glslangValidator: {error}"))?;
+    // NB! glslangValidator reports errors on stdout
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    read_spirv(&output_path)
+}
+
+fn read_spirv(path: &Path) -> Result<Vec<u32>, String> {
+    File::open(path)
+        .and_then(|mut file| ash::util::read_spv(&mut file))
+        .map_err(|error| format!("failed to read {}: {error}", path.display()))
+}
@@ -0,0 +1,374 @@
+use super::{Scene, node::Node};
+
+use gltf::animation::util::ReadOutputs;
+use spirv_std::glam::{Mat4, Quat, Vec3};
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Property {
+    Translation,
+    Rotation,
+    Scale,
+    // morph target weights
+    Weights,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    CubicSpline,
+}
+
+// One animated property of one node.
+#[derive(Clone)]
+pub struct Channel {
+    pub node: usize,
+    pub property: Property,
+    pub interpolation: Interpolation,
+    // seconds, ascending
+    pub times: Vec<f32>,
+    // width floats per key; cubic-spline keys are in-tangent, value and out-tangent, in that order
+    pub values: Vec<f32>,
+    // 3 for translation and scale, 4 for rotation (xyzw), the target count for weights
+    pub width: usize,
+}
+
+#[derive(Clone)]
+pub struct Animation {
+    pub name: String,
+    pub channels: Vec<Channel>,
+    // seconds; the last key of the longest channel
+    pub duration: f32,
+}
+
+pub fn load_animations(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<Animation> {
+    document
+        .animations()
+        .map(|gltf_animation| {
+            let channels: Vec<Channel> = gltf_animation
+                .channels()
+                .filter_map(|gltf_channel| load_channel(&gltf_channel, buffers))
+                .collect();
+            let duration = channels
+                .iter()
+                .filter_map(|channel| channel.times.last().copied())
+                .fold(0.0, f32::max);
+
+            Animation {
+                name: String::from(gltf_animation.name().unwrap_or("")),
+                channels,
+                duration,
+            }
+        })
+        .collect()
+}
+
+fn load_channel(
+    gltf_channel: &gltf::animation::Channel,
+    buffers: &[gltf::buffer::Data],
+) -> Option<Channel> {
+    let reader = gltf_channel.reader(|buffer| Some(&buffers[buffer.index()]));
+    let times: Vec<f32> = reader.read_inputs()?.collect();
+    let (property, values): (Property, Vec<f32>) = match reader.read_outputs()? {
+        ReadOutputs::Translations(iter) => (Property::Translation, iter.flatten().collect()),
+        ReadOutputs::Rotations(iter) => (Property::Rotation, iter.into_f32().flatten().collect()),
+        ReadOutputs::Scales(iter) => (Property::Scale, iter.flatten().collect()),
+        ReadOutputs::MorphTargetWeights(iter) => (Property::Weights, iter.into_f32().collect()),
+    };
+    let interpolation = match gltf_channel.sampler().interpolation() {
+        gltf::animation::Interpolation::Linear => Interpolation::Linear,
+        gltf::animation::Interpolation::Step => Interpolation::Step,
+        gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+    };
+
+    let values_per_key = match interpolation {
+        Interpolation::CubicSpline => 3 * times.len(),
+        _ => times.len(),
+    };
+    if times.is_empty() || values.len() % values_per_key != 0 {
+        log::warn!(
+            "koi::scene::animation - skipping Channel with {} keys and {} values",
+            times.len(),
+            values.len()
+        );
+        return None;
+    }
+
+    Some(Channel {
+        node: gltf_channel.target().node().index(),
+        property,
+        interpolation,
+        width: values.len() / values_per_key,
+        times,
+        values,
+    })
+}
+
+impl Channel {
+    // Writes width floats; times outside the keys clamp to the first or last key.
+    pub fn sample(&self, time: f32, out: &mut [f32]) {
+        let last = self.times.len() - 1;
+        let next = self.times.partition_point(|&key_time| key_time <= time);
+        if next == 0 || next > last {
+            out.copy_from_slice(self.get_value(if next == 0 { 0 } else { last }));
+            return;
+        }
+
+        let previous = next - 1;
+        let delta = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / delta;
+        match self.interpolation {
+            Interpolation::Step => out.copy_from_slice(self.get_value(previous)),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                let from = Quat::from_slice(self.get_value(previous));
+                let to = Quat::from_slice(self.get_value(next));
+                from.slerp(to, t).write_to_slice(out);
+            }
+            Interpolation::Linear => {
+                let (from, to) = (self.get_value(previous), self.get_value(next));
+                for (index, value) in out.iter_mut().enumerate() {
+                    *value = from[index] + (to[index] - from[index]) * t;
+                }
+            }
+            Interpolation::CubicSpline => {
+                // Hermite basis; tangents are per second, so they're scaled by the key interval
+                let (t2, t3) = (t * t, t * t * t);
+                let from = self.get_value(previous);
+                let from_tangent = self.get_key(previous, 2);
+                let to = self.get_value(next);
+                let to_tangent = self.get_key(next, 0);
+                for (index, value) in out.iter_mut().enumerate() {
+                    *value = (2.0 * t3 - 3.0 * t2 + 1.0) * from[index]
+                        + (t3 - 2.0 * t2 + t) * delta * from_tangent[index]
+                        + (-2.0 * t3 + 3.0 * t2) * to[index]
+                        + (t3 - t2) * delta * to_tangent[index];
+                }
+                if self.property == Property::Rotation {
+                    Quat::from_slice(out).normalize().write_to_slice(out);
+                }
+            }
+        }
+    }
+
+    fn get_value(&self, key: usize) -> &[f32] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.get_key(key, 1),
+            _ => &self.values[key * self.width..(key + 1) * self.width],
+        }
+    }
+
+    // Cubic-spline keys only; part 0 is the in-tangent, 1 the value and 2 the out-tangent.
+    fn get_key(&self, key: usize, part: usize) -> &[f32] {
+        let start = (key * 3 + part) * self.width;
+        &self.values[start..start + self.width]
+    }
+}
+
+// Local translation, rotation, scale and morph weights of one node; what clips sample and blend.
+#[derive(Clone, Default)]
+pub struct Pose {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+    pub weights: Vec<f32>,
+}
+
+impl Pose {
+    // NB! animated nodes are TRS by spec, so decomposing the local transform is lossless
+    pub fn from_node(node: &Node) -> Self {
+        let (scale, rotation, translation) = node.local_transform.to_scale_rotation_translation();
+        Self {
+            translation,
+            rotation,
+            scale,
+            weights: node.weights.clone(),
+        }
+    }
+
+    // weight 0 is self, 1 is other; missing morph weights count as 0
+    pub fn blend(&self, other: &Pose, weight: f32) -> Pose {
+        let weight_count = self.weights.len().max(other.weights.len());
+        let get_weight = |weights: &[f32], index: usize| weights.get(index).copied().unwrap_or(0.0);
+        Pose {
+            translation: self.translation.lerp(other.translation, weight),
+            rotation: self.rotation.slerp(other.rotation, weight),
+            scale: self.scale.lerp(other.scale, weight),
+            weights: (0..weight_count)
+                .map(|index| {
+                    let from = get_weight(&self.weights, index);
+                    from + (get_weight(&other.weights, index) - from) * weight
+                })
+                .collect(),
+        }
+    }
+
+    pub fn apply(&self, node: &mut Node) {
+        node.local_transform =
+            Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation);
+        node.weights.clone_from(&self.weights);
+    }
+}
+
+impl Animation {
+    // Nodes the clip animates; may repeat.
+    pub fn get_nodes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.channels.iter().map(|channel| channel.node)
+    }
+
+    // Overwrites the animated properties of the poses, keyed by node; every node the clip animates
+    // must have one.
+    pub fn sample_poses(&self, time: f32, poses: &mut HashMap<usize, Pose>) {
+        let mut value = [0.0; 4];
+        for channel in &self.channels {
+            let pose = poses
+                .get_mut(&channel.node)
+                .expect("koi::scene::animation - missing Pose for animated Node");
+            if channel.property == Property::Weights {
+                pose.weights.resize(channel.width, 0.0);
+                channel.sample(time, &mut pose.weights);
+                continue;
+            }
+
+            let value = &mut value[..channel.width];
+            channel.sample(time, value);
+            match channel.property {
+                Property::Translation => pose.translation = Vec3::from_slice(value),
+                Property::Rotation => pose.rotation = Quat::from_slice(value),
+                Property::Scale => pose.scale = Vec3::from_slice(value),
+                Property::Weights => unreachable!(),
+            }
+        }
+    }
+
+    // Poses the animated nodes' local transforms and weights, keeping whatever they don't animate;
+    // call node::update_world_transforms afterwards.
+    pub fn sample(&self, time: f32, nodes: &mut [Node]) {
+        let mut poses: HashMap<usize, Pose> = self
+            .get_nodes()
+            .map(|node| (node, Pose::from_node(&nodes[node])))
+            .collect();
+        self.sample_poses(time, &mut poses);
+        for (node, pose) in &poses {
+            pose.apply(&mut nodes[*node]);
+        }
+    }
+
+    // Where a clip is at the given playback time; looping wraps, otherwise the last key holds.
+    pub fn get_clip_time(&self, time: f32, looping: bool) -> f32 {
+        match looping && self.duration > 0.0 {
+            true => time.rem_euclid(self.duration),
+            false => time.clamp(0.0, self.duration),
+        }
+    }
+}
+
+// A second clip mixed over the player's.
+#[derive(Clone, Copy)]
+pub struct Blend {
+    // index into Scene::animations
+    pub animation: usize,
+    // 0 plays the player's clip alone, 1 this one
+    pub weight: f32,
+}
+
+// Plays one of the scene's animations, optionally blended with a second one sharing its clock.
+pub struct AnimationPlayer {
+    // index into Scene::animations; None stops playback and leaves nodes where they are
+    pub animation: Option<usize>,
+    // seconds into the animation
+    pub time: f32,
+    pub speed: f32,
+    pub playing: bool,
+    // wraps at the end of the clip; otherwise playback pauses on its last key
+    pub looping: bool,
+    pub blend: Option<Blend>,
+    // animated nodes' poses before the player first moved them; properties a clip doesn't animate
+    // are held there, so blending between clips that animate different nodes doesn't drift
+    pub rest_poses: HashMap<usize, Pose>,
+}
+
+impl Default for AnimationPlayer {
+    fn default() -> Self {
+        Self {
+            animation: Some(0),
+            time: 0.0,
+            speed: 1.0,
+            playing: true,
+            looping: true,
+            blend: None,
+            rest_poses: HashMap::new(),
+        }
+    }
+}
+
+impl AnimationPlayer {
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    // Scrubs to time seconds; applied by the next update, paused or not.
+    pub fn seek(&mut self, time: f32) {
+        self.time = time;
+    }
+
+    // Switches clips from the start, keeping the play state.
+    pub fn set_animation(&mut self, animation: Option<usize>) {
+        self.animation = animation;
+        self.time = 0.0;
+    }
+
+    // Advances by delta seconds while playing and poses the scene's nodes, world transforms
+    // included. NB! expects the scene the player was last updated with; reset it on scene change
+    pub fn update(&mut self, scene: &mut Scene, delta: f32) {
+        let Some(animation) = self.animation.and_then(|index| scene.animations.get(index)) else {
+            return;
+        };
+        let blend = self.blend.and_then(|blend| {
+            let clip = scene.animations.get(blend.animation)?;
+            Some((clip, blend.weight))
+        });
+
+        if self.playing {
+            self.time += delta * self.speed;
+        }
+        let time = animation.get_clip_time(self.time, self.looping);
+        if !self.looping && time != self.time {
+            self.pause();
+        }
+        self.time = time;
+
+        let nodes = animation
+            .get_nodes()
+            .chain(blend.iter().flat_map(|(clip, _)| clip.get_nodes()));
+        let mut poses = HashMap::new();
+        for node in nodes {
+            let rest_pose = self
+                .rest_poses
+                .entry(node)
+                .or_insert_with(|| Pose::from_node(&scene.nodes[node]));
+            poses.insert(node, rest_pose.clone());
+        }
+
+        let mut blend_poses = blend.map(|_| poses.clone());
+        animation.sample_poses(self.time, &mut poses);
+        if let (Some((clip, weight)), Some(blend_poses)) = (blend, &mut blend_poses) {
+            clip.sample_poses(clip.get_clip_time(self.time, self.looping), blend_poses);
+            for (node, pose) in &mut poses {
+                *pose = pose.blend(&blend_poses[node], weight);
+            }
+        }
+
+        for (node, pose) in &poses {
+            pose.apply(&mut scene.nodes[*node]);
+        }
+        super::node::update_world_transforms(&mut scene.nodes, &scene.roots);
+    }
+}
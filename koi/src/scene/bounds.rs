@@ -0,0 +1,152 @@
+use spirv_std::glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+// Axis-aligned box plus a bounding sphere; the sphere is the cheap first test, the box the tight one.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Bounds {
+    pub min: Vec3,
+    pub max: Vec3,
+    // xyz: center, w: radius
+    pub sphere: Vec4,
+}
+
+// NB! empty; min > max until a point is added
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            min: Vec3::splat(f32::MAX),
+            max: Vec3::splat(f32::MIN),
+            sphere: Vec4::ZERO,
+        }
+    }
+}
+
+impl Bounds {
+    // Sphere is centered on the box; radius is the box's half diagonal.
+    pub fn from_min_max(min: Vec3, max: Vec3) -> Self {
+        let center = (min + max) * 0.5;
+        Self {
+            min,
+            max,
+            sphere: Vec4::from((center, (max - min).length() * 0.5)),
+        }
+    }
+
+    pub fn from_points(points: impl Iterator<Item = Vec3> + Clone) -> Self {
+        let (min, max) = points.clone().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), point| (min.min(point), max.max(point)),
+        );
+        if min.x > max.x {
+            return Self::default();
+        }
+        Self::from_min_max(min, max).fit_sphere(points)
+    }
+
+    // Shrinks the radius to reach the farthest point, which is usually tighter than the half
+    // diagonal; the points must lie within the box.
+    pub fn fit_sphere(mut self, points: impl Iterator<Item = Vec3>) -> Self {
+        let center = self.center();
+        self.sphere.w = points
+            .map(|point| point.distance(center))
+            .fold(0.0, f32::max);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x
+    }
+
+    pub fn center(&self) -> Vec3 {
+        self.sphere.xyz()
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.sphere.w
+    }
+
+    pub fn union(&self, other: &Bounds) -> Self {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Self::from_min_max(self.min.min(other.min), self.max.max(other.max))
+    }
+
+    // Arvo's method for the box; the sphere keeps its radius, scaled by the largest axis scale.
+    pub fn transform(&self, transform: &Mat4) -> Self {
+        if self.is_empty() {
+            return *self;
+        }
+
+        let center = (self.min + self.max) * 0.5;
+        let extent = (self.max - self.min) * 0.5;
+        let world_center = transform.transform_point3(center);
+        let world_extent = Vec3::new(
+            transform.row(0).xyz().abs().dot(extent),
+            transform.row(1).xyz().abs().dot(extent),
+            transform.row(2).xyz().abs().dot(extent),
+        );
+
+        let scale = Vec3::new(
+            transform.x_axis.xyz().length(),
+            transform.y_axis.xyz().length(),
+            transform.z_axis.xyz().length(),
+        );
+        Self {
+            min: world_center - world_extent,
+            max: world_center + world_extent,
+            sphere: Vec4::from((
+                transform.transform_point3(self.center()),
+                self.radius() * scale.max_element(),
+            )),
+        }
+    }
+}
+
+pub struct Frustum {
+    // left, right, bottom, top, near, far; xyz: normal pointing inwards, w: distance
+    pub planes: [Vec4; 6],
+}
+
+impl Frustum {
+    // Gribb-Hartmann plane extraction; expects a reverse-Z projection with [0, 1] depth.
+    pub fn from_view_projection(view_projection: Mat4) -> Self {
+        let row = |index: usize| view_projection.row(index);
+        let planes = [
+            row(3) + row(0),
+            row(3) - row(0),
+            row(3) + row(1),
+            row(3) - row(1),
+            row(3) - row(2),
+            row(2),
+        ];
+        Self {
+            planes: planes.map(|plane| plane / plane.xyz().length()),
+        }
+    }
+
+    // Sphere first; boxes passing it are tested with their most positive vertex per plane.
+    pub fn intersects(&self, bounds: &Bounds) -> bool {
+        if bounds.is_empty() {
+            return false;
+        }
+
+        let center = bounds.center();
+        let radius = bounds.radius();
+        if self
+            .planes
+            .iter()
+            .any(|plane| plane.xyz().dot(center) + plane.w < -radius)
+        {
+            return false;
+        }
+
+        self.planes.iter().all(|plane| {
+            let normal = plane.xyz();
+            let positive = Vec3::select(normal.cmpge(Vec3::ZERO), bounds.max, bounds.min);
+            normal.dot(positive) + plane.w >= 0.0
+        })
+    }
+}
@@ -0,0 +1,273 @@
+use ktx2::{Format, Reader};
+
+const KTX2_IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BlockFormat {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc6h,
+    Bc7,
+}
+
+// Block-compressed image as stored in its container; levels hold the mip chain, largest first.
+pub struct CompressedImage {
+    pub format: BlockFormat,
+    pub srgb: bool,
+    pub signed: bool,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>,
+}
+
+pub enum Ktx2Image {
+    Rgba8 {
+        width: u32,
+        height: u32,
+        pixels: Vec<u8>,
+    },
+    Compressed(CompressedImage),
+}
+
+pub fn is_ktx2(bytes: &[u8]) -> bool {
+    bytes.starts_with(&KTX2_IDENTIFIER)
+}
+
+// NB! only single-layer 2D textures without supercompression are supported. Supercompressed
+// containers (BasisLZ, Zstandard or ZLIB, as KHR_texture_basisu assets usually are) are rejected
+// with a warning; the loader substitutes a white texel, so re-encode them without
+// supercompression.
+pub fn load_ktx2(bytes: &[u8]) -> Result<Ktx2Image, String> {
+    let reader = Reader::new(bytes).map_err(|_| "malformed KTX2 container")?;
+    let header = reader.header();
+
+    if let Some(scheme) = header.supercompression_scheme {
+        return Err(format!(
+            "{scheme:?} supercompressed KTX2 is not supported; re-encode without supercompression"
+        ));
+    }
+    if header.pixel_depth > 1 || header.layer_count > 1 || header.face_count != 1 {
+        return Err(String::from("only 2D KTX2 textures are supported"));
+    }
+
+    let width = header.pixel_width;
+    let height = header.pixel_height.max(1);
+    let mut levels = reader.levels().map(|level| level.data.to_vec());
+
+    let (format, srgb, signed) = match header.format {
+        Some(Format::R8G8B8A8_UNORM) | Some(Format::R8G8B8A8_SRGB) => {
+            return Ok(Ktx2Image::Rgba8 {
+                width,
+                height,
+                pixels: levels.next().ok_or("KTX2 container has no levels")?,
+            });
+        }
+        Some(Format::BC1_RGB_UNORM_BLOCK) | Some(Format::BC1_RGBA_UNORM_BLOCK) => {
+            (BlockFormat::Bc1, false, false)
+        }
+        Some(Format::BC1_RGB_SRGB_BLOCK) | Some(Format::BC1_RGBA_SRGB_BLOCK) => {
+            (BlockFormat::Bc1, true, false)
+        }
+        Some(Format::BC2_UNORM_BLOCK) => (BlockFormat::Bc2, false, false),
+        Some(Format::BC2_SRGB_BLOCK) => (BlockFormat::Bc2, true, false),
+        Some(Format::BC3_UNORM_BLOCK) => (BlockFormat::Bc3, false, false),
+        Some(Format::BC3_SRGB_BLOCK) => (BlockFormat::Bc3, true, false),
+        Some(Format::BC4_UNORM_BLOCK) => (BlockFormat::Bc4, false, false),
+        Some(Format::BC4_SNORM_BLOCK) => (BlockFormat::Bc4, false, true),
+        Some(Format::BC5_UNORM_BLOCK) => (BlockFormat::Bc5, false, false),
+        Some(Format::BC5_SNORM_BLOCK) => (BlockFormat::Bc5, false, true),
+        Some(Format::BC6H_UFLOAT_BLOCK) => (BlockFormat::Bc6h, false, false),
+        Some(Format::BC6H_SFLOAT_BLOCK) => (BlockFormat::Bc6h, false, true),
+        Some(Format::BC7_UNORM_BLOCK) => (BlockFormat::Bc7, false, false),
+        Some(Format::BC7_SRGB_BLOCK) => (BlockFormat::Bc7, true, false),
+        _ => return Err(String::from("unsupported KTX2 format")),
+    };
+
+    Ok(Ktx2Image::Compressed(CompressedImage {
+        format,
+        srgb,
+        signed,
+        width,
+        height,
+        levels: levels.collect(),
+    }))
+}
+
+impl CompressedImage {
+    // CPU transcode of the base level into RGBA8, for devices without block compression support;
+    // opaque white when the data doesn't decode.
+    // NB! signed BC4/BC5 come out biased into unsigned range, as a regular normal map would be
+    pub fn decode_rgba8(&self) -> Vec<u8> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let Some(data) = self.levels.first() else {
+            log::warn!("koi::scene::compressed - block-compressed Image has no levels");
+            return vec![u8::MAX; width * height * 4];
+        };
+        let mut image = vec![0u32; width * height];
+
+        let result = match self.format {
+            BlockFormat::Bc1 => texture2ddecoder::decode_bc1(data, width, height, &mut image),
+            BlockFormat::Bc2 => {
+                decode_bc2(data, width, height, &mut image);
+                Ok(())
+            }
+            BlockFormat::Bc3 => texture2ddecoder::decode_bc3(data, width, height, &mut image),
+            BlockFormat::Bc4 => {
+                texture2ddecoder::decode_bc4(&self.to_unsigned(data), width, height, &mut image)
+            }
+            BlockFormat::Bc5 => {
+                texture2ddecoder::decode_bc5(&self.to_unsigned(data), width, height, &mut image)
+            }
+            BlockFormat::Bc6h => {
+                texture2ddecoder::decode_bc6(data, width, height, &mut image, self.signed)
+            }
+            BlockFormat::Bc7 => texture2ddecoder::decode_bc7(data, width, height, &mut image),
+        };
+        if let Err(error) = result {
+            log::warn!("koi::scene::compressed - failed to decode block-compressed Image: {error}");
+            return vec![u8::MAX; width * height * 4];
+        }
+
+        // decoder output is packed 0xAARRGGBB
+        image
+            .into_iter()
+            .flat_map(|pixel| {
+                [
+                    (pixel >> 16) as u8,
+                    (pixel >> 8) as u8,
+                    pixel as u8,
+                    (pixel >> 24) as u8,
+                ]
+            })
+            .collect()
+    }
+
+    // Re-biases SNORM BC4/BC5 endpoints so the blocks decode as UNORM; interpolation order is kept.
+    // NB! a BC5 block is two 8-byte BC4 channel blocks back to back
+    fn to_unsigned(&self, data: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        if self.signed {
+            data.chunks_exact_mut(8).for_each(|block| {
+                block[0] ^= 0x80;
+                block[1] ^= 0x80;
+            });
+        }
+        data
+    }
+}
+
+// BC2: 64 bits of explicit 4-bit alpha followed by a four-colour BC1 block.
+fn decode_bc2(data: &[u8], width: usize, height: usize, image: &mut [u32]) {
+    let blocks_x = width.div_ceil(4);
+
+    for (block_index, block) in data.chunks_exact(16).enumerate() {
+        let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+        let color_0 = u16::from_le_bytes([block[8], block[9]]);
+        let color_1 = u16::from_le_bytes([block[10], block[11]]);
+        let indices = u32::from_le_bytes(block[12..16].try_into().unwrap());
+
+        let endpoints = [unpack_565(color_0), unpack_565(color_1)];
+        let palette = [
+            endpoints[0],
+            endpoints[1],
+            interpolate(endpoints[0], endpoints[1], 2, 1),
+            interpolate(endpoints[0], endpoints[1], 1, 2),
+        ];
+
+        let block_x = (block_index % blocks_x) * 4;
+        let block_y = (block_index / blocks_x) * 4;
+        for texel in 0..16 {
+            let (x, y) = (block_x + texel % 4, block_y + texel / 4);
+            if x >= width || y >= height {
+                continue;
+            }
+            let [r, g, b] = palette[((indices >> (texel * 2)) & 0b11) as usize];
+            let a = ((alpha >> (texel * 4)) & 0xF) as u32 * 17;
+            image[y * width + x] = a << 24 | r << 16 | g << 8 | b;
+        }
+    }
+}
+
+fn unpack_565(color: u16) -> [u32; 3] {
+    let r = ((color >> 11) & 0x1F) as u32;
+    let g = ((color >> 5) & 0x3F) as u32;
+    let b = (color & 0x1F) as u32;
+    [
+        (r << 3) | (r >> 2),
+        (g << 2) | (g >> 4),
+        (b << 3) | (b >> 2),
+    ]
+}
+
+fn interpolate(a: [u32; 3], b: [u32; 3], weight_a: u32, weight_b: u32) -> [u32; 3] {
+    [0, 1, 2].map(|i| (a[i] * weight_a + b[i] * weight_b) / (weight_a + weight_b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode_block(format: BlockFormat, signed: bool, block: &[u8]) -> Vec<u8> {
+        CompressedImage {
+            format,
+            srgb: false,
+            signed,
+            width: 4,
+            height: 4,
+            levels: vec![block.to_vec()],
+        }
+        .decode_rgba8()
+    }
+
+    // A signed BC4 channel block with endpoints +127 and -127, in 8-value mode; texel 1 picks the
+    // second endpoint, every other texel the first.
+    const SIGNED_BC4_BLOCK: [u8; 8] = [0x7F, 0x81, 0x08, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn decodes_bc2_block() {
+        // texel i: alpha i, palette entry i % 4 between pure red and pure blue
+        let alpha = (0..16).fold(0u64, |alpha, texel| alpha | texel << (texel * 4));
+        let mut block = alpha.to_le_bytes().to_vec();
+        block.extend(0xF800u16.to_le_bytes());
+        block.extend(0x001Fu16.to_le_bytes());
+        block.extend(0xE4E4_E4E4u32.to_le_bytes());
+
+        let palette = [[255, 0, 0], [0, 0, 255], [170, 0, 85], [85, 0, 170]];
+        let pixels = decode_block(BlockFormat::Bc2, false, &block);
+        for (texel, pixel) in pixels.chunks_exact(4).enumerate() {
+            let [r, g, b] = palette[texel % 4];
+            assert_eq!(pixel, [r, g, b, texel as u8 * 17], "texel {texel}");
+        }
+    }
+
+    #[test]
+    fn rebiases_signed_bc4() {
+        let pixels = decode_block(BlockFormat::Bc4, true, &SIGNED_BC4_BLOCK);
+        let red: Vec<u8> = pixels.chunks_exact(4).map(|pixel| pixel[0]).collect();
+        let mut expected = [255; 16];
+        expected[1] = 1;
+        assert_eq!(red, expected);
+    }
+
+    #[test]
+    fn rebiases_signed_bc5() {
+        // green: endpoints -127 and +127 in 6-value mode, every texel on the second endpoint
+        let mut block = SIGNED_BC4_BLOCK.to_vec();
+        block.extend([0x81, 0x7F, 0x49, 0x92, 0x24, 0x49, 0x92, 0x24]);
+
+        let pixels = decode_block(BlockFormat::Bc5, true, &block);
+        for (texel, pixel) in pixels.chunks_exact(4).enumerate() {
+            let red = match texel {
+                1 => 1,
+                _ => 255,
+            };
+            assert_eq!(pixel[..2], [red, 255], "texel {texel}");
+        }
+    }
+}
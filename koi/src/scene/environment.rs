@@ -0,0 +1,38 @@
+use std::path::Path;
+
+// Equirectangular HDR environment map; pixels are linear RGBA32F.
+pub struct Environment {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+// NB! neutral grey studio, used until an environment map is loaded
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            name: String::from("default"),
+            width: 1,
+            height: 1,
+            pixels: vec![0.5, 0.5, 0.5, 1.0],
+        }
+    }
+}
+
+pub fn load(path: &Path) -> Environment {
+    let image = image::open(path)
+        .expect("koi::scene::environment - failed to load Environment")
+        .into_rgba32f();
+
+    Environment {
+        name: String::from(
+            path.file_stem()
+                .and_then(|stem| stem.to_str())
+                .unwrap_or(""),
+        ),
+        width: image.width(),
+        height: image.height(),
+        pixels: image.into_raw(),
+    }
+}
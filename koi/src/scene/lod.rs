@@ -0,0 +1,252 @@
+use super::{Lod, Mesh};
+
+use koi_gpu::MAX_LODS;
+use spirv_std::glam::{DMat4, DVec3, DVec4, Vec3, Vec4Swizzles};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+};
+
+// each level targets this fraction of the previous level's triangles
+const LOD_REDUCTION: f32 = 0.5;
+// levels that don't shed at least this fraction of the previous level's triangles are dropped
+const LOD_MIN_REDUCTION: f32 = 0.1;
+// keeps open borders (and attribute seams, which are split vertices) from collapsing inwards
+const BORDER_WEIGHT: f64 = 100.0;
+
+// Appends simplified index ranges after the mesh's existing indices; surface ranges are untouched.
+pub fn generate_lods(mesh: &mut Mesh) {
+    let positions: Vec<Vec3> = mesh
+        .vertices
+        .iter()
+        .map(|vertex| vertex.position_uv_x.xyz())
+        .collect();
+
+    for surface_index in 0..mesh.surfaces.len() {
+        let surface = mesh.surfaces[surface_index];
+        let start = surface.start_index as usize;
+        let mut source = mesh.indices[start..start + surface.count as usize].to_vec();
+        let mut lods = vec![Lod {
+            start_index: surface.start_index,
+            count: surface.count,
+            error: 0.0,
+        }];
+
+        while lods.len() < MAX_LODS {
+            let target = ((source.len() / 3) as f32 * LOD_REDUCTION) as usize * 3;
+            let (indices, error) = simplify(&source, &positions, target);
+            let reduction = 1.0 - indices.len() as f32 / source.len() as f32;
+            if indices.is_empty() || reduction < LOD_MIN_REDUCTION {
+                break;
+            }
+
+            // NB! errors are cumulative; each level is simplified from the previous one
+            lods.push(Lod {
+                start_index: mesh.indices.len() as u32,
+                count: indices.len() as u32,
+                error: lods.last().unwrap().error + error,
+            });
+            mesh.indices.extend_from_slice(&indices);
+            source = indices;
+        }
+
+        let surface = &mut mesh.surfaces[surface_index];
+        surface.lod_count = lods.len() as u32;
+        surface.lods[..lods.len()].copy_from_slice(&lods);
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Collapse {
+    cost: f64,
+    from: u32,
+    to: u32,
+    // stale once either endpoint changes
+    versions: (u32, u32),
+}
+
+impl Eq for Collapse {}
+
+// min-heap on cost
+impl Ord for Collapse {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost)
+    }
+}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn plane_quadric(normal: DVec3, point: DVec3, weight: f64) -> DMat4 {
+    let plane = DVec4::from((normal, -normal.dot(point)));
+    DMat4::from_cols(
+        plane * plane.x,
+        plane * plane.y,
+        plane * plane.z,
+        plane * plane.w,
+    ) * weight
+}
+
+fn quadric_error(quadric: &DMat4, point: DVec3) -> f64 {
+    let point = DVec4::from((point, 1.0));
+    point.dot(*quadric * point).max(0.0)
+}
+
+fn triangle_normal(positions: &[Vec3], triangle: [u32; 3]) -> DVec3 {
+    let [a, b, c] = triangle.map(|index| positions[index as usize].as_dvec3());
+    (b - a).cross(c - a)
+}
+
+// Quadric error metric edge collapse (Garland-Heckbert), restricted to half-edge collapses so the
+// result only references existing vertices. Returns the simplified triangle list and the largest
+// collapse error, as an object-space distance.
+pub fn simplify(indices: &[u32], positions: &[Vec3], target_index_count: usize) -> (Vec<u32>, f32) {
+    let mut triangles: Vec<[u32; 3]> = indices
+        .chunks_exact(3)
+        .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+        .filter(|[a, b, c]| a != b && b != c && a != c)
+        .collect();
+    let mut alive = vec![true; triangles.len()];
+    let mut live_count = triangles.len();
+
+    let mut quadrics = vec![DMat4::ZERO; positions.len()];
+    let mut vertex_triangles = vec![vec![]; positions.len()];
+    let mut edge_counts = HashMap::new();
+    for (triangle_index, &triangle) in triangles.iter().enumerate() {
+        // NB! unweighted, so collapse costs stay squared distances
+        let normal = triangle_normal(positions, triangle).normalize_or_zero();
+        if normal != DVec3::ZERO {
+            let point = positions[triangle[0] as usize].as_dvec3();
+            let quadric = plane_quadric(normal, point, 1.0);
+            triangle
+                .iter()
+                .for_each(|&vertex| quadrics[vertex as usize] += quadric);
+        }
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            vertex_triangles[a as usize].push(triangle_index);
+            *edge_counts.entry((a.min(b), a.max(b))).or_insert(0u32) += 1;
+        }
+    }
+
+    // border edges get a plane perpendicular to the adjacent face, pinning them in place
+    for triangle in &triangles {
+        let normal = triangle_normal(positions, *triangle);
+        for corner in 0..3 {
+            let (a, b) = (triangle[corner], triangle[(corner + 1) % 3]);
+            if edge_counts[&(a.min(b), a.max(b))] != 1 {
+                continue;
+            }
+            let (pa, pb) = (
+                positions[a as usize].as_dvec3(),
+                positions[b as usize].as_dvec3(),
+            );
+            let border_normal = (pb - pa).cross(normal).normalize_or_zero();
+            let quadric = plane_quadric(border_normal, pa, BORDER_WEIGHT * (pb - pa).length());
+            quadrics[a as usize] += quadric;
+            quadrics[b as usize] += quadric;
+        }
+    }
+
+    let mut versions = vec![0u32; positions.len()];
+    let mut heap = BinaryHeap::new();
+    let push_edge =
+        |heap: &mut BinaryHeap<Collapse>, quadrics: &[DMat4], versions: &[u32], a: u32, b: u32| {
+            let quadric = quadrics[a as usize] + quadrics[b as usize];
+            let cost_to_a = quadric_error(&quadric, positions[a as usize].as_dvec3());
+            let cost_to_b = quadric_error(&quadric, positions[b as usize].as_dvec3());
+            let (from, to, cost) = match cost_to_a <= cost_to_b {
+                true => (b, a, cost_to_a),
+                false => (a, b, cost_to_b),
+            };
+            heap.push(Collapse {
+                cost,
+                from,
+                to,
+                versions: (versions[from as usize], versions[to as usize]),
+            });
+        };
+    // NB! sorted, so ties resolve the same way on every import
+    let mut edges: Vec<_> = edge_counts.keys().copied().collect();
+    edges.sort_unstable();
+    for (a, b) in edges {
+        push_edge(&mut heap, &quadrics, &versions, a, b);
+    }
+
+    let mut max_error = 0.0f64;
+    while live_count * 3 > target_index_count {
+        let Some(collapse) = heap.pop() else {
+            break;
+        };
+        let (from, to) = (collapse.from as usize, collapse.to as usize);
+        if collapse.versions != (versions[from], versions[to]) {
+            continue;
+        }
+
+        // reject collapses that flip a surviving triangle around the removed vertex
+        let flips = vertex_triangles[from].iter().any(|&triangle_index| {
+            let triangle = triangles[triangle_index];
+            if !alive[triangle_index] || triangle.contains(&collapse.to) {
+                return false;
+            }
+            let before = triangle_normal(positions, triangle);
+            let moved = triangle.map(|vertex| match vertex == collapse.from {
+                true => collapse.to,
+                false => vertex,
+            });
+            let [a, b, c] = moved.map(|index| positions[index as usize].as_dvec3());
+            let after = (b - a).cross(c - a);
+            before.dot(after) <= 0.0
+        });
+        if flips {
+            continue;
+        }
+
+        max_error = max_error.max(collapse.cost);
+        quadrics[to] = quadrics[to] + quadrics[from];
+        versions[from] += 1;
+        versions[to] += 1;
+
+        let moved_triangles = std::mem::take(&mut vertex_triangles[from]);
+        for &triangle_index in &moved_triangles {
+            if !alive[triangle_index] {
+                continue;
+            }
+            let triangle = &mut triangles[triangle_index];
+            if triangle.contains(&collapse.to) {
+                alive[triangle_index] = false;
+                live_count -= 1;
+                continue;
+            }
+            triangle
+                .iter_mut()
+                .filter(|vertex| **vertex == collapse.from)
+                .for_each(|vertex| *vertex = collapse.to);
+            vertex_triangles[to].push(triangle_index);
+        }
+
+        // re-queue every edge around the surviving vertex
+        let mut neighbours: Vec<u32> = vertex_triangles[to]
+            .iter()
+            .filter(|&&triangle_index| alive[triangle_index])
+            .flat_map(|&triangle_index| triangles[triangle_index])
+            .filter(|&vertex| vertex != collapse.to)
+            .collect();
+        neighbours.sort_unstable();
+        neighbours.dedup();
+        for neighbour in neighbours {
+            push_edge(&mut heap, &quadrics, &versions, collapse.to, neighbour);
+        }
+    }
+
+    let simplified = triangles
+        .iter()
+        .zip(&alive)
+        .filter(|(_, alive)| **alive)
+        .flat_map(|(triangle, _)| *triangle)
+        .collect();
+    (simplified, max_error.sqrt() as f32)
+}
@@ -1,16 +1,82 @@
+pub mod animation;
+pub mod bounds;
+pub mod compressed;
+pub mod environment;
+pub mod lod;
+pub mod node;
+pub mod optimize;
+pub mod skin;
+pub mod tangent;
+pub mod texture;
+
+use bounds::Bounds;
 use std::{path::Path, str::FromStr};
 
 use gltf::{
     self,
     mesh::util::{ReadColors, ReadIndices, ReadTexCoords},
 };
-use koi_gpu::Vertex;
-use spirv_std::glam::{Vec3, Vec4, Vec4Swizzles};
+use koi_gpu::{MAX_LODS, MorphDelta, SkinVertex, Vertex, VertexFormat};
+use spirv_std::glam::{UVec4, Vec3, Vec4, Vec4Swizzles};
+
+// An index range of the mesh's index buffer drawn at one level of detail.
+#[derive(Default, Clone, Copy)]
+pub struct Lod {
+    pub start_index: u32,
+    pub count: u32,
+    // object-space distance from the full-detail surface
+    pub error: f32,
+}
+
+// Drawn in this order; blended surfaces go last so they composite over the others.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Debug)]
+pub enum AlphaMode {
+    #[default]
+    Opaque,
+    Mask,
+    Blend,
+}
+
+// A glTF material's factors; alpha_mode and double_sided also pick a surface's pipeline variant.
+#[derive(Clone, Copy)]
+pub struct Material {
+    pub alpha_mode: AlphaMode,
+    // Mask only; fragments less opaque than this are discarded
+    pub alpha_cutoff: f32,
+    pub base_color: Vec4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            base_color: Vec4::ONE,
+            metallic: 1.0,
+            roughness: 1.0,
+            double_sided: false,
+        }
+    }
+}
 
 #[derive(Default, Clone, Copy)]
 pub struct Surface {
     pub start_index: u32,
     pub count: u32,
+    // object space
+    pub bounds: Bounds,
+    // lods[0] is the full-detail range above; filled by lod::generate_lods, lod_count 0 until then
+    pub lods: [Lod; MAX_LODS],
+    pub lod_count: u32,
+    // index into Scene::textures; tangent-space normals
+    pub normal_texture: Option<usize>,
+    pub normal_scale: f32,
+    // index into Scene::textures; sRGB, multiplied by the material's base color
+    pub base_color_texture: Option<usize>,
+    pub material: Material,
 }
 
 #[derive(Default)]
@@ -18,21 +84,68 @@ pub struct Mesh {
     pub name: String,
     pub indices: Vec<u32>,
     pub vertices: Vec<Vertex>,
+    // parallel to vertices; empty unless the mesh has JOINTS_0 and WEIGHTS_0
+    pub skin_vertices: Vec<SkinVertex>,
+    // one list per morph target, each parallel to vertices; weighted by the mesh's nodes
+    pub morph_targets: Vec<Vec<MorphDelta>>,
     pub surfaces: Vec<Surface>,
+    // object space; union of the surfaces' bounds
+    pub bounds: Bounds,
+    // layout vertices are uploaded in; vertices stay full precision on the CPU
+    pub vertex_format: VertexFormat,
 }
 
 #[derive(Default)]
 pub struct Scene {
     pub meshes: Vec<Mesh>,
+    pub nodes: Vec<node::Node>,
+    pub roots: Vec<usize>,
+    pub skins: Vec<skin::Skin>,
+    pub animations: Vec<animation::Animation>,
+    pub images: Vec<texture::Image>,
+    pub textures: Vec<texture::Texture>,
+}
+
+// Import-time processing applied by load_with_options.
+#[derive(Clone, Copy)]
+pub struct LoadOptions {
+    // vertex deduplication, cache and overdraw reordering, vertex fetch remapping; see optimize
+    pub optimize: bool,
+    // picks each loaded mesh's upload layout; skinned and morphed meshes are always Full
+    pub vertex_format: fn(&Mesh) -> VertexFormat,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            optimize: true,
+            vertex_format: |_| VertexFormat::Full,
+        }
+    }
 }
 
 pub fn load(path: &Path) -> Scene {
-    let (gltf, buffers, _images) = gltf::import(path).expect("koi::scene - failed to load Scene");
+    load_with_options(path, LoadOptions::default())
+}
+
+pub fn load_with_options(path: &Path, options: LoadOptions) -> Scene {
+    // NB! images are read by hand; gltf::import can't decode KTX2 sources
+    let gltf = gltf::Gltf::open(path).expect("koi::scene - failed to load Scene");
+    let base = path.parent();
+    let buffers = gltf::import_buffers(&gltf.document, base, gltf.blob.clone())
+        .expect("koi::scene - failed to load Scene Buffers");
 
     let mut scene = Scene::default();
+    scene.textures = texture::load_textures(&gltf);
+    scene.images = texture::load_images(&gltf, base, &buffers);
+    (scene.nodes, scene.roots) = node::load_nodes(&gltf, &buffers);
+    scene.skins = skin::load_skins(&gltf, &buffers);
+    scene.animations = animation::load_animations(&gltf, &buffers);
 
     let mut indices = vec![];
     let mut vertices = vec![];
+    let mut skin_vertices = vec![];
+    let mut morph_targets: Vec<Vec<MorphDelta>> = vec![];
 
     for gltf_mesh in gltf.meshes() {
         let mut mesh = Mesh::default();
@@ -41,11 +154,16 @@ pub fn load(path: &Path) -> Scene {
 
         indices.clear();
         vertices.clear();
+        skin_vertices.clear();
+        morph_targets.clear();
+        let mut missing_tangents = vec![];
 
         for primitive in gltf_mesh.primitives() {
             let mut surface = Surface::default();
 
             let start_index = indices.len();
+            // primitives index their own vertices; rebase them onto the mesh's shared buffer
+            let vertex_base = vertices.len();
 
             surface.start_index = start_index as u32;
 
@@ -55,23 +173,32 @@ pub fn load(path: &Path) -> Scene {
                 match idxs {
                     ReadIndices::U8(iter) => {
                         for index in iter {
-                            indices.push(index as u32);
+                            indices.push(vertex_base as u32 + index as u32);
                         }
                     }
                     ReadIndices::U16(iter) => {
                         for index in iter {
-                            indices.push(index as u32);
+                            indices.push(vertex_base as u32 + index as u32);
                         }
                     }
                     ReadIndices::U32(iter) => {
                         for index in iter {
-                            indices.push(index);
+                            indices.push(vertex_base as u32 + index);
                         }
                     }
                 }
             }
 
             if let Some(iter) = reader.read_positions() {
+                // NB! POSITION accessors must declare min/max; the sphere is fitted to the points
+                let bounding_box = primitive.bounding_box();
+                let points = iter.clone().map(Vec3::from_array);
+                surface.bounds = Bounds::from_min_max(
+                    Vec3::from_array(bounding_box.min),
+                    Vec3::from_array(bounding_box.max),
+                )
+                .fit_sphere(points);
+
                 for vertex_position in iter {
                     let mut vertex = Vertex::default();
                     vertex.position_uv_x = Vec4::from((Vec3::from_array(vertex_position), 0.0));
@@ -81,29 +208,92 @@ pub fn load(path: &Path) -> Scene {
 
             if let Some(iter) = reader.read_normals() {
                 for (i, normal) in iter.enumerate() {
-                    vertices[start_index + i].normal_uv_y =
+                    vertices[vertex_base + i].normal_uv_y =
                         Vec4::from((Vec3::from_array(normal), 0.0));
                 }
             }
 
+            match reader.read_tangents() {
+                Some(iter) => {
+                    for (i, tangent) in iter.enumerate() {
+                        vertices[vertex_base + i].tangent = Vec4::from_array(tangent);
+                    }
+                }
+                // NB! MikkTSpace needs normals; without them there's nothing to map against
+                None if reader.read_normals().is_some() => {
+                    missing_tangents.push(mesh.surfaces.len());
+                }
+                None => {}
+            }
+
+            if let (Some(joints), Some(weights)) = (reader.read_joints(0), reader.read_weights(0)) {
+                skin_vertices.resize(vertices.len(), SkinVertex::default());
+                for (i, (joints, weights)) in joints.into_u16().zip(weights.into_f32()).enumerate()
+                {
+                    skin_vertices[vertex_base + i] = SkinVertex {
+                        joints: UVec4::from_array(joints.map(u32::from)),
+                        weights: Vec4::from_array(weights),
+                    };
+                }
+            }
+
+            for (target, (positions, normals, tangents)) in reader.read_morph_targets().enumerate()
+            {
+                if target == morph_targets.len() {
+                    morph_targets.push(vec![]);
+                }
+                let deltas = &mut morph_targets[target];
+                deltas.resize(vertices.len(), MorphDelta::default());
+                for (i, position) in positions.into_iter().flatten().enumerate() {
+                    deltas[vertex_base + i].position = Vec4::from((Vec3::from(position), 0.0));
+                }
+                for (i, normal) in normals.into_iter().flatten().enumerate() {
+                    deltas[vertex_base + i].normal = Vec4::from((Vec3::from(normal), 0.0));
+                }
+                for (i, tangent) in tangents.into_iter().flatten().enumerate() {
+                    deltas[vertex_base + i].tangent = Vec4::from((Vec3::from(tangent), 0.0));
+                }
+            }
+
+            let material = primitive.material();
+            if let Some(normal_texture) = material.normal_texture() {
+                surface.normal_texture = Some(normal_texture.texture().index());
+                surface.normal_scale = normal_texture.scale();
+            }
+            let pbr = material.pbr_metallic_roughness();
+            surface.base_color_texture =
+                pbr.base_color_texture().map(|info| info.texture().index());
+            surface.material = Material {
+                alpha_mode: match material.alpha_mode() {
+                    gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+                    gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+                    gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+                },
+                alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+                base_color: Vec4::from_array(pbr.base_color_factor()),
+                metallic: pbr.metallic_factor(),
+                roughness: pbr.roughness_factor(),
+                double_sided: material.double_sided(),
+            };
+
             if let Some(coords) = reader.read_tex_coords(0) {
                 match coords {
                     ReadTexCoords::U8(iter) => {
                         for (i, uv) in iter.enumerate() {
-                            vertices[start_index + i].position_uv_x.w = uv[0] as f32;
-                            vertices[start_index + i].normal_uv_y.w = uv[1] as f32;
+                            vertices[vertex_base + i].position_uv_x.w = uv[0] as f32;
+                            vertices[vertex_base + i].normal_uv_y.w = uv[1] as f32;
                         }
                     }
                     ReadTexCoords::U16(iter) => {
                         for (i, uv) in iter.enumerate() {
-                            vertices[start_index + i].position_uv_x.w = uv[0] as f32;
-                            vertices[start_index + i].normal_uv_y.w = uv[1] as f32;
+                            vertices[vertex_base + i].position_uv_x.w = uv[0] as f32;
+                            vertices[vertex_base + i].normal_uv_y.w = uv[1] as f32;
                         }
                     }
                     ReadTexCoords::F32(iter) => {
                         for (i, uv) in iter.enumerate() {
-                            vertices[start_index + i].position_uv_x.w = uv[0];
-                            vertices[start_index + i].normal_uv_y.w = uv[1];
+                            vertices[vertex_base + i].position_uv_x.w = uv[0];
+                            vertices[vertex_base + i].normal_uv_y.w = uv[1];
                         }
                     }
                 }
@@ -113,25 +303,25 @@ pub fn load(path: &Path) -> Scene {
                 match colors {
                     ReadColors::RgbU8(iter) => {
                         for (i, color) in iter.enumerate() {
-                            vertices[start_index + i].color =
+                            vertices[vertex_base + i].color =
                                 Vec4::new(color[0] as f32, color[1] as f32, color[2] as f32, 1.0);
                         }
                     }
                     ReadColors::RgbU16(iter) => {
                         for (i, color) in iter.enumerate() {
-                            vertices[start_index + i].color =
+                            vertices[vertex_base + i].color =
                                 Vec4::new(color[0] as f32, color[1] as f32, color[2] as f32, 1.0);
                         }
                     }
                     ReadColors::RgbF32(iter) => {
                         for (i, color) in iter.enumerate() {
-                            vertices[start_index + i].color =
+                            vertices[vertex_base + i].color =
                                 Vec4::from((Vec3::from_array(color), 1.0));
                         }
                     }
                     ReadColors::RgbaU8(iter) => {
                         for (i, color) in iter.enumerate() {
-                            vertices[start_index + i].color = Vec4::new(
+                            vertices[vertex_base + i].color = Vec4::new(
                                 color[0] as f32,
                                 color[1] as f32,
                                 color[2] as f32,
@@ -141,7 +331,7 @@ pub fn load(path: &Path) -> Scene {
                     }
                     ReadColors::RgbaU16(iter) => {
                         for (i, color) in iter.enumerate() {
-                            vertices[start_index + i].color = Vec4::new(
+                            vertices[vertex_base + i].color = Vec4::new(
                                 color[0] as f32,
                                 color[1] as f32,
                                 color[2] as f32,
@@ -151,13 +341,14 @@ pub fn load(path: &Path) -> Scene {
                     }
                     ReadColors::RgbaF32(iter) => {
                         for (i, color) in iter.enumerate() {
-                            vertices[start_index + i].color = Vec4::from_array(color);
+                            vertices[vertex_base + i].color = Vec4::from_array(color);
                         }
                     }
                 }
             }
 
             surface.count = (indices.len() - start_index) as u32;
+            mesh.bounds = mesh.bounds.union(&surface.bounds);
             mesh.surfaces.push(surface);
         }
 
@@ -171,7 +362,49 @@ pub fn load(path: &Path) -> Scene {
 
         mesh.indices = indices.clone();
         mesh.vertices = vertices.clone();
+        if !skin_vertices.is_empty() {
+            // unskinned primitives of a skinned mesh keep zero weights, i.e. stay in bind pose
+            skin_vertices.resize(vertices.len(), SkinVertex::default());
+            mesh.skin_vertices = skin_vertices.clone();
+        }
+        if !morph_targets.is_empty() {
+            // primitives without a target keep zero deltas
+            for deltas in &mut morph_targets {
+                deltas.resize(vertices.len(), MorphDelta::default());
+            }
+            mesh.morph_targets = morph_targets.clone();
+        }
+        for surface_index in missing_tangents {
+            if !tangent::generate_tangents(&mut mesh, surface_index) {
+                log::warn!(
+                    "koi::scene - failed to generate Tangents for Mesh {}, Surface {surface_index}",
+                    mesh.name
+                );
+            }
+        }
+        // NB! before LOD generation, which appends ranges the optimizer doesn't know about
+        if options.optimize {
+            optimize::optimize_mesh(&mut mesh);
+        }
+        mesh.vertex_format = if mesh.skin_vertices.is_empty() && mesh.morph_targets.is_empty() {
+            (options.vertex_format)(&mesh)
+        } else {
+            // NB! skinning reads and writes full vertices
+            VertexFormat::Full
+        };
+        lod::generate_lods(&mut mesh);
         scene.meshes.push(mesh);
     }
+
+    // every morphed node gets a weight per target, so they can be animated and edited alike
+    for node in &mut scene.nodes {
+        let target_count = node
+            .mesh
+            .map_or(0, |mesh| scene.meshes[mesh].morph_targets.len());
+        if target_count > 0 {
+            node.weights.resize(target_count, 0.0);
+        }
+    }
+    node::update_bounds(&mut scene.nodes, &scene.roots, &scene.meshes);
     scene
 }
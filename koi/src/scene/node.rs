@@ -0,0 +1,155 @@
+use super::{Mesh, bounds::Bounds};
+
+use gltf::accessor::{DataType, Iter};
+use gltf::animation::util::Rotations;
+use spirv_std::glam::{Mat4, Quat, Vec3};
+
+#[derive(Clone)]
+pub struct Node {
+    pub name: String,
+    pub mesh: Option<usize>,
+    // index into Scene::skins; deforms the node's mesh
+    pub skin: Option<usize>,
+    pub children: Vec<usize>,
+    pub local_transform: Mat4,
+    // EXT_mesh_gpu_instancing; relative to the node, empty when the mesh is drawn once
+    pub instances: Vec<Mat4>,
+    // morph target weights; the node's own, else its mesh's defaults. Animated by weights channels
+    pub weights: Vec<f32>,
+    // NB! derived from the hierarchy on load; call update_world_transforms after editing locals
+    pub world_transform: Mat4,
+    // world space; covers the node's mesh and every descendant. Call update_bounds after moving nodes
+    pub bounds: Bounds,
+}
+
+// Nodes are stored flat, in glTF order; roots are the default scene's (or first scene's) nodes.
+pub fn load_nodes(
+    document: &gltf::Document,
+    buffers: &[gltf::buffer::Data],
+) -> (Vec<Node>, Vec<usize>) {
+    let mut nodes: Vec<_> = document
+        .nodes()
+        .map(|gltf_node| Node {
+            name: String::from(gltf_node.name().unwrap_or("")),
+            mesh: gltf_node.mesh().map(|mesh| mesh.index()),
+            skin: gltf_node.skin().map(|skin| skin.index()),
+            children: gltf_node.children().map(|child| child.index()).collect(),
+            local_transform: Mat4::from_cols_array_2d(&gltf_node.transform().matrix()),
+            instances: load_instances(document, &gltf_node, buffers),
+            weights: gltf_node
+                .weights()
+                .or_else(|| gltf_node.mesh().and_then(|mesh| mesh.weights()))
+                .map(|weights| weights.to_vec())
+                .unwrap_or_default(),
+            world_transform: Mat4::IDENTITY,
+            bounds: Bounds::default(),
+        })
+        .collect();
+
+    let roots: Vec<usize> = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .map(|scene| scene.nodes().map(|node| node.index()).collect())
+        .unwrap_or_default();
+
+    update_world_transforms(&mut nodes, &roots);
+    (nodes, roots)
+}
+
+// Reads EXT_mesh_gpu_instancing TRANSLATION/ROTATION/SCALE; missing attributes are identity.
+// NB! ROTATION may also be a normalized i8/i16 quaternion; TRANSLATION and SCALE are always float
+fn load_instances(
+    document: &gltf::Document,
+    gltf_node: &gltf::Node,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<Mat4> {
+    let Some(attributes) = gltf_node
+        .extension_value("EXT_mesh_gpu_instancing")
+        .and_then(|extension| extension.get("attributes"))
+    else {
+        return vec![];
+    };
+
+    let accessor = |name: &str| {
+        attributes
+            .get(name)
+            .and_then(|index| index.as_u64())
+            .and_then(|index| document.accessors().nth(index as usize))
+    };
+    let get_buffer_data =
+        |buffer: gltf::Buffer| buffers.get(buffer.index()).map(|data| data.0.as_slice());
+    let read_vec3 = |name: &str| -> Vec<Vec3> {
+        accessor(name)
+            .filter(|accessor| accessor.data_type() == DataType::F32)
+            .and_then(|accessor| Iter::<[f32; 3]>::new(accessor, get_buffer_data))
+            .map(|iter| iter.map(Vec3::from_array).collect())
+            .unwrap_or_default()
+    };
+
+    let translations = read_vec3("TRANSLATION");
+    let scales = read_vec3("SCALE");
+    let rotations: Vec<Quat> = accessor("ROTATION")
+        .and_then(|accessor| match accessor.data_type() {
+            DataType::F32 => Iter::new(accessor, get_buffer_data).map(Rotations::F32),
+            DataType::I16 => Iter::new(accessor, get_buffer_data).map(Rotations::I16),
+            DataType::I8 => Iter::new(accessor, get_buffer_data).map(Rotations::I8),
+            data_type => {
+                log::warn!(
+                    "koi::scene::node - unsupported instance rotation type {data_type:?}, using identity"
+                );
+                None
+            }
+        })
+        .map(|rotations| rotations.into_f32().map(Quat::from_array).collect())
+        .unwrap_or_default();
+
+    let count = translations.len().max(rotations.len()).max(scales.len());
+    (0..count)
+        .map(|index| {
+            Mat4::from_scale_rotation_translation(
+                scales.get(index).copied().unwrap_or(Vec3::ONE),
+                rotations.get(index).copied().unwrap_or(Quat::IDENTITY),
+                translations.get(index).copied().unwrap_or(Vec3::ZERO),
+            )
+        })
+        .collect()
+}
+
+pub fn update_world_transforms(nodes: &mut [Node], roots: &[usize]) {
+    let mut stack: Vec<_> = roots.iter().map(|&root| (root, Mat4::IDENTITY)).collect();
+    while let Some((index, parent_transform)) = stack.pop() {
+        let node = &mut nodes[index];
+        node.world_transform = parent_transform * node.local_transform;
+        let world_transform = node.world_transform;
+        stack.extend(node.children.iter().map(|&child| (child, world_transform)));
+    }
+}
+
+// Expects world transforms to be up to date.
+pub fn update_bounds(nodes: &mut [Node], roots: &[usize], meshes: &[Mesh]) {
+    fn update(nodes: &mut [Node], index: usize, meshes: &[Mesh]) -> Bounds {
+        let node = &nodes[index];
+        let mut bounds = node
+            .mesh
+            .map(|mesh| match node.instances.is_empty() {
+                true => meshes[mesh].bounds.transform(&node.world_transform),
+                false => node
+                    .instances
+                    .iter()
+                    .fold(Bounds::default(), |bounds, instance| {
+                        let transform = node.world_transform * *instance;
+                        bounds.union(&meshes[mesh].bounds.transform(&transform))
+                    }),
+            })
+            .unwrap_or_default();
+        for child in node.children.clone() {
+            bounds = bounds.union(&update(nodes, child, meshes));
+        }
+        nodes[index].bounds = bounds;
+        bounds
+    }
+
+    roots.iter().for_each(|&root| {
+        update(nodes, root, meshes);
+    });
+}
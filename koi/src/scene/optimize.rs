@@ -0,0 +1,621 @@
+use super::Mesh;
+
+use koi_gpu::{MorphDelta, SkinVertex, Vertex};
+use spirv_std::glam::{Vec3, Vec4Swizzles};
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::Hash,
+};
+
+// post-transform cache size assumed by the reordering and simulated by get_acmr
+pub const VERTEX_CACHE_SIZE: usize = 16;
+// how much overdraw reordering may degrade a cluster's ACMR; 1.05 allows 5%
+pub const OVERDRAW_THRESHOLD: f32 = 1.05;
+
+// Forsyth's scoring constants, as published
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+// Deduplicates vertices, reorders every surface's triangles for the post-transform cache and then
+// for overdraw, and finally lays vertices out in first-use order. Surface ranges are preserved, so
+// this must run before lod::generate_lods appends its own. Skin vertices and morph deltas follow
+// their vertices.
+pub fn optimize_mesh(mesh: &mut Mesh) {
+    let acmr_before = get_acmr(&mesh.indices, VERTEX_CACHE_SIZE);
+    let vertex_count_before = mesh.vertices.len();
+
+    let keys: Vec<MeshVertexKey> = (0..mesh.vertices.len())
+        .map(|index| get_mesh_vertex_key(mesh, index))
+        .collect();
+    let unique = get_unique_vertices(&mut mesh.indices, keys.into_iter());
+    remap_vertices(mesh, &unique);
+
+    let positions: Vec<Vec3> = mesh
+        .vertices
+        .iter()
+        .map(|vertex| vertex.position_uv_x.xyz())
+        .collect();
+    for surface in &mesh.surfaces {
+        let range = surface.start_index as usize..(surface.start_index + surface.count) as usize;
+        let indices = optimize_vertex_cache(&mesh.indices[range.clone()], positions.len());
+        let indices = optimize_overdraw(&indices, &positions, OVERDRAW_THRESHOLD);
+        mesh.indices[range].copy_from_slice(&indices);
+    }
+
+    let order = get_fetch_order(&mut mesh.indices, mesh.vertices.len());
+    remap_vertices(mesh, &order);
+
+    log::info!(
+        "koi::scene::optimize - {}: ACMR {acmr_before:.3} -> {:.3}, {vertex_count_before} -> {} vertices",
+        mesh.name,
+        get_acmr(&mesh.indices, VERTEX_CACHE_SIZE),
+        mesh.vertices.len(),
+    );
+}
+
+// Average cache miss ratio: vertex shader invocations per triangle under a FIFO cache. 3 is the
+// worst case, 0.5 the limit for large regular meshes.
+pub fn get_acmr(indices: &[u32], cache_size: usize) -> f32 {
+    if indices.len() < 3 {
+        return 0.0;
+    }
+
+    let mut cache = VecDeque::with_capacity(cache_size + 1);
+    let misses = count_misses(&mut cache, cache_size, indices);
+    misses as f32 / (indices.len() / 3) as f32
+}
+
+// Simulates a FIFO cache over indices, returning how many of them missed.
+fn count_misses(cache: &mut VecDeque<u32>, cache_size: usize, indices: &[u32]) -> u32 {
+    let mut misses = 0;
+    for index in indices {
+        if cache.contains(index) {
+            continue;
+        }
+        misses += 1;
+        cache.push_back(*index);
+        if cache.len() > cache_size {
+            cache.pop_front();
+        }
+    }
+    misses
+}
+
+// Merges bitwise identical vertices; indices are remapped in place.
+pub fn deduplicate_vertices(indices: &mut [u32], vertices: &[Vertex]) -> Vec<Vertex> {
+    get_unique_vertices(indices, vertices.iter().map(get_vertex_key))
+        .iter()
+        .map(|&index| vertices[index as usize])
+        .collect()
+}
+
+// First vertex of every distinct key, in order; indices are remapped to positions in that list.
+fn get_unique_vertices<K: Hash + Eq>(
+    indices: &mut [u32],
+    keys: impl ExactSizeIterator<Item = K>,
+) -> Vec<u32> {
+    let mut unique = Vec::with_capacity(keys.len());
+    let mut lookup = HashMap::with_capacity(keys.len());
+    let remap: Vec<u32> = keys
+        .enumerate()
+        .map(|(index, key)| {
+            *lookup.entry(key).or_insert_with(|| {
+                unique.push(index as u32);
+                unique.len() as u32 - 1
+            })
+        })
+        .collect();
+
+    indices
+        .iter_mut()
+        .for_each(|index| *index = remap[*index as usize]);
+    unique
+}
+
+fn get_vertex_key(vertex: &Vertex) -> [[u32; 4]; 4] {
+    [
+        vertex.position_uv_x,
+        vertex.normal_uv_y,
+        vertex.color,
+        vertex.tangent,
+    ]
+    .map(|channels| channels.to_array().map(f32::to_bits))
+}
+
+fn get_skin_vertex_key(skin_vertex: &SkinVertex) -> ([u32; 4], [u32; 4]) {
+    (
+        skin_vertex.joints.to_array(),
+        skin_vertex.weights.to_array().map(f32::to_bits),
+    )
+}
+
+fn get_morph_delta_key(delta: &MorphDelta) -> [[u32; 4]; 3] {
+    [delta.position, delta.normal, delta.tangent]
+        .map(|channels| channels.to_array().map(f32::to_bits))
+}
+
+// vertex, skin vertex if skinned, and one delta per morph target
+type MeshVertexKey = (
+    [[u32; 4]; 4],
+    Option<([u32; 4], [u32; 4])>,
+    Vec<[[u32; 4]; 3]>,
+);
+
+// Everything the mesh stores for one vertex; vertices only merge when all of it matches.
+fn get_mesh_vertex_key(mesh: &Mesh, index: usize) -> MeshVertexKey {
+    (
+        get_vertex_key(&mesh.vertices[index]),
+        mesh.skin_vertices.get(index).map(get_skin_vertex_key),
+        mesh.morph_targets
+            .iter()
+            .map(|deltas| get_morph_delta_key(&deltas[index]))
+            .collect(),
+    )
+}
+
+// Keeps the listed vertices, in order, with their skin vertices and morph deltas.
+fn remap_vertices(mesh: &mut Mesh, order: &[u32]) {
+    mesh.vertices = order
+        .iter()
+        .map(|&index| mesh.vertices[index as usize])
+        .collect();
+    if !mesh.skin_vertices.is_empty() {
+        mesh.skin_vertices = order
+            .iter()
+            .map(|&index| mesh.skin_vertices[index as usize])
+            .collect();
+    }
+    for deltas in &mut mesh.morph_targets {
+        *deltas = order.iter().map(|&index| deltas[index as usize]).collect();
+    }
+}
+
+fn get_vertex_score(cache_position: Option<usize>, remaining_triangles: usize) -> f32 {
+    if remaining_triangles == 0 {
+        return -1.0;
+    }
+
+    let cache_score = match cache_position {
+        // the triangle just emitted; deliberately below the freshest reusable slots
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => {
+            let scale = 1.0 / (VERTEX_CACHE_SIZE - 3) as f32;
+            (1.0 - (position - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+        None => 0.0,
+    };
+    // favours vertices with few triangles left, so they are finished off rather than stranded
+    let valence_boost =
+        VALENCE_BOOST_SCALE * (remaining_triangles as f32).powf(-VALENCE_BOOST_POWER);
+    cache_score + valence_boost
+}
+
+// Forsyth's linear-speed vertex cache optimisation: greedily emits the highest scoring triangle
+// among those touching the simulated LRU cache. Triangles keep their winding.
+pub fn optimize_vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    let triangle = |triangle_index: usize| &indices[triangle_index * 3..triangle_index * 3 + 3];
+
+    let mut vertex_triangles = vec![vec![]; vertex_count];
+    for triangle_index in 0..triangle_count {
+        for &vertex in triangle(triangle_index) {
+            vertex_triangles[vertex as usize].push(triangle_index);
+        }
+    }
+
+    let mut cache_positions = vec![None; vertex_count];
+    let mut vertex_scores: Vec<f32> = vertex_triangles
+        .iter()
+        .map(|triangles| get_vertex_score(None, triangles.len()))
+        .collect();
+    let mut triangle_scores: Vec<f32> = (0..triangle_count)
+        .map(|triangle_index| {
+            triangle(triangle_index)
+                .iter()
+                .map(|&vertex| vertex_scores[vertex as usize])
+                .sum()
+        })
+        .collect();
+    let mut emitted = vec![false; triangle_count];
+
+    let mut cache: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+    let mut optimized = Vec::with_capacity(triangle_count * 3);
+    let mut best = None;
+    let mut cursor = 0;
+    while optimized.len() < triangle_count * 3 {
+        // NB! nothing in the cache has triangles left; restart from the next unemitted triangle
+        let next = best.unwrap_or_else(|| {
+            while emitted[cursor] {
+                cursor += 1;
+            }
+            cursor
+        });
+        emitted[next] = true;
+
+        let vertices = triangle(next);
+        optimized.extend_from_slice(vertices);
+        for &vertex in vertices {
+            let triangles = &mut vertex_triangles[vertex as usize];
+            if let Some(position) = triangles.iter().position(|&index| index == next) {
+                triangles.swap_remove(position);
+            }
+        }
+
+        // emitted vertices move to the front; whatever falls off the end is evicted
+        let mut touched: Vec<u32> = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+        for &vertex in vertices.iter().chain(&cache) {
+            if !touched.contains(&vertex) {
+                touched.push(vertex);
+            }
+        }
+        cache = touched[..touched.len().min(VERTEX_CACHE_SIZE)].to_vec();
+        for &vertex in &touched {
+            cache_positions[vertex as usize] = None;
+        }
+        for (position, &vertex) in cache.iter().enumerate() {
+            cache_positions[vertex as usize] = Some(position);
+        }
+
+        // rescore everything that moved in or out of the cache, then pick among cached triangles
+        for &vertex in &touched {
+            let vertex = vertex as usize;
+            let score = get_vertex_score(cache_positions[vertex], vertex_triangles[vertex].len());
+            let delta = score - vertex_scores[vertex];
+            vertex_scores[vertex] = score;
+            for &triangle_index in &vertex_triangles[vertex] {
+                triangle_scores[triangle_index] += delta;
+            }
+        }
+
+        best = None;
+        let mut best_score = f32::MIN;
+        for &vertex in &cache {
+            for &triangle_index in &vertex_triangles[vertex as usize] {
+                if triangle_scores[triangle_index] > best_score {
+                    best_score = triangle_scores[triangle_index];
+                    best = Some(triangle_index);
+                }
+            }
+        }
+    }
+    optimized
+}
+
+// Sander et al., "Fast Triangle Reordering for Vertex Locality and Reduced Overdraw": splits the
+// cache-optimized order into clusters, at cache flushes and wherever a cluster's ACMR is within
+// threshold of its hard cluster's, then draws outward-facing clusters first.
+pub fn optimize_overdraw(indices: &[u32], positions: &[Vec3], threshold: f32) -> Vec<u32> {
+    let triangle_count = indices.len() / 3;
+    if triangle_count < 2 {
+        return indices.to_vec();
+    }
+
+    // hard boundaries: triangles missing on all three vertices, i.e. a new region of the mesh
+    let mut cache = VecDeque::with_capacity(VERTEX_CACHE_SIZE + 1);
+    let misses: Vec<u32> = indices
+        .chunks_exact(3)
+        .map(|triangle| count_misses(&mut cache, VERTEX_CACHE_SIZE, triangle))
+        .collect();
+    let mut hard_boundaries: Vec<usize> = (0..triangle_count)
+        .filter(|&triangle_index| triangle_index == 0 || misses[triangle_index] == 3)
+        .collect();
+    hard_boundaries.push(triangle_count);
+
+    let mut clusters = vec![];
+    for bounds in hard_boundaries.windows(2) {
+        let (start, end) = (bounds[0], bounds[1]);
+        let cluster_misses: u32 = misses[start..end].iter().sum();
+        let threshold_acmr = cluster_misses as f32 / (end - start) as f32 * threshold;
+
+        // soft boundaries: restart the cache once the running ACMR is back within threshold
+        cache.clear();
+        let mut cluster_start = start;
+        let mut running_misses = 0;
+        for triangle_index in start..end {
+            let triangle = &indices[triangle_index * 3..triangle_index * 3 + 3];
+            running_misses += count_misses(&mut cache, VERTEX_CACHE_SIZE, triangle);
+            let running_acmr = running_misses as f32 / (triangle_index + 1 - cluster_start) as f32;
+            if running_acmr <= threshold_acmr {
+                clusters.push(cluster_start..triangle_index + 1);
+                cluster_start = triangle_index + 1;
+                running_misses = 0;
+                cache.clear();
+            }
+        }
+        if cluster_start < end {
+            clusters.push(cluster_start..end);
+        }
+    }
+
+    let get_triangle = |triangle_index: usize| -> [Vec3; 3] {
+        [0, 1, 2].map(|corner| positions[indices[triangle_index * 3 + corner] as usize])
+    };
+
+    // area-weighted centroids and normals
+    let mut mesh_centroid = Vec3::ZERO;
+    let mut mesh_area = 0.0;
+    let cluster_shapes: Vec<(Vec3, Vec3)> = clusters
+        .iter()
+        .map(|cluster| {
+            let mut centroid = Vec3::ZERO;
+            let mut normal = Vec3::ZERO;
+            let mut area = 0.0;
+            for triangle_index in cluster.clone() {
+                let points = get_triangle(triangle_index);
+                let area_normal = (points[1] - points[0]).cross(points[2] - points[0]);
+                let triangle_area = area_normal.length();
+                centroid += (points[0] + points[1] + points[2]) / 3.0 * triangle_area;
+                normal += area_normal;
+                area += triangle_area;
+            }
+            mesh_centroid += centroid;
+            mesh_area += area;
+            let centroid = match area > 0.0 {
+                true => centroid / area,
+                false => get_triangle(cluster.start)[0],
+            };
+            (centroid, normal.normalize_or_zero())
+        })
+        .collect();
+    if mesh_area > 0.0 {
+        mesh_centroid /= mesh_area;
+    }
+
+    // NB! stable; equal keys keep their cache-friendly order
+    let mut order: Vec<usize> = (0..clusters.len()).collect();
+    let keys: Vec<f32> = cluster_shapes
+        .iter()
+        .map(|(centroid, normal)| (*centroid - mesh_centroid).dot(*normal))
+        .collect();
+    order.sort_by(|&a, &b| keys[b].total_cmp(&keys[a]));
+
+    order
+        .iter()
+        .flat_map(|&cluster_index| {
+            let cluster = &clusters[cluster_index];
+            indices[cluster.start * 3..cluster.end * 3].iter().copied()
+        })
+        .collect()
+}
+
+// Lays vertices out in the order indices first reference them; unreferenced vertices are dropped
+// and indices are remapped in place.
+pub fn optimize_vertex_fetch(indices: &mut [u32], vertices: &[Vertex]) -> Vec<Vertex> {
+    get_fetch_order(indices, vertices.len())
+        .iter()
+        .map(|&index| vertices[index as usize])
+        .collect()
+}
+
+// Referenced vertices in first-use order; indices are remapped to positions in that list.
+fn get_fetch_order(indices: &mut [u32], vertex_count: usize) -> Vec<u32> {
+    let mut remap = vec![u32::MAX; vertex_count];
+    let mut order = Vec::with_capacity(vertex_count);
+    for index in indices.iter_mut() {
+        let remapped = &mut remap[*index as usize];
+        if *remapped == u32::MAX {
+            *remapped = order.len() as u32;
+            order.push(*index);
+        }
+        *index = *remapped;
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scene::Surface;
+
+    use spirv_std::glam::{UVec4, Vec2, Vec4};
+
+    // size x size quads in the xy plane, listed row by row
+    fn grid(size: u32) -> (Vec<u32>, Vec<Vertex>) {
+        let vertices = (0..=size)
+            .flat_map(|y| (0..=size).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let position = Vec3::new(x as f32, y as f32, 0.0);
+                Vertex::new(position, Vec3::Z, Vec2::ZERO, Vec4::ONE)
+            })
+            .collect();
+        let row = size + 1;
+        let indices = (0..size)
+            .flat_map(|y| (0..size).map(move |x| y * row + x))
+            .flat_map(|corner| {
+                [
+                    corner,
+                    corner + 1,
+                    corner + row,
+                    corner + 1,
+                    corner + row + 1,
+                    corner + row,
+                ]
+            })
+            .collect();
+        (indices, vertices)
+    }
+
+    // deterministic Fisher-Yates over triangles
+    fn shuffle_triangles(indices: &[u32]) -> Vec<u32> {
+        let mut triangles: Vec<&[u32]> = indices.chunks_exact(3).collect();
+        let mut state = 0x2545_f491_u32;
+        for index in (1..triangles.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            triangles.swap(index, state as usize % (index + 1));
+        }
+        triangles.concat()
+    }
+
+    // triangles rotated to start at their smallest index, then sorted; winding is kept
+    fn canonical_triangles(indices: &[u32]) -> Vec<[u32; 3]> {
+        let mut triangles: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|triangle| {
+                let start = (0..3).min_by_key(|&corner| triangle[corner]).unwrap();
+                [0, 1, 2].map(|offset| triangle[(start + offset) % 3])
+            })
+            .collect();
+        triangles.sort_unstable();
+        triangles
+    }
+
+    #[test]
+    fn acmr_counts_misses_per_triangle() {
+        assert_eq!(get_acmr(&[], VERTEX_CACHE_SIZE), 0.0);
+        assert_eq!(get_acmr(&[0, 1, 2], VERTEX_CACHE_SIZE), 3.0);
+        assert_eq!(get_acmr(&[0, 1, 2, 2, 1, 3], VERTEX_CACHE_SIZE), 2.0);
+        // a 3 entry FIFO has evicted 0 by the time it is reused
+        assert_eq!(get_acmr(&[0, 1, 2, 1, 2, 3, 3, 2, 0], 3), 5.0 / 3.0);
+    }
+
+    #[test]
+    fn deduplicate_merges_identical_vertices() {
+        let vertex = |x: f32| Vertex::new(Vec3::new(x, 0.0, 0.0), Vec3::Z, Vec2::ZERO, Vec4::ONE);
+        let vertices = [
+            vertex(0.0),
+            vertex(1.0),
+            vertex(0.0),
+            vertex(2.0),
+            vertex(1.0),
+        ];
+        let mut indices = vec![0, 1, 3, 2, 4, 3];
+
+        let unique = deduplicate_vertices(&mut indices, &vertices);
+
+        assert_eq!(unique.len(), 3);
+        assert_eq!(indices, [0, 1, 2, 0, 1, 2]);
+        for (index, original) in [0, 1, 3, 2, 4, 3].into_iter().enumerate() {
+            assert_eq!(
+                unique[indices[index] as usize].position_uv_x,
+                vertices[original].position_uv_x
+            );
+        }
+    }
+
+    #[test]
+    fn vertex_cache_reorders_triangles_and_lowers_acmr() {
+        let (indices, vertices) = grid(16);
+        let shuffled = shuffle_triangles(&indices);
+
+        let optimized = optimize_vertex_cache(&shuffled, vertices.len());
+
+        assert_eq!(
+            canonical_triangles(&optimized),
+            canonical_triangles(&indices)
+        );
+        let acmr = get_acmr(&optimized, VERTEX_CACHE_SIZE);
+        assert!(acmr < get_acmr(&shuffled, VERTEX_CACHE_SIZE));
+        assert!(acmr < get_acmr(&indices, VERTEX_CACHE_SIZE));
+    }
+
+    #[test]
+    fn overdraw_keeps_triangles_within_threshold() {
+        let (indices, vertices) = grid(16);
+        let positions: Vec<Vec3> = vertices
+            .iter()
+            .map(|vertex| vertex.position_uv_x.xyz())
+            .collect();
+        let cache_optimized = optimize_vertex_cache(&shuffle_triangles(&indices), vertices.len());
+
+        let optimized = optimize_overdraw(&cache_optimized, &positions, OVERDRAW_THRESHOLD);
+
+        assert_eq!(
+            canonical_triangles(&optimized),
+            canonical_triangles(&indices)
+        );
+        // cluster restarts cost some locality; a fully shuffled order would sit near 3
+        assert!(get_acmr(&optimized, VERTEX_CACHE_SIZE) < 1.5);
+    }
+
+    #[test]
+    fn vertex_fetch_orders_vertices_by_first_use() {
+        let (_, vertices) = grid(1);
+        let mut indices = vec![3, 1, 2, 1, 3, 0];
+
+        let optimized = optimize_vertex_fetch(&mut indices, &vertices);
+
+        assert_eq!(indices, [0, 1, 2, 1, 0, 3]);
+        assert_eq!(optimized[0].position_uv_x, vertices[3].position_uv_x);
+        assert_eq!(optimized[3].position_uv_x, vertices[0].position_uv_x);
+    }
+
+    #[test]
+    fn vertex_fetch_drops_unreferenced_vertices() {
+        let (_, vertices) = grid(1);
+        let mut indices = vec![2, 1, 3];
+
+        let optimized = optimize_vertex_fetch(&mut indices, &vertices);
+
+        assert_eq!(optimized.len(), 3);
+        assert_eq!(indices, [0, 1, 2]);
+    }
+
+    // three copies of a grid; the first and last share their skin and morph data, so only those
+    // two merge
+    // Four copies of a grid; skin and morph data each tell one copy apart, and the first and last
+    // copies are identical.
+    fn deformed_grids() -> Mesh {
+        let (indices, vertices) = grid(4);
+        let vertex_count = vertices.len();
+        let skin_vertex = |joint: u32| SkinVertex {
+            joints: UVec4::splat(joint),
+            weights: Vec4::X,
+        };
+        let delta = |offset: f32| MorphDelta {
+            position: Vec4::new(0.0, 0.0, offset, 0.0),
+            ..Default::default()
+        };
+        let mut mesh = Mesh {
+            indices: (0..4)
+                .flat_map(|copy| {
+                    indices
+                        .iter()
+                        .map(move |index| index + copy * vertex_count as u32)
+                })
+                .collect(),
+            vertices: vertices.repeat(4),
+            skin_vertices: [0, 1, 0, 0]
+                .into_iter()
+                .flat_map(|joint| vec![skin_vertex(joint); vertex_count])
+                .collect(),
+            morph_targets: vec![
+                [0.0, 0.0, 1.0, 0.0]
+                    .into_iter()
+                    .flat_map(|offset| vec![delta(offset); vertex_count])
+                    .collect(),
+            ],
+            ..Default::default()
+        };
+        mesh.surfaces.push(Surface {
+            start_index: 0,
+            count: mesh.indices.len() as u32,
+            ..Default::default()
+        });
+        mesh
+    }
+
+    #[test]
+    fn optimize_mesh_keeps_deformation_data_with_its_vertices() {
+        let mut mesh = deformed_grids();
+        let vertex_count = mesh.vertices.len() / 4;
+
+        optimize_mesh(&mut mesh);
+
+        // only the last copy merges; ignoring either attribute would merge a second one
+        let deltas = &mesh.morph_targets[0];
+        assert_eq!(mesh.vertices.len(), 3 * vertex_count);
+        assert_eq!(mesh.skin_vertices.len(), mesh.vertices.len());
+        assert_eq!(deltas.len(), mesh.vertices.len());
+        for triangle in mesh.indices.chunks_exact(3) {
+            let first = triangle[0] as usize;
+            assert!(triangle.iter().all(|&index| {
+                mesh.skin_vertices[index as usize].joints == mesh.skin_vertices[first].joints
+                    && deltas[index as usize] == deltas[first]
+            }));
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use super::node::Node;
+
+use spirv_std::glam::Mat4;
+
+pub struct Skin {
+    pub name: String,
+    // node indices; JOINTS_0 values index this list
+    pub joints: Vec<usize>,
+    // parallel to joints; identity when the skin doesn't provide them
+    pub inverse_bind_matrices: Vec<Mat4>,
+}
+
+pub fn load_skins(document: &gltf::Document, buffers: &[gltf::buffer::Data]) -> Vec<Skin> {
+    document
+        .skins()
+        .map(|gltf_skin| {
+            let joints: Vec<usize> = gltf_skin.joints().map(|joint| joint.index()).collect();
+            let reader = gltf_skin.reader(|buffer| Some(&buffers[buffer.index()]));
+            let mut inverse_bind_matrices: Vec<Mat4> = reader
+                .read_inverse_bind_matrices()
+                .map(|iter| {
+                    iter.map(|matrix| Mat4::from_cols_array_2d(&matrix))
+                        .collect()
+                })
+                .unwrap_or_default();
+            inverse_bind_matrices.resize(joints.len(), Mat4::IDENTITY);
+
+            Skin {
+                name: String::from(gltf_skin.name().unwrap_or("")),
+                joints,
+                inverse_bind_matrices,
+            }
+        })
+        .collect()
+}
+
+// Writes one matrix per joint, taking bind-pose vertices to the skinned node's object space; the
+// node's world transform is then applied as for any other instance. Expects world transforms to be
+// up to date.
+pub fn write_joint_matrices(skin: &Skin, nodes: &[Node], node: &Node, joint_matrices: &mut [Mat4]) {
+    let inverse_node_transform = node.world_transform.inverse();
+    for ((joint_matrix, &joint), inverse_bind_matrix) in joint_matrices
+        .iter_mut()
+        .zip(&skin.joints)
+        .zip(&skin.inverse_bind_matrices)
+    {
+        *joint_matrix =
+            inverse_node_transform * nodes[joint].world_transform * *inverse_bind_matrix;
+    }
+}
@@ -0,0 +1,91 @@
+use super::Mesh;
+
+use bevy_mikktspace::Geometry;
+use koi_gpu::Vertex;
+use spirv_std::glam::{Vec4, Vec4Swizzles};
+use std::collections::HashMap;
+
+struct SurfaceGeometry<'a> {
+    indices: &'a [u32],
+    vertices: &'a [Vertex],
+    // one per corner
+    tangents: Vec<Vec4>,
+}
+
+impl SurfaceGeometry<'_> {
+    fn get_vertex(&self, face: usize, vert: usize) -> &Vertex {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl Geometry for SurfaceGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.get_vertex(face, vert).position_uv_x.xyz().to_array()
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.get_vertex(face, vert).normal_uv_y.xyz().to_array()
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        let vertex = self.get_vertex(face, vert);
+        [vertex.position_uv_x.w, vertex.normal_uv_y.w]
+    }
+
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        self.tangents[face * 3 + vert] = Vec4::from_array(tangent);
+    }
+}
+
+// MikkTSpace tangents for one surface, as glTF prescribes when TANGENT is missing. MikkTSpace works
+// per corner; corners that share a vertex but not a tangent get their own copy of the vertex (and
+// of its joints, weights and morph deltas, when it has them).
+pub fn generate_tangents(mesh: &mut Mesh, surface_index: usize) -> bool {
+    let surface = mesh.surfaces[surface_index];
+    let range = surface.start_index as usize..(surface.start_index + surface.count) as usize;
+
+    let mut geometry = SurfaceGeometry {
+        indices: &mesh.indices[range.clone()],
+        vertices: &mesh.vertices,
+        tangents: vec![Vec4::ZERO; surface.count as usize],
+    };
+    if !bevy_mikktspace::generate_tangents(&mut geometry) {
+        return false;
+    }
+    let tangents = geometry.tangents;
+
+    let mut assigned: HashMap<u32, Vec4> = HashMap::new();
+    let mut copies: HashMap<(u32, [u32; 4]), u32> = HashMap::new();
+    for (index, tangent) in mesh.indices[range].iter_mut().zip(tangents) {
+        match assigned.get(index) {
+            None => {
+                assigned.insert(*index, tangent);
+                mesh.vertices[*index as usize].tangent = tangent;
+            }
+            Some(&existing) if existing == tangent => {}
+            Some(_) => {
+                let key = (*index, tangent.to_array().map(f32::to_bits));
+                *index = *copies.entry(key).or_insert_with(|| {
+                    let vertex = mesh.vertices[*index as usize].tangent(tangent);
+                    mesh.vertices.push(vertex);
+                    if let Some(&skin_vertex) = mesh.skin_vertices.get(*index as usize) {
+                        mesh.skin_vertices.push(skin_vertex);
+                    }
+                    for deltas in &mut mesh.morph_targets {
+                        deltas.push(deltas[*index as usize]);
+                    }
+                    mesh.vertices.len() as u32 - 1
+                });
+            }
+        }
+    }
+    true
+}
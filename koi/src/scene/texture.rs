@@ -0,0 +1,282 @@
+use super::compressed::{self, CompressedImage, Ktx2Image};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use gltf::{
+    self,
+    image::{Format, Source},
+    texture::{MagFilter, MinFilter, WrappingMode},
+};
+use std::path::Path;
+
+// Decides how texel data is interpreted: Color is sRGB encoded, Data is linear.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TextureRole {
+    Color,
+    #[default]
+    Data,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Filter {
+    Nearest,
+    #[default]
+    Linear,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddressMode {
+    #[default]
+    Repeat,
+    MirroredRepeat,
+    ClampToEdge,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sampler {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap_filter: Filter,
+    pub address_mode_u: AddressMode,
+    pub address_mode_v: AddressMode,
+}
+
+pub enum ImageData {
+    // NB! always RGBA8; source formats are converted on load and mips are generated on upload
+    Rgba8(Vec<u8>),
+    Compressed(CompressedImage),
+}
+
+pub struct Image {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub data: ImageData,
+    pub role: TextureRole,
+}
+
+#[derive(Default, Clone, Copy)]
+pub struct Texture {
+    pub image: usize,
+    pub sampler: Sampler,
+}
+
+pub fn load_images(
+    document: &gltf::Document,
+    base: Option<&Path>,
+    buffers: &[gltf::buffer::Data],
+) -> Vec<Image> {
+    let roles = get_image_roles(document);
+
+    document
+        .images()
+        .map(|gltf_image| {
+            let name = String::from(gltf_image.name().unwrap_or(""));
+            let role = roles[gltf_image.index()];
+            let source = gltf_image.source();
+            let result = match is_ktx2_source(&source) {
+                true => read_source(&source, base, buffers).and_then(|bytes| load_ktx2(&bytes)),
+                false => gltf::image::Data::from_source(source, base, buffers)
+                    .map(|data| {
+                        let pixels = to_rgba8(data.format, &data.pixels);
+                        (data.width, data.height, ImageData::Rgba8(pixels))
+                    })
+                    .map_err(|error| error.to_string()),
+            };
+
+            match result {
+                Ok((width, height, data)) => Image {
+                    name,
+                    width,
+                    height,
+                    data,
+                    role,
+                },
+                Err(error) => {
+                    log::warn!("koi::scene::texture - skipping Image {name}: {error}");
+                    Image {
+                        name,
+                        width: 1,
+                        height: 1,
+                        data: ImageData::Rgba8(vec![u8::MAX; 4]),
+                        role,
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+fn load_ktx2(bytes: &[u8]) -> Result<(u32, u32, ImageData), String> {
+    if !compressed::is_ktx2(bytes) {
+        return Err(String::from("not a KTX2 container"));
+    }
+    match compressed::load_ktx2(bytes)? {
+        Ktx2Image::Rgba8 {
+            width,
+            height,
+            pixels,
+        } => Ok((width, height, ImageData::Rgba8(pixels))),
+        Ktx2Image::Compressed(compressed_image) => Ok((
+            compressed_image.width,
+            compressed_image.height,
+            ImageData::Compressed(compressed_image),
+        )),
+    }
+}
+
+pub fn load_textures(document: &gltf::Document) -> Vec<Texture> {
+    document
+        .textures()
+        .map(|gltf_texture| Texture {
+            image: get_source(&gltf_texture),
+            sampler: get_sampler(&gltf_texture.sampler()),
+        })
+        .collect()
+}
+
+// Images referenced as base color or emissive are sRGB; everything else holds linear data.
+fn get_image_roles(document: &gltf::Document) -> Vec<TextureRole> {
+    let mut roles = vec![TextureRole::Data; document.images().len()];
+
+    for material in document.materials() {
+        let color_textures = [
+            material
+                .pbr_metallic_roughness()
+                .base_color_texture()
+                .map(|info| info.texture()),
+            material.emissive_texture().map(|info| info.texture()),
+        ];
+        for texture in color_textures.into_iter().flatten() {
+            roles[texture.source().index()] = TextureRole::Color;
+            roles[get_source(&texture)] = TextureRole::Color;
+        }
+    }
+
+    roles
+}
+
+fn get_sampler(sampler: &gltf::texture::Sampler) -> Sampler {
+    let mag_filter = match sampler.mag_filter() {
+        Some(MagFilter::Nearest) => Filter::Nearest,
+        _ => Filter::Linear,
+    };
+    let (min_filter, mipmap_filter) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => {
+            (Filter::Nearest, Filter::Nearest)
+        }
+        Some(MinFilter::NearestMipmapLinear) => (Filter::Nearest, Filter::Linear),
+        Some(MinFilter::LinearMipmapNearest) => (Filter::Linear, Filter::Nearest),
+        _ => (Filter::Linear, Filter::Linear),
+    };
+
+    Sampler {
+        mag_filter,
+        min_filter,
+        mipmap_filter,
+        address_mode_u: get_address_mode(sampler.wrap_s()),
+        address_mode_v: get_address_mode(sampler.wrap_t()),
+    }
+}
+
+fn get_address_mode(wrapping_mode: WrappingMode) -> AddressMode {
+    match wrapping_mode {
+        WrappingMode::Repeat => AddressMode::Repeat,
+        WrappingMode::MirroredRepeat => AddressMode::MirroredRepeat,
+        WrappingMode::ClampToEdge => AddressMode::ClampToEdge,
+    }
+}
+
+// KHR_texture_basisu points at a KTX2 image; prefer it over the core (fallback) source.
+fn get_source(texture: &gltf::Texture) -> usize {
+    texture
+        .extension_value("KHR_texture_basisu")
+        .and_then(|extension| extension.get("source"))
+        .and_then(|source| source.as_u64())
+        .map(|source| source as usize)
+        .unwrap_or_else(|| texture.source().index())
+}
+
+// KTX2 images carry the image/ktx2 mime type, which URIs may leave to their extension.
+fn is_ktx2_source(source: &Source) -> bool {
+    match *source {
+        Source::View { mime_type, .. } => mime_type == "image/ktx2",
+        Source::Uri { uri, mime_type } => {
+            mime_type == Some("image/ktx2")
+                || uri.starts_with("data:image/ktx2")
+                || uri.ends_with(".ktx2")
+        }
+    }
+}
+
+fn read_source(
+    source: &Source,
+    base: Option<&Path>,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Vec<u8>, String> {
+    match *source {
+        Source::View { ref view, .. } => {
+            let buffer = &buffers[view.buffer().index()];
+            Ok(buffer[view.offset()..view.offset() + view.length()].to_vec())
+        }
+        Source::Uri { uri, .. } => match uri.strip_prefix("data:") {
+            Some(data_uri) => {
+                let (_, encoded) = data_uri
+                    .split_once(";base64,")
+                    .ok_or("unsupported data URI")?;
+                BASE64_STANDARD
+                    .decode(encoded)
+                    .map_err(|error| format!("failed to decode data URI: {error}"))
+            }
+            None => {
+                // NB! relative references are percent-encoded, e.g. spaces as %20
+                let path = urlencoding::decode(uri)
+                    .map_err(|error| format!("failed to decode URI {uri}: {error}"))?;
+                std::fs::read(base.unwrap_or(Path::new("")).join(&*path))
+                    .map_err(|error| format!("failed to read {path}: {error}"))
+            }
+        },
+    }
+}
+
+fn to_rgba8(format: Format, pixels: &[u8]) -> Vec<u8> {
+    match format {
+        Format::R8G8B8A8 => pixels.to_vec(),
+        Format::R8G8B8 => expand::<1, 3>(pixels, |c| c[0]),
+        Format::R8G8 => expand::<1, 2>(pixels, |c| c[0]),
+        Format::R8 => expand::<1, 1>(pixels, |c| c[0]),
+        Format::R16G16B16A16 => expand::<2, 4>(pixels, unorm16_to_unorm8),
+        Format::R16G16B16 => expand::<2, 3>(pixels, unorm16_to_unorm8),
+        Format::R16G16 => expand::<2, 2>(pixels, unorm16_to_unorm8),
+        Format::R16 => expand::<2, 1>(pixels, unorm16_to_unorm8),
+        Format::R32G32B32A32FLOAT => expand::<4, 4>(pixels, float32_to_unorm8),
+        Format::R32G32B32FLOAT => expand::<4, 3>(pixels, float32_to_unorm8),
+    }
+}
+
+// Widens N-channel texels of B-byte components to RGBA8; missing channels are zeroed, alpha is opaque.
+// NB! single-channel images are splatted to grey, matching how glTF viewers display them
+fn expand<const B: usize, const N: usize>(pixels: &[u8], convert: fn(&[u8]) -> u8) -> Vec<u8> {
+    pixels
+        .chunks_exact(B * N)
+        .flat_map(|texel| {
+            let mut rgba = [0, 0, 0, u8::MAX];
+            for (channel, component) in texel.chunks_exact(B).enumerate() {
+                rgba[channel] = convert(component);
+            }
+            if N == 1 {
+                rgba[1] = rgba[0];
+                rgba[2] = rgba[0];
+            }
+            rgba
+        })
+        .collect()
+}
+
+fn unorm16_to_unorm8(component: &[u8]) -> u8 {
+    (u16::from_ne_bytes([component[0], component[1]]) >> 8) as u8
+}
+
+fn float32_to_unorm8(component: &[u8]) -> u8 {
+    let value = f32::from_ne_bytes([component[0], component[1], component[2], component[3]]);
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
@@ -0,0 +1,221 @@
+#![cfg_attr(target_arch = "spirv", no_std)]
+
+use koi_gpu::{
+    CullPushConstants, DrawIndexedIndirectCommand, Instance, InstanceLods, OcclusionData,
+    select_lod,
+};
+use spirv_std::arch::atomic_i_add;
+use spirv_std::glam::{UVec2, UVec3, UVec4, Vec2, Vec3, Vec4, Vec4Swizzles};
+use spirv_std::image::{Image, sample_with};
+use spirv_std::memory::{Scope, Semantics};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use spirv_std::spirv;
+
+pub type PyramidImage = Image!(2D, type = f32, sampled = true, depth = false);
+
+// counters written ahead of the draw counts; read back for statistics
+const VISIBLE: usize = 0;
+const FRUSTUM_CULLED: usize = 1;
+const OCCLUSION_CULLED: usize = 2;
+
+// The instance's bounding sphere in world space, scaled by the instance's largest axis scale; the
+// scale is returned too, for LOD selection.
+fn get_world_sphere(instance: &Instance) -> (Vec3, f32, f32) {
+    let transform = instance.transform;
+    let center = transform.transform_point3(instance.bounding_sphere.xyz());
+    let scale = Vec3::new(
+        transform.x_axis.xyz().length(),
+        transform.y_axis.xyz().length(),
+        transform.z_axis.xyz().length(),
+    );
+    let scale = scale.max_element();
+    (center, instance.bounding_sphere.w * scale, scale)
+}
+
+fn is_in_frustum(center: Vec3, radius: f32, constants: &CullPushConstants) -> bool {
+    let mut index = 0;
+    while index < 6 {
+        let plane = constants.frustum_planes[index];
+        if plane.xyz().dot(center) + plane.w < -radius {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
+
+// Projects the sphere's bounding box with the pyramid's view-projection and compares its nearest
+// depth against the farthest depth stored at the mip where the box spans at most 2x2 texels.
+fn is_occluded(
+    center: Vec3,
+    radius: f32,
+    occlusion: &OcclusionData,
+    pyramid: &PyramidImage,
+) -> bool {
+    if occlusion.pyramid.w == 0.0 {
+        return false;
+    }
+
+    let mut min_uv = Vec2::ONE;
+    let mut max_uv = Vec2::ZERO;
+    let mut max_depth = 0.0f32;
+    let mut corner = 0;
+    while corner < 8 {
+        let offset = Vec3::new(
+            if corner & 1 == 0 { -radius } else { radius },
+            if corner & 2 == 0 { -radius } else { radius },
+            if corner & 4 == 0 { -radius } else { radius },
+        );
+        let clip = occlusion.view_projection * Vec4::from((center + offset, 1.0));
+        // crosses the near plane; can't be projected conservatively
+        if clip.w <= 0.0 {
+            return false;
+        }
+        let ndc = clip.xyz() / clip.w;
+        // y is flipped by the negative viewport height
+        let uv = Vec2::new(ndc.x * 0.5 + 0.5, 0.5 - ndc.y * 0.5);
+        min_uv = min_uv.min(uv);
+        max_uv = max_uv.max(uv);
+        // reverse-Z: the nearest point has the largest depth
+        max_depth = max_depth.max(ndc.z);
+        corner += 1;
+    }
+    min_uv = min_uv.clamp(Vec2::ZERO, Vec2::ONE);
+    max_uv = max_uv.clamp(Vec2::ZERO, Vec2::ONE);
+
+    let pyramid_size = occlusion.pyramid.xy();
+    let extent = (max_uv - min_uv) * pyramid_size;
+    let max_level = occlusion.pyramid.z - 1.0;
+    let level = extent
+        .max_element()
+        .max(1.0)
+        .log2()
+        .ceil()
+        .clamp(0.0, max_level) as u32;
+
+    let level_size = (pyramid_size.as_uvec2() >> level).max(UVec2::ONE);
+    let max_texel = level_size - UVec2::ONE;
+    let min_coord = (min_uv * level_size.as_vec2()).as_uvec2().min(max_texel);
+    let max_coord = (max_uv * level_size.as_vec2()).as_uvec2().min(max_texel);
+
+    let lod = sample_with::lod(level as i32);
+    let texels: [Vec4; 4] = [
+        pyramid.fetch_with(min_coord, lod),
+        pyramid.fetch_with(UVec2::new(max_coord.x, min_coord.y), lod),
+        pyramid.fetch_with(UVec2::new(min_coord.x, max_coord.y), lod),
+        pyramid.fetch_with(max_coord, lod),
+    ];
+    let occluder_depth = texels[0]
+        .x
+        .min(texels[1].x)
+        .min(texels[2].x)
+        .min(texels[3].x);
+
+    max_depth < occluder_depth
+}
+
+// Projected error is measured from the sphere's nearest point; inside it, the finest level wins.
+fn get_lod(
+    center: Vec3,
+    radius: f32,
+    scale: f32,
+    constants: &CullPushConstants,
+    lods: &InstanceLods,
+    previous: u32,
+) -> u32 {
+    let distance = (center.distance(constants.lod.xyz()) - radius).max(1e-3);
+    select_lod(lods, scale * constants.lod.w / distance, previous)
+}
+
+fn increment(counter: &mut u32) -> u32 {
+    unsafe { atomic_i_add::<u32, { Scope::Device as u32 }, { Semantics::NONE.bits() }>(counter, 1) }
+}
+
+// Ordered draw groups keep a command per instance, so a culled instance leaves one that draws
+// nothing; compacted groups just skip it.
+fn skip_draw(
+    draw_group: UVec4,
+    instance_index: u32,
+    draw_commands: &mut [DrawIndexedIndirectCommand],
+    counters: &mut [u32],
+) {
+    if draw_group.z == 0 {
+        return;
+    }
+    increment(&mut counters[draw_group.y as usize]);
+    draw_commands[draw_group.x as usize] = DrawIndexedIndirectCommand {
+        index_count: 0,
+        instance_count: 0,
+        first_index: 0,
+        vertex_offset: 0,
+        first_instance: instance_index,
+    };
+}
+
+// Writes one compacted indirect command per visible instance, at its selected level of detail, into
+// its draw group's range, or into the instance's own slot for ordered groups; first_instance
+// carries the instance index.
+// NB! occlusion is tested against the previous frame's pyramid; newly disoccluded instances pop in a
+// frame late
+#[spirv(compute(threads(64)))]
+pub fn main_cs(
+    #[spirv(push_constant)] constants: &CullPushConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] instances: &[Instance],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)]
+    draw_commands: &mut [DrawIndexedIndirectCommand],
+    // 0: visible, 1: frustum culled, 2: occlusion culled, then each draw group's draw count
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] counters: &mut [u32],
+    #[spirv(descriptor_set = 0, binding = 3)] pyramid: &PyramidImage,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 4)] occlusion: &OcclusionData,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 5)] instance_lods: &[InstanceLods],
+    // level each instance was last drawn at, kept for hysteresis
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 6)] lod_levels: &mut [u32],
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let instance_index = global_coord.x;
+    if instance_index >= constants.counts.x {
+        return;
+    }
+
+    let instance = &instances[instance_index as usize];
+    let (center, radius, scale) = get_world_sphere(instance);
+    // deformed vertices may leave the bind-pose sphere
+    let is_deformed = instance.vertex_format.y != 0;
+    let draw_group = instance.draw_group;
+    if !is_deformed && !is_in_frustum(center, radius, constants) {
+        increment(&mut counters[FRUSTUM_CULLED]);
+        skip_draw(draw_group, instance_index, draw_commands, counters);
+        return;
+    }
+    if !is_deformed && is_occluded(center, radius, occlusion, pyramid) {
+        increment(&mut counters[OCCLUSION_CULLED]);
+        skip_draw(draw_group, instance_index, draw_commands, counters);
+        return;
+    }
+
+    let lods = &instance_lods[instance_index as usize];
+    let level = get_lod(
+        center,
+        radius,
+        scale,
+        constants,
+        lods,
+        lod_levels[instance_index as usize],
+    );
+    lod_levels[instance_index as usize] = level;
+
+    increment(&mut counters[VISIBLE]);
+    let slot = increment(&mut counters[draw_group.y as usize]);
+    let slot = match draw_group.z {
+        0 => draw_group.x + slot,
+        _ => draw_group.x,
+    };
+    draw_commands[slot as usize] = DrawIndexedIndirectCommand {
+        index_count: lods.index_counts[level as usize],
+        instance_count: 1,
+        first_index: lods.first_indices[level as usize],
+        vertex_offset: 0,
+        first_instance: instance_index,
+    };
+}
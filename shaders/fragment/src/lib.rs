@@ -1,11 +1,213 @@
 #![cfg_attr(target_arch = "spirv", no_std)]
 
+use koi_gpu::{FrameData, NO_TEXTURE, PushConstants};
+use spirv_std::arch::kill;
+use spirv_std::image::{Image, SampledImage};
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
 use spirv_std::{
-    glam::{Vec2, Vec3, Vec4},
+    RuntimeArray, Sampler,
+    glam::{UVec4, Vec2, Vec3, Vec4, Vec4Swizzles},
     spirv,
 };
 
+pub type CubeImage = Image!(cube, type = f32, sampled = true, depth = false);
+pub type LutImage = Image!(2D, type = f32, sampled = true, depth = false);
+pub type Texture = SampledImage<Image!(2D, type = f32, sampled = true, depth = false)>;
+
+// Schlick's approximation with a roughness term, so rough surfaces don't over-brighten at grazing angles.
+fn fresnel_schlick_roughness(n_dot_v: f32, f0: Vec3, roughness: f32) -> Vec3 {
+    let f90 = Vec3::splat(1.0 - roughness).max(f0);
+    f0 + (f90 - f0) * (1.0 - n_dot_v).clamp(0.0, 1.0).powf(5.0)
+}
+
+fn reflect(incident: Vec3, normal: Vec3) -> Vec3 {
+    incident - 2.0 * normal.dot(incident) * normal
+}
+
+// Tangent-space normal mapping; the tangent is re-orthogonalized against the interpolated normal
+// and the bitangent rebuilt from its sign, as MikkTSpace expects.
+fn get_mapped_normal(normal: Vec3, tangent: Vec4, sampled: Vec4, scale: f32) -> Vec3 {
+    let tangent_normal = sampled.xyz() * 2.0 - Vec3::ONE;
+    let tangent_normal = Vec3::new(
+        tangent_normal.x * scale,
+        tangent_normal.y * scale,
+        tangent_normal.z,
+    );
+    let t = (tangent.xyz() - normal * normal.dot(tangent.xyz())).normalize_or_zero();
+    let b = normal.cross(t) * tangent.w;
+    (t * tangent_normal.x + b * tangent_normal.y + normal * tangent_normal.z).normalize_or_zero()
+}
+
+// The interpolated vertex shader outputs.
+struct Varyings {
+    // vertex color times the base color factor; the entry points fold in the base color texture
+    color: Vec4,
+    uv: Vec2,
+    current_position: Vec4,
+    previous_position: Vec4,
+    normal: Vec3,
+    world_position: Vec3,
+    // w is 0 when the vertex has no tangent
+    tangent: Vec4,
+    // the instance's textures and factors, as in Instance
+    textures: UVec4,
+    factors: Vec4,
+}
+
+// Vertex color times the base color factor, times the base color texture when there is one.
+fn get_base_color(varyings: &Varyings, textures: &RuntimeArray<Texture>) -> Vec4 {
+    let mut base_color = varyings.color;
+    if varyings.textures.y != NO_TEXTURE {
+        let texture = unsafe { textures.index(varyings.textures.y as usize) };
+        let sampled: Vec4 = texture.sample(varyings.uv);
+        base_color *= sampled;
+    }
+    base_color
+}
+
+// Returns the lit color and the fragment's velocity.
+#[cfg_attr(not(target_arch = "spirv"), allow(unused_variables))]
+fn shade(
+    varyings: &Varyings,
+    constants: &PushConstants,
+    irradiance: &CubeImage,
+    prefiltered: &CubeImage,
+    brdf_lut: &LutImage,
+    sampler: &Sampler,
+    textures: &RuntimeArray<Texture>,
+) -> (Vec4, Vec2) {
+    #[cfg(target_arch = "spirv")]
+    let frame_data = unsafe { FrameData::load(constants.frame_data_address) };
+    // host builds only type-check the shader
+    #[cfg(not(target_arch = "spirv"))]
+    let frame_data = FrameData::default();
+    let intensity = frame_data.environment.x;
+    let max_lod = frame_data.environment.y;
+    let metallic = varyings.factors.y;
+    let roughness = varyings.factors.z;
+
+    let albedo = varyings.color.xyz();
+    let mut normal = varyings.normal.normalize();
+    if varyings.textures.x != NO_TEXTURE && varyings.tangent.w != 0.0 {
+        let normal_map = unsafe { textures.index(varyings.textures.x as usize) };
+        let sampled: Vec4 = normal_map.sample(varyings.uv);
+        normal = get_mapped_normal(normal, varyings.tangent, sampled, varyings.factors.x);
+    }
+    let view = (frame_data.camera_position.xyz() - varyings.world_position).normalize();
+    let n_dot_v = normal.dot(view).max(0.0);
+
+    // split-sum IBL: diffuse from irradiance, specular from prefiltered environment and BRDF LUT
+    let f0 = Vec3::splat(0.04).lerp(albedo, metallic);
+    let fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    let diffuse_weight = (Vec3::ONE - fresnel) * (1.0 - metallic);
+
+    let irradiance: Vec4 = irradiance.sample_by_lod(*sampler, normal, 0.0);
+    let diffuse = irradiance.xyz() * albedo;
+
+    let reflection = reflect(-view, normal);
+    let prefiltered: Vec4 = prefiltered.sample_by_lod(*sampler, reflection, roughness * max_lod);
+    let brdf: Vec4 = brdf_lut.sample_by_lod(*sampler, Vec2::new(n_dot_v, roughness), 0.0);
+    let specular = prefiltered.xyz() * (fresnel * brdf.x + Vec3::splat(brdf.y));
+
+    let color = (diffuse_weight * diffuse + specular) * intensity;
+
+    let current = varyings.current_position.xy() / varyings.current_position.w;
+    let previous = varyings.previous_position.xy() / varyings.previous_position.w;
+    // NDC delta to UV delta; y is flipped by the negative viewport height
+    let velocity = (current - previous) * Vec2::new(0.5, -0.5);
+    (Vec4::from((color, varyings.color.w)), velocity)
+}
+
+// Opaque and blended surfaces. NB! no discard, so early depth testing stays on
+#[spirv(fragment)]
+pub fn main_fs(
+    in_color: Vec4,
+    in_uv: Vec2,
+    in_current_position: Vec4,
+    in_previous_position: Vec4,
+    in_normal: Vec3,
+    in_world_position: Vec3,
+    in_tangent: Vec4,
+    #[spirv(flat)] in_textures: UVec4,
+    #[spirv(flat)] in_factors: Vec4,
+    #[spirv(push_constant)] constants: &PushConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] irradiance: &CubeImage,
+    #[spirv(descriptor_set = 0, binding = 1)] prefiltered: &CubeImage,
+    #[spirv(descriptor_set = 0, binding = 2)] brdf_lut: &LutImage,
+    #[spirv(descriptor_set = 0, binding = 3)] sampler: &Sampler,
+    // NB! indexed per instance; rust-gpu can't decorate the index NonUniform, which works in
+    // practice but isn't guaranteed by the spec
+    #[spirv(descriptor_set = 2, binding = 0)] textures: &RuntimeArray<Texture>,
+    output: &mut Vec4,
+    out_velocity: &mut Vec2,
+) {
+    let mut varyings = Varyings {
+        color: in_color,
+        uv: in_uv,
+        current_position: in_current_position,
+        previous_position: in_previous_position,
+        normal: in_normal,
+        world_position: in_world_position,
+        tangent: in_tangent,
+        textures: in_textures,
+        factors: in_factors,
+    };
+    varyings.color = get_base_color(&varyings, textures);
+    (*output, *out_velocity) = shade(
+        &varyings,
+        constants,
+        irradiance,
+        prefiltered,
+        brdf_lut,
+        sampler,
+        textures,
+    );
+}
+
+// Masked surfaces; discards where the base color alpha falls below the instance's alpha cutoff.
 #[spirv(fragment)]
-pub fn main_fs(in_color: Vec3, _in_uv: Vec2, output: &mut Vec4) {
-    *output = Vec4::from((in_color, 1.0));
+pub fn main_masked_fs(
+    in_color: Vec4,
+    in_uv: Vec2,
+    in_current_position: Vec4,
+    in_previous_position: Vec4,
+    in_normal: Vec3,
+    in_world_position: Vec3,
+    in_tangent: Vec4,
+    #[spirv(flat)] in_textures: UVec4,
+    #[spirv(flat)] in_factors: Vec4,
+    #[spirv(push_constant)] constants: &PushConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] irradiance: &CubeImage,
+    #[spirv(descriptor_set = 0, binding = 1)] prefiltered: &CubeImage,
+    #[spirv(descriptor_set = 0, binding = 2)] brdf_lut: &LutImage,
+    #[spirv(descriptor_set = 0, binding = 3)] sampler: &Sampler,
+    #[spirv(descriptor_set = 2, binding = 0)] textures: &RuntimeArray<Texture>,
+    output: &mut Vec4,
+    out_velocity: &mut Vec2,
+) {
+    let mut varyings = Varyings {
+        color: in_color,
+        uv: in_uv,
+        current_position: in_current_position,
+        previous_position: in_previous_position,
+        normal: in_normal,
+        world_position: in_world_position,
+        tangent: in_tangent,
+        textures: in_textures,
+        factors: in_factors,
+    };
+    varyings.color = get_base_color(&varyings, textures);
+    if varyings.color.w < varyings.factors.w {
+        kill();
+    }
+    (*output, *out_velocity) = shade(
+        &varyings,
+        constants,
+        irradiance,
+        prefiltered,
+        brdf_lut,
+        sampler,
+        textures,
+    );
 }
@@ -0,0 +1,83 @@
+#![no_std]
+
+use koi_gpu::{ComputePushConstants, load_clamped, sample_bilinear};
+use spirv_std::glam::{IVec2, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use spirv_std::image::Image;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use spirv_std::spirv;
+
+pub type Image2 = Image!(2D, format = rgba16f, sampled = false, depth = false);
+
+const LUMA: Vec3 = Vec3::new(0.299, 0.587, 0.114);
+const REDUCE_MIN: f32 = 1.0 / 128.0;
+const REDUCE_MUL: f32 = 1.0 / 8.0;
+const SPAN_MAX: f32 = 8.0;
+
+fn luma(image: &Image2, coord: IVec2, image_size: UVec2) -> f32 {
+    load_clamped(image, coord, image_size).xyz().dot(LUMA)
+}
+
+// Classic FXAA (Lottes), edge-directed blur along the local luma gradient.
+#[spirv(compute(threads(16, 16)))]
+pub fn main_cs(
+    #[spirv(push_constant)] constants: &ComputePushConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] color: &Image2,
+    #[spirv(descriptor_set = 0, binding = 3)] output: &Image2,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let texel_coord = global_coord.xy();
+    let image_size: UVec2 = color.query_size();
+
+    if texel_coord.x >= image_size.x || texel_coord.y >= image_size.y {
+        return;
+    }
+
+    let edge_threshold = constants.data_0.z;
+    let edge_threshold_min = constants.data_0.w;
+
+    let coord = texel_coord.as_ivec2();
+    let center: Vec4 = color.read(texel_coord);
+    let luma_center = center.xyz().dot(LUMA);
+    let luma_nw = luma(color, coord + IVec2::new(-1, -1), image_size);
+    let luma_ne = luma(color, coord + IVec2::new(1, -1), image_size);
+    let luma_sw = luma(color, coord + IVec2::new(-1, 1), image_size);
+    let luma_se = luma(color, coord + IVec2::new(1, 1), image_size);
+
+    let luma_min = luma_center.min(luma_nw.min(luma_ne).min(luma_sw.min(luma_se)));
+    let luma_max = luma_center.max(luma_nw.max(luma_ne).max(luma_sw.max(luma_se)));
+
+    if luma_max - luma_min < edge_threshold_min.max(luma_max * edge_threshold) {
+        unsafe { output.write(texel_coord, center) };
+        return;
+    }
+
+    let mut direction = Vec2::new(
+        -((luma_nw + luma_ne) - (luma_sw + luma_se)),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let direction_reduce =
+        ((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * REDUCE_MUL).max(REDUCE_MIN);
+    let inverse_direction_min = 1.0 / (direction.x.abs().min(direction.y.abs()) + direction_reduce);
+    direction = (direction * inverse_direction_min)
+        .clamp(Vec2::splat(-SPAN_MAX), Vec2::splat(SPAN_MAX))
+        / image_size.as_vec2();
+
+    let uv = (texel_coord.as_vec2() + Vec2::splat(0.5)) / image_size.as_vec2();
+    let color_a = 0.5
+        * (sample_bilinear(color, uv + direction * (1.0 / 3.0 - 0.5), image_size)
+            + sample_bilinear(color, uv + direction * (2.0 / 3.0 - 0.5), image_size));
+    let color_b = color_a * 0.5
+        + 0.25
+            * (sample_bilinear(color, uv - direction * 0.5, image_size)
+                + sample_bilinear(color, uv + direction * 0.5, image_size));
+
+    let luma_b = color_b.xyz().dot(LUMA);
+    let result = if luma_b < luma_min || luma_b > luma_max {
+        color_a
+    } else {
+        color_b
+    };
+
+    unsafe { output.write(texel_coord, Vec4::from((result.xyz(), center.w))) };
+}
@@ -0,0 +1,18 @@
+// Exercises the build.rs HLSL path; checked by the reflection tests against glsl_test.
+
+struct Constants
+{
+    float4 color;
+    float scale;
+};
+
+[[vk::push_constant]] ConstantBuffer<Constants> constants;
+[[vk::binding(0, 0)]] RWTexture2D<float4> image;
+[[vk::binding(1, 0)]] RWStructuredBuffer<float> values;
+
+[numthreads(8, 8, 1)]
+void main(uint3 global_coord : SV_DispatchThreadID)
+{
+    float value = values[global_coord.x] * constants.scale;
+    image[global_coord.xy] = constants.color * value;
+}
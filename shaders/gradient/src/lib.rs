@@ -1,5 +1,6 @@
-#![no_std]
+#![cfg_attr(target_arch = "spirv", no_std)]
 
+use koi_gpu::ComputePushConstants;
 use spirv_std::glam::{UVec2, UVec3, Vec3Swizzles, Vec4};
 use spirv_std::image::Image;
 use spirv_std::spirv;
@@ -27,30 +28,83 @@ pub fn main_cs_old(
     }
 }
 
-#[derive(Copy, Clone)]
-#[allow(unused)]
-pub struct PushConstants {
-    data_0: Vec4,
-    data_1: Vec4,
-    data_2: Vec4,
-    data_3: Vec4,
+// What main_cs writes at global_coord.xy; None outside the image.
+pub fn get_texel(
+    global_coord: UVec3,
+    image_size: UVec2,
+    constants: &ComputePushConstants,
+) -> Option<Vec4> {
+    let texel_coord = global_coord.xy();
+    if texel_coord.x >= image_size.x || texel_coord.y >= image_size.y {
+        return None;
+    }
+
+    let top_color = constants.data_0;
+    let bottom_color = constants.data_1;
+    let blend = texel_coord.y as f32 / image_size.y as f32;
+    Some(blend * bottom_color + (1.0 - blend) * top_color)
 }
 
 #[spirv(compute(threads(16, 16)))]
 pub fn main_cs(
-    #[spirv(push_constant)] constants: &PushConstants,
+    #[spirv(push_constant)] constants: &ComputePushConstants,
     #[spirv(descriptor_set = 0, binding = 0)] image: &Image2,
     #[spirv(global_invocation_id)] global_coord: UVec3,
 ) {
-    let texel_coord = global_coord.xy();
     let image_size: UVec2 = image.query_size();
+    if let Some(color) = get_texel(global_coord, image_size, constants) {
+        unsafe { image.write(global_coord.xy(), color) };
+    }
+}
 
-    let top_color = constants.data_0;
-    let bottom_color = constants.data_1;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if texel_coord.x < image_size.x && texel_coord.y < image_size.y {
-        let blend = texel_coord.y as f32 / image_size.y as f32;
-        let color = blend * bottom_color + (1.0 - blend) * top_color;
-        unsafe { image.write(texel_coord, color) };
+    use koi_gpu::dispatch_2d;
+
+    const GROUP_SIZE: UVec2 = UVec2::splat(16);
+
+    fn get_constants(top_color: Vec4, bottom_color: Vec4) -> ComputePushConstants {
+        ComputePushConstants::default()
+            .data_0(top_color)
+            .data_1(bottom_color)
+    }
+
+    #[test]
+    fn blends_rows_from_top_to_bottom() {
+        let top_color = Vec4::new(1.0, 0.0, 0.0, 1.0);
+        let bottom_color = Vec4::new(0.0, 0.0, 1.0, 1.0);
+        let constants = get_constants(top_color, bottom_color);
+        let image_size = UVec2::new(3, 4);
+        let image = dispatch_2d(image_size, GROUP_SIZE, |global_coord| {
+            get_texel(global_coord, image_size, &constants)
+        });
+
+        // rows at 0, 1/4, 1/2 and 3/4 of the way down
+        let rows = [
+            Vec4::new(1.0, 0.0, 0.0, 1.0),
+            Vec4::new(0.75, 0.0, 0.25, 1.0),
+            Vec4::new(0.5, 0.0, 0.5, 1.0),
+            Vec4::new(0.25, 0.0, 0.75, 1.0),
+        ];
+        for (index, texel) in image.iter().enumerate() {
+            let expected = rows[index / image_size.x as usize];
+            assert!(texel.abs_diff_eq(expected, 1e-6), "texel {index}: {texel}");
+        }
+    }
+
+    #[test]
+    fn skips_invocations_outside_the_image() {
+        let constants = get_constants(Vec4::ONE, Vec4::ZERO);
+        let image_size = UVec2::new(17, 1);
+        assert!(get_texel(UVec3::new(17, 0, 0), image_size, &constants).is_none());
+        assert!(get_texel(UVec3::new(0, 1, 0), image_size, &constants).is_none());
+
+        // two groups wide, the second one almost entirely outside the image
+        let image = dispatch_2d(image_size, GROUP_SIZE, |global_coord| {
+            get_texel(global_coord, image_size, &constants)
+        });
+        assert!(image.iter().all(|texel| *texel == Vec4::ONE));
     }
 }
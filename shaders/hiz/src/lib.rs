@@ -0,0 +1,49 @@
+#![no_std]
+
+use spirv_std::glam::{UVec2, UVec3, Vec3Swizzles, Vec4};
+use spirv_std::image::Image;
+use spirv_std::spirv;
+
+pub type DepthImage = Image!(2D, type = f32, sampled = true, depth = false);
+pub type PyramidImage = Image!(2D, format = r32f, sampled = false, depth = false);
+
+// Min reduction from mip N-1 (or the depth image) into mip N; reverse-Z puts the farthest depth at
+// the minimum. Odd source sizes fold their last row/column into the edge texels, so no depth is
+// lost and the pyramid stays conservative.
+#[spirv(compute(threads(8, 8)))]
+pub fn main_cs(
+    #[spirv(descriptor_set = 0, binding = 0)] src: &DepthImage,
+    #[spirv(descriptor_set = 0, binding = 1)] dst: &PyramidImage,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let texel_coord = global_coord.xy();
+    let dst_size: UVec2 = dst.query_size();
+
+    if texel_coord.x >= dst_size.x || texel_coord.y >= dst_size.y {
+        return;
+    }
+
+    let src_size: UVec2 = src.query_size_lod(0);
+    let base = texel_coord * 2;
+    let mut end = (base + UVec2::ONE).min(src_size - UVec2::ONE);
+    if texel_coord.x == dst_size.x - 1 && src_size.x > 1 && src_size.x % 2 == 1 {
+        end.x = src_size.x - 1;
+    }
+    if texel_coord.y == dst_size.y - 1 && src_size.y > 1 && src_size.y % 2 == 1 {
+        end.y = src_size.y - 1;
+    }
+
+    let mut depth = 1.0f32;
+    let mut y = base.y;
+    while y <= end.y {
+        let mut x = base.x;
+        while x <= end.x {
+            let texel: Vec4 = src.fetch(UVec2::new(x, y));
+            depth = depth.min(texel.x);
+            x += 1;
+        }
+        y += 1;
+    }
+
+    unsafe { dst.write(texel_coord, Vec4::splat(depth)) };
+}
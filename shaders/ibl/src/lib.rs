@@ -0,0 +1,276 @@
+#![no_std]
+
+use core::f32::consts::PI;
+use koi_gpu::ComputePushConstants;
+use spirv_std::glam::{IVec2, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use spirv_std::image::Image;
+#[cfg(target_arch = "spirv")]
+use spirv_std::num_traits::Float;
+use spirv_std::{Sampler, spirv};
+
+pub type EquirectImage = Image!(2D, format = rgba32f, sampled = false, depth = false);
+pub type CubeImage = Image!(cube, type = f32, sampled = true, depth = false);
+pub type CubeStorageImage = Image!(
+    2D,
+    format = rgba16f,
+    sampled = false,
+    arrayed = true,
+    depth = false
+);
+pub type LutImage = Image!(2D, format = rg16f, sampled = false, depth = false);
+
+// Vulkan cube face order: +X, -X, +Y, -Y, +Z, -Z.
+fn get_cube_direction(texel_coord: UVec2, face: u32, face_size: u32) -> Vec3 {
+    let uv = (texel_coord.as_vec2() + Vec2::splat(0.5)) / face_size as f32 * 2.0 - Vec2::ONE;
+    let direction = match face {
+        0 => Vec3::new(1.0, -uv.y, -uv.x),
+        1 => Vec3::new(-1.0, -uv.y, uv.x),
+        2 => Vec3::new(uv.x, 1.0, uv.y),
+        3 => Vec3::new(uv.x, -1.0, -uv.y),
+        4 => Vec3::new(uv.x, -uv.y, 1.0),
+        _ => Vec3::new(-uv.x, -uv.y, -1.0),
+    };
+    direction.normalize()
+}
+
+fn load_equirect(image: &EquirectImage, coord: IVec2, image_size: UVec2) -> Vec4 {
+    let size = image_size.as_ivec2();
+    // wrap horizontally, clamp at the poles
+    let x = ((coord.x % size.x) + size.x) % size.x;
+    let y = coord.y.clamp(0, size.y - 1);
+    image.read(IVec2::new(x, y).as_uvec2())
+}
+
+fn sample_equirect(image: &EquirectImage, direction: Vec3, image_size: UVec2) -> Vec4 {
+    let uv = Vec2::new(
+        direction.z.atan2(direction.x) / (2.0 * PI) + 0.5,
+        direction.y.clamp(-1.0, 1.0).acos() / PI,
+    );
+    let position = uv * image_size.as_vec2() - Vec2::splat(0.5);
+    let base = position.floor();
+    let weight = position - base;
+    let coord = base.as_ivec2();
+
+    let top = load_equirect(image, coord, image_size).lerp(
+        load_equirect(image, coord + IVec2::new(1, 0), image_size),
+        weight.x,
+    );
+    let bottom = load_equirect(image, coord + IVec2::new(0, 1), image_size).lerp(
+        load_equirect(image, coord + IVec2::new(1, 1), image_size),
+        weight.x,
+    );
+    top.lerp(bottom, weight.y)
+}
+
+fn radical_inverse_vdc(mut bits: u32) -> f32 {
+    bits = (bits << 16) | (bits >> 16);
+    bits = ((bits & 0x55555555) << 1) | ((bits & 0xAAAAAAAA) >> 1);
+    bits = ((bits & 0x33333333) << 2) | ((bits & 0xCCCCCCCC) >> 2);
+    bits = ((bits & 0x0F0F0F0F) << 4) | ((bits & 0xF0F0F0F0) >> 4);
+    bits = ((bits & 0x00FF00FF) << 8) | ((bits & 0xFF00FF00) >> 8);
+    bits as f32 * 2.328_306_4e-10
+}
+
+fn hammersley(index: u32, count: u32) -> Vec2 {
+    Vec2::new(index as f32 / count as f32, radical_inverse_vdc(index))
+}
+
+fn get_tangent_basis(normal: Vec3) -> (Vec3, Vec3) {
+    let up = match normal.z.abs() < 0.999 {
+        true => Vec3::Z,
+        false => Vec3::X,
+    };
+    let tangent = up.cross(normal).normalize();
+    (tangent, normal.cross(tangent))
+}
+
+// GGX importance sample around the normal; returns the half vector.
+fn importance_sample_ggx(xi: Vec2, normal: Vec3, roughness: f32) -> Vec3 {
+    let alpha = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cos_theta = ((1.0 - xi.y) / (1.0 + (alpha * alpha - 1.0) * xi.y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let (tangent, bitangent) = get_tangent_basis(normal);
+    (tangent * phi.cos() * sin_theta + bitangent * phi.sin() * sin_theta + normal * cos_theta)
+        .normalize()
+}
+
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let alpha = roughness * roughness;
+    let alpha_2 = alpha * alpha;
+    let denominator = n_dot_h * n_dot_h * (alpha_2 - 1.0) + 1.0;
+    alpha_2 / (PI * denominator * denominator)
+}
+
+fn geometry_smith_ibl(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let ggx_v = n_dot_v / (n_dot_v * (1.0 - k) + k);
+    let ggx_l = n_dot_l / (n_dot_l * (1.0 - k) + k);
+    ggx_v * ggx_l
+}
+
+// Equirectangular HDR -> cubemap faces.
+#[spirv(compute(threads(16, 16, 1)))]
+pub fn equirect_cs(
+    #[spirv(descriptor_set = 0, binding = 0)] equirect: &EquirectImage,
+    #[spirv(descriptor_set = 0, binding = 3)] output: &CubeStorageImage,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let output_size: UVec3 = output.query_size();
+    if global_coord.x >= output_size.x || global_coord.y >= output_size.y {
+        return;
+    }
+
+    let direction = get_cube_direction(global_coord.xy(), global_coord.z, output_size.x);
+    let equirect_size: UVec2 = equirect.query_size();
+    let color = sample_equirect(equirect, direction, equirect_size);
+
+    unsafe { output.write(global_coord, Vec4::from((color.xyz(), 1.0))) };
+}
+
+// Cosine-weighted hemisphere convolution for diffuse lighting.
+#[spirv(compute(threads(8, 8, 1)))]
+pub fn irradiance_cs(
+    #[spirv(push_constant)] constants: &ComputePushConstants,
+    #[spirv(descriptor_set = 0, binding = 1)] environment: &CubeImage,
+    #[spirv(descriptor_set = 0, binding = 2)] sampler: &Sampler,
+    #[spirv(descriptor_set = 0, binding = 3)] output: &CubeStorageImage,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let output_size: UVec3 = output.query_size();
+    if global_coord.x >= output_size.x || global_coord.y >= output_size.y {
+        return;
+    }
+
+    let normal = get_cube_direction(global_coord.xy(), global_coord.z, output_size.x);
+    let (tangent, bitangent) = get_tangent_basis(normal);
+    // NB! sampling a lower mip hides the coarse angular step
+    let lod = constants.data_0.w;
+    let delta = 0.025;
+
+    let mut irradiance = Vec3::ZERO;
+    let mut sample_count = 0.0;
+    let mut phi = 0.0;
+    while phi < 2.0 * PI {
+        let mut theta = 0.0;
+        while theta < 0.5 * PI {
+            let tangent_sample = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.sin() * phi.sin(),
+                theta.cos(),
+            );
+            let direction = tangent * tangent_sample.x
+                + bitangent * tangent_sample.y
+                + normal * tangent_sample.z;
+            let color: Vec4 = environment.sample_by_lod(*sampler, direction, lod);
+            irradiance += color.xyz() * theta.cos() * theta.sin();
+            sample_count += 1.0;
+            theta += delta;
+        }
+        phi += delta;
+    }
+    irradiance *= PI / sample_count;
+
+    unsafe { output.write(global_coord, Vec4::from((irradiance, 1.0))) };
+}
+
+// Split-sum prefiltered radiance for one roughness level; one dispatch per mip.
+#[spirv(compute(threads(8, 8, 1)))]
+pub fn prefilter_cs(
+    #[spirv(push_constant)] constants: &ComputePushConstants,
+    #[spirv(descriptor_set = 0, binding = 1)] environment: &CubeImage,
+    #[spirv(descriptor_set = 0, binding = 2)] sampler: &Sampler,
+    #[spirv(descriptor_set = 0, binding = 3)] output: &CubeStorageImage,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let output_size: UVec3 = output.query_size();
+    if global_coord.x >= output_size.x || global_coord.y >= output_size.y {
+        return;
+    }
+
+    let roughness = constants.data_0.x;
+    let sample_count = constants.data_0.y as u32;
+    let environment_size = constants.data_0.z;
+
+    // NB! assumes view == normal, as in the split-sum approximation
+    let normal = get_cube_direction(global_coord.xy(), global_coord.z, output_size.x);
+    let view = normal;
+
+    let mut prefiltered = Vec3::ZERO;
+    let mut total_weight = 0.0;
+    let mut index = 0;
+    while index < sample_count {
+        let xi = hammersley(index, sample_count);
+        let half = importance_sample_ggx(xi, normal, roughness);
+        let light = (2.0 * view.dot(half) * half - view).normalize();
+        let n_dot_l = normal.dot(light);
+
+        if n_dot_l > 0.0 {
+            // sample a mip matching the solid angle covered by this sample, to avoid fireflies
+            let n_dot_h = normal.dot(half).max(0.0);
+            let h_dot_v = half.dot(view).max(0.0);
+            let pdf = distribution_ggx(n_dot_h, roughness) * n_dot_h / (4.0 * h_dot_v) + 0.0001;
+            let texel_solid_angle = 4.0 * PI / (6.0 * environment_size * environment_size);
+            let sample_solid_angle = 1.0 / (sample_count as f32 * pdf + 0.0001);
+            let lod = match roughness == 0.0 {
+                true => 0.0,
+                false => 0.5 * (sample_solid_angle / texel_solid_angle).log2(),
+            };
+
+            let color: Vec4 = environment.sample_by_lod(*sampler, light, lod.max(0.0));
+            prefiltered += color.xyz() * n_dot_l;
+            total_weight += n_dot_l;
+        }
+        index += 1;
+    }
+    prefiltered /= total_weight.max(0.0001);
+
+    unsafe { output.write(global_coord, Vec4::from((prefiltered, 1.0))) };
+}
+
+// Split-sum BRDF integration; x = scale, y = bias applied to F0.
+#[spirv(compute(threads(16, 16, 1)))]
+pub fn brdf_lut_cs(
+    #[spirv(push_constant)] constants: &ComputePushConstants,
+    #[spirv(descriptor_set = 0, binding = 4)] output: &LutImage,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let output_size: UVec2 = output.query_size();
+    if global_coord.x >= output_size.x || global_coord.y >= output_size.y {
+        return;
+    }
+
+    let uv = (global_coord.xy().as_vec2() + Vec2::splat(0.5)) / output_size.as_vec2();
+    let n_dot_v = uv.x.max(0.0001);
+    let roughness = uv.y;
+    let sample_count = constants.data_0.y as u32;
+
+    let view = Vec3::new((1.0 - n_dot_v * n_dot_v).sqrt(), 0.0, n_dot_v);
+    let normal = Vec3::Z;
+
+    let mut scale = 0.0;
+    let mut bias = 0.0;
+    let mut index = 0;
+    while index < sample_count {
+        let xi = hammersley(index, sample_count);
+        let half = importance_sample_ggx(xi, normal, roughness);
+        let light = (2.0 * view.dot(half) * half - view).normalize();
+
+        let n_dot_l = light.z.max(0.0);
+        let n_dot_h = half.z.max(0.0);
+        let v_dot_h = view.dot(half).max(0.0);
+
+        if n_dot_l > 0.0 {
+            let geometry = geometry_smith_ibl(n_dot_v, n_dot_l, roughness);
+            let geometry_visibility = geometry * v_dot_h / (n_dot_h * n_dot_v);
+            let fresnel = (1.0 - v_dot_h).powf(5.0);
+            scale += (1.0 - fresnel) * geometry_visibility;
+            bias += fresnel * geometry_visibility;
+        }
+        index += 1;
+    }
+
+    let result = Vec2::new(scale, bias) / sample_count as f32;
+    unsafe { output.write(global_coord.xy(), Vec4::new(result.x, result.y, 0.0, 0.0)) };
+}
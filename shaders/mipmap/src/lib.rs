@@ -0,0 +1,61 @@
+#![no_std]
+
+use koi_gpu::ComputePushConstants;
+use spirv_std::glam::{UVec2, UVec3, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use spirv_std::image::Image;
+use spirv_std::spirv;
+
+pub type Image8 = Image!(2D, format = rgba8, sampled = false, depth = false);
+
+fn srgb_to_linear(color: Vec3) -> Vec3 {
+    let low = color / 12.92;
+    let high = ((color + Vec3::splat(0.055)) / 1.055).powf(2.4);
+    Vec3::select(color.cmple(Vec3::splat(0.04045)), low, high)
+}
+
+fn linear_to_srgb(color: Vec3) -> Vec3 {
+    let low = color * 12.92;
+    let high = 1.055 * color.powf(1.0 / 2.4) - Vec3::splat(0.055);
+    Vec3::select(color.cmple(Vec3::splat(0.0031308)), low, high)
+}
+
+fn load(image: &Image8, coord: UVec2, image_size: UVec2, srgb: bool) -> Vec4 {
+    let texel: Vec4 = image.read(coord.min(image_size - UVec2::ONE));
+    match srgb {
+        true => Vec4::from((srgb_to_linear(texel.xyz()), texel.w)),
+        false => texel,
+    }
+}
+
+// 2x2 box filter from mip N-1 into mip N; averages in linear space for sRGB data.
+#[spirv(compute(threads(8, 8)))]
+pub fn main_cs(
+    #[spirv(push_constant)] constants: &ComputePushConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] src: &Image8,
+    #[spirv(descriptor_set = 0, binding = 1)] dst: &Image8,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let texel_coord = global_coord.xy();
+    let dst_size: UVec2 = dst.query_size();
+
+    if texel_coord.x >= dst_size.x || texel_coord.y >= dst_size.y {
+        return;
+    }
+
+    let srgb = constants.data_0.x > 0.0;
+    let src_size: UVec2 = src.query_size();
+    let base = texel_coord * 2;
+
+    let average = 0.25
+        * (load(src, base, src_size, srgb)
+            + load(src, base + UVec2::new(1, 0), src_size, srgb)
+            + load(src, base + UVec2::new(0, 1), src_size, srgb)
+            + load(src, base + UVec2::new(1, 1), src_size, srgb));
+
+    let result = match srgb {
+        true => Vec4::from((linear_to_srgb(average.xyz()), average.w)),
+        false => average,
+    };
+
+    unsafe { dst.write(texel_coord, result) };
+}
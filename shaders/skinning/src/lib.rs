@@ -0,0 +1,61 @@
+#![cfg_attr(target_arch = "spirv", no_std)]
+
+use koi_gpu::{
+    MorphDelta, SkinVertex, SkinningPushConstants, Vertex, get_skin_matrix, morph_vertex,
+    skin_vertex,
+};
+use spirv_std::glam::{Mat4, UVec3};
+use spirv_std::spirv;
+
+// Deforms one skinned or morphed instance's bind-pose vertices into its output range; dispatched
+// once per instance. Morph targets are applied first, then skinning, as glTF specifies. Joint
+// matrices already carry the inverse bind matrices and bring vertices back into the instance's
+// object space, so the mesh pass applies the instance transform as usual.
+#[spirv(compute(threads(64)))]
+pub fn main_cs(
+    #[spirv(push_constant)] constants: &SkinningPushConstants,
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 0)] sources: &[Vertex],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 1)] skin_vertices: &[SkinVertex],
+    #[spirv(storage_buffer, descriptor_set = 0, binding = 2)] skinned: &mut [Vertex],
+    #[spirv(storage_buffer, descriptor_set = 1, binding = 0)] joint_matrices: &[Mat4],
+    #[spirv(storage_buffer, descriptor_set = 1, binding = 1)] morph_weights: &[f32],
+    #[spirv(storage_buffer, descriptor_set = 2, binding = 0)] morph_deltas: &[MorphDelta],
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let vertex_index = global_coord.x;
+    let vertex_count = constants.offsets.z;
+    if vertex_index >= vertex_count {
+        return;
+    }
+
+    let source_index = (constants.offsets.x + vertex_index) as usize;
+    let source = &sources[source_index];
+    let mut vertex = Vertex {
+        position_uv_x: source.position_uv_x,
+        normal_uv_y: source.normal_uv_y,
+        color: source.color,
+        tangent: source.tangent,
+    };
+    let mut target = 0;
+    while target < constants.morph.x {
+        let weight = morph_weights[(constants.morph.y + target) as usize];
+        let delta = &morph_deltas[(target * vertex_count + vertex_index) as usize];
+        vertex = morph_vertex(&vertex, delta, weight);
+        target += 1;
+    }
+
+    // NB! unskinned vertices still go through skin_vertex to renormalize morphed normals
+    let mut skin_matrix = Mat4::IDENTITY;
+    if constants.morph.z != 0 {
+        let skin = &skin_vertices[source_index];
+        let joint_offset = constants.offsets.w;
+        skin_matrix = get_skin_matrix(skin.weights, [
+            joint_matrices[(joint_offset + skin.joints.x) as usize],
+            joint_matrices[(joint_offset + skin.joints.y) as usize],
+            joint_matrices[(joint_offset + skin.joints.z) as usize],
+            joint_matrices[(joint_offset + skin.joints.w) as usize],
+        ]);
+    }
+
+    skinned[(constants.offsets.y + vertex_index) as usize] = skin_vertex(&vertex, skin_matrix);
+}
@@ -1,80 +1,283 @@
-#![no_std]
+#![cfg_attr(target_arch = "spirv", no_std)]
 
-use spirv_std::glam::{UVec2, UVec3, Vec2, Vec2Swizzles, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use core::f32::consts::PI;
+use koi_gpu::{ComputePushConstants, get_view_direction};
+use spirv_std::glam::{Mat4, UVec2, UVec3, Vec2, Vec3, Vec3Swizzles, Vec4, Vec4Swizzles};
 use spirv_std::image::Image;
-#[allow(unused)]
+#[cfg(target_arch = "spirv")]
 use spirv_std::num_traits::Float;
 use spirv_std::spirv;
 
 pub type Image2 = Image!(2D, format = rgba16f, sampled = false, depth = false);
 
-#[derive(Copy, Clone)]
-#[allow(unused)]
-pub struct PushConstants {
-    data_0: Vec4,
-    data_1: Vec4,
-    data_2: Vec4,
-    data_3: Vec4,
+// Earth-like atmosphere; distances in meters.
+const EARTH_RADIUS: f32 = 6360e3;
+const ATMOSPHERE_RADIUS: f32 = 6420e3;
+const RAYLEIGH_SCALE_HEIGHT: f32 = 7994.0;
+const MIE_SCALE_HEIGHT: f32 = 1200.0;
+const RAYLEIGH_SCATTERING: Vec3 = Vec3::new(5.5e-6, 13.0e-6, 22.4e-6);
+const MIE_SCATTERING: f32 = 21e-6;
+// Mie extinction is slightly larger than its scattering; aerosols absorb
+const MIE_EXTINCTION: f32 = MIE_SCATTERING * 1.1;
+const MIE_ANISOTROPY: f32 = 0.76;
+const SUN_INTENSITY: f32 = 20.0;
+// cosine of the sun's angular radius (~0.5 degrees)
+const SUN_DISC_COSINE: f32 = 0.99996;
+const VIEW_SAMPLES: u32 = 16;
+const LIGHT_SAMPLES: u32 = 8;
+
+// Returns near and far distances along the ray; both negative when the sphere is missed.
+fn intersect_sphere(origin: Vec3, direction: Vec3, radius: f32) -> Vec2 {
+    let b = origin.dot(direction);
+    let c = origin.dot(origin) - radius * radius;
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return Vec2::splat(-1.0);
+    }
+    let root = discriminant.sqrt();
+    Vec2::new(-b - root, -b + root)
 }
 
-fn noise_2d(sample_pos: Vec2) -> f32 {
-    let x_hash = (sample_pos.x * 37.0).cos();
-    let y_hash = (sample_pos.y * 57.0).cos();
-    (415.92653 * (x_hash + y_hash)).fract()
+fn extinction(rayleigh_depth: f32, mie_depth: f32) -> Vec3 {
+    let tau = RAYLEIGH_SCATTERING * rayleigh_depth + Vec3::splat(MIE_EXTINCTION * mie_depth);
+    Vec3::new((-tau.x).exp(), (-tau.y).exp(), (-tau.z).exp())
 }
 
-fn noisy_starfield(sample_pos: Vec2, threshold: f32) -> f32 {
-    let value = noise_2d(sample_pos);
+// Single scattering (Rayleigh + Mie) along the view ray, for a viewer standing on the ground.
+fn atmosphere(direction: Vec3, sun: Vec3) -> Vec3 {
+    let origin = Vec3::new(0.0, EARTH_RADIUS + 1.0, 0.0);
+
+    let ground = intersect_sphere(origin, direction, EARTH_RADIUS);
+    let ray_length = match ground.x > 0.0 {
+        true => ground.x,
+        false => intersect_sphere(origin, direction, ATMOSPHERE_RADIUS).y,
+    };
+    let segment = ray_length / VIEW_SAMPLES as f32;
+
+    let mu = direction.dot(sun);
+    let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+    let g2 = MIE_ANISOTROPY * MIE_ANISOTROPY;
+    let mie_phase = 3.0 / (8.0 * PI) * ((1.0 - g2) * (1.0 + mu * mu))
+        / ((2.0 + g2) * (1.0 + g2 - 2.0 * MIE_ANISOTROPY * mu).powf(1.5));
+
+    let mut rayleigh_sum = Vec3::ZERO;
+    let mut mie_sum = Vec3::ZERO;
+    let mut rayleigh_depth = 0.0;
+    let mut mie_depth = 0.0;
+
+    let mut view_sample = 0;
+    while view_sample < VIEW_SAMPLES {
+        let position = origin + direction * (segment * (view_sample as f32 + 0.5));
+        let height = position.length() - EARTH_RADIUS;
+        let rayleigh_density = (-height / RAYLEIGH_SCALE_HEIGHT).exp() * segment;
+        let mie_density = (-height / MIE_SCALE_HEIGHT).exp() * segment;
+        rayleigh_depth += rayleigh_density;
+        mie_depth += mie_density;
+
+        // march towards the sun; samples in the planet's shadow receive no light
+        let light_segment =
+            intersect_sphere(position, sun, ATMOSPHERE_RADIUS).y / LIGHT_SAMPLES as f32;
+        let mut light_rayleigh_depth = 0.0;
+        let mut light_mie_depth = 0.0;
+        let mut lit = true;
+
+        let mut light_sample = 0;
+        while light_sample < LIGHT_SAMPLES {
+            let light_position = position + sun * (light_segment * (light_sample as f32 + 0.5));
+            let light_height = light_position.length() - EARTH_RADIUS;
+            if light_height < 0.0 {
+                lit = false;
+                break;
+            }
+            light_rayleigh_depth += (-light_height / RAYLEIGH_SCALE_HEIGHT).exp() * light_segment;
+            light_mie_depth += (-light_height / MIE_SCALE_HEIGHT).exp() * light_segment;
+            light_sample += 1;
+        }
+
+        if lit {
+            let attenuation = extinction(
+                rayleigh_depth + light_rayleigh_depth,
+                mie_depth + light_mie_depth,
+            );
+            rayleigh_sum += attenuation * rayleigh_density;
+            mie_sum += attenuation * mie_density;
+        }
+        view_sample += 1;
+    }
+
+    let mut color = (rayleigh_sum * RAYLEIGH_SCATTERING * rayleigh_phase
+        + mie_sum * MIE_SCATTERING * mie_phase)
+        * SUN_INTENSITY;
+
+    if ground.x < 0.0 && mu > SUN_DISC_COSINE {
+        color += extinction(rayleigh_depth, mie_depth) * SUN_INTENSITY;
+    }
+    color
+}
+
+fn hash_3d(cell: Vec3) -> f32 {
+    (cell.dot(Vec3::new(12.9898, 78.233, 37.719)).sin() * 43758.547).fract()
+}
+
+// Stars are hashed on a grid over view directions, so they stay fixed as the camera turns.
+fn starfield(direction: Vec3, threshold: f32) -> f32 {
+    let value = hash_3d((direction * 512.0).floor());
     match value >= threshold {
         true => ((value - threshold) / (1.0 - threshold)).powf(6.0),
         false => 0.0,
     }
 }
 
-// Stabilize noisy_starfield() by only sampling at integer values.
-fn stable_starfield(sample_pos: Vec2, threshold: f32) -> f32 {
-    // Linear interpolation between four samples.
-    // Note: This approach has some visual artifacts.
-    // There must be a better way to "anti alias" the star field.
-    let fract = Vec2::new(sample_pos.x.fract(), sample_pos.y.fract());
-    let floor_sample = Vec2::new(sample_pos.x.floor(), sample_pos.y.floor());
-    let v1 = noisy_starfield(floor_sample, threshold);
-    let v2 = noisy_starfield(floor_sample + Vec2::new(0.0, 1.0), threshold);
-    let v3 = noisy_starfield(floor_sample + Vec2::new(1.0, 0.0), threshold);
-    let v4 = noisy_starfield(floor_sample + Vec2::new(1.0, 1.0), threshold);
-
-    v1 * (1.0 - fract.x) * (1.0 - fract.y)
-        + v2 * (1.0 - fract.x) * fract.y
-        + v3 * fract.x * (1.0 - fract.y)
-        + v4 * fract.x * fract.y
-}
+fn sky(direction: Vec3, constants: &ComputePushConstants) -> Vec4 {
+    let sun = constants.data_4.xyz().normalize();
+    let mut color = atmosphere(direction, sun);
 
-fn sky(texel_coord: UVec2, image_size: UVec2, constants: &PushConstants) -> Vec4 {
-    let mut vec_color = constants.data_0.xyz() * texel_coord.y as f32 / image_size.y as f32;
+    // fade stars in as the sun sets below the horizon
+    let night = (-sun.y * 10.0).clamp(0.0, 1.0);
+    if direction.y > 0.0 {
+        color += Vec3::splat(starfield(direction, constants.data_4.w) * night);
+    }
+
+    Vec4::from((color, 1.0))
+}
 
-    // Note: Choose fThreshhold in the range [0.99, 0.9999].
-    // Higher values (i.e., closer to one) yield a sparser starfield.
-    let star_field_threshold = constants.data_1.w;
+// What main_cs writes at global_coord.xy; None outside the image.
+pub fn get_texel(
+    global_coord: UVec3,
+    image_size: UVec2,
+    constants: &ComputePushConstants,
+) -> Option<Vec4> {
+    let texel_coord = global_coord.xy();
+    if texel_coord.x >= image_size.x || texel_coord.y >= image_size.y {
+        return None;
+    }
 
-    // Stars with a slow crawl
-    let star_rate = Vec2::new(0.2, -0.06);
-    let sample_pos = texel_coord.xy().as_vec2() + star_rate;
-    let star_val = stable_starfield(sample_pos, star_field_threshold);
-    vec_color += Vec3::splat(star_val);
+    let inverse_view_projection = Mat4::from_cols(
+        constants.data_0,
+        constants.data_1,
+        constants.data_2,
+        constants.data_3,
+    );
+    let direction = get_view_direction(inverse_view_projection, texel_coord, image_size);
 
-    Vec4::from((vec_color, 1.0))
+    Some(sky(direction, constants))
 }
 
+// NB! constants.data_0..data_3 hold the columns of the inverse view-projection matrix;
+// data_4.xyz is the direction towards the sun, data_4.w the starfield threshold
 #[spirv(compute(threads(16, 16)))]
 pub fn main_cs(
-    #[spirv(push_constant)] constants: &PushConstants,
+    #[spirv(push_constant)] constants: &ComputePushConstants,
     #[spirv(descriptor_set = 0, binding = 0)] image: &Image2,
     #[spirv(global_invocation_id)] global_coord: UVec3,
 ) {
-    let texel_coord = global_coord.xy();
     let image_size: UVec2 = image.query_size();
+    if let Some(color) = get_texel(global_coord, image_size, constants) {
+        unsafe { image.write(global_coord.xy(), color) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::f32::consts::FRAC_PI_2;
+    use koi_gpu::dispatch_2d;
+
+    const GROUP_SIZE: UVec2 = UVec2::splat(16);
+    const IMAGE_SIZE: UVec2 = UVec2::splat(8);
+
+    // A 90 degree, square camera at the origin looking down -z, with the renderer's reverse-Z
+    // projection.
+    fn get_constants(sun: Vec3, star_threshold: f32) -> ComputePushConstants {
+        let projection = Mat4::perspective_rh(FRAC_PI_2, 1.0, 10000.0, 0.1);
+        let inverse_view_projection = projection.inverse();
+        ComputePushConstants::default()
+            .data_0(inverse_view_projection.col(0))
+            .data_1(inverse_view_projection.col(1))
+            .data_2(inverse_view_projection.col(2))
+            .data_3(inverse_view_projection.col(3))
+            .data_4(Vec4::from((sun, star_threshold)))
+    }
+
+    fn get_direction(index: usize) -> Vec3 {
+        let texel_coord = UVec2::new(index as u32 % IMAGE_SIZE.x, index as u32 / IMAGE_SIZE.x);
+        let uv = (texel_coord.as_vec2() + Vec2::splat(0.5)) / IMAGE_SIZE.as_vec2();
+        Vec3::new(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, -1.0).normalize()
+    }
+
+    fn dispatch(constants: &ComputePushConstants) -> Vec<Vec4> {
+        dispatch_2d(IMAGE_SIZE, GROUP_SIZE, |global_coord| {
+            get_texel(global_coord, IMAGE_SIZE, constants)
+        })
+    }
+
+    fn assert_close(actual: Vec4, expected: Vec4, index: usize) {
+        let tolerance = 1e-4 * expected.abs().max_element().max(1.0);
+        assert!(
+            actual.abs_diff_eq(expected, tolerance),
+            "texel {index}: {actual}, expected {expected}"
+        );
+    }
+
+    #[test]
+    fn intersects_spheres() {
+        let hit = intersect_sphere(Vec3::new(0.0, 0.0, -5.0), Vec3::Z, 1.0);
+        assert!(hit.abs_diff_eq(Vec2::new(4.0, 6.0), 1e-5));
+        let inside = intersect_sphere(Vec3::ZERO, Vec3::Y, 2.0);
+        assert!(inside.abs_diff_eq(Vec2::new(-2.0, 2.0), 1e-5));
+        let miss = intersect_sphere(Vec3::new(0.0, 2.0, -5.0), Vec3::Z, 1.0);
+        assert_eq!(miss, Vec2::splat(-1.0));
+    }
+
+    #[test]
+    fn reconstructs_view_directions() {
+        let constants = get_constants(Vec3::new(0.0, 1.0, -1.0), 1.0);
+        for (index, texel) in dispatch(&constants).into_iter().enumerate() {
+            assert_close(texel, sky(get_direction(index), &constants), index);
+        }
+    }
+
+    #[test]
+    fn scatters_blue_at_noon() {
+        let constants = get_constants(Vec3::Y, 1.0);
+        let image = dispatch(&constants);
+        // top row looks up, bottom row down at the ground
+        for texel in &image[..IMAGE_SIZE.x as usize] {
+            assert!(texel.z > texel.y && texel.y > texel.x, "{texel}");
+            assert_eq!(texel.w, 1.0);
+        }
+        let zenith = image[3].xyz().length();
+        let ground = image[image.len() - 4].xyz().length();
+        assert!(ground < zenith, "ground {ground}, zenith {zenith}");
+    }
+
+    #[test]
+    fn shows_stars_only_at_night() {
+        for (sun, night) in [(Vec3::Y, false), (Vec3::NEG_Y, true)] {
+            let stars = dispatch(&get_constants(sun, 0.0));
+            let no_stars = dispatch(&get_constants(sun, 1.0));
+            let mut star_count = 0;
+            for (index, (texel, sky)) in stars.into_iter().zip(no_stars).enumerate() {
+                let direction = get_direction(index);
+                let star = match night && direction.y > 0.0 {
+                    true => starfield(direction, 0.0),
+                    false => 0.0,
+                };
+                assert_close(texel, sky + Vec4::new(star, star, star, 0.0), index);
+                star_count += (star > 0.0) as u32;
+            }
+            assert_eq!(star_count > 0, night);
+        }
+    }
 
-    if texel_coord.x < image_size.x && texel_coord.y < image_size.y {
-        unsafe { image.write(texel_coord, sky(texel_coord, image_size, constants)) };
+    #[test]
+    fn hashes_stars_below_one() {
+        for cell in [Vec3::ZERO, Vec3::new(1.0, -2.0, 3.0), Vec3::splat(511.0)] {
+            let value = hash_3d(cell);
+            assert!(value.abs() < 1.0, "{value}");
+            assert_eq!(value, hash_3d(cell));
+        }
+        assert_eq!(starfield(Vec3::Y, 1.0), 0.0);
     }
 }
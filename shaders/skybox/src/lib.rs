@@ -0,0 +1,37 @@
+#![no_std]
+
+use koi_gpu::{ComputePushConstants, get_view_direction};
+use spirv_std::glam::{Mat4, UVec2, UVec3, Vec3Swizzles, Vec4, Vec4Swizzles};
+use spirv_std::image::Image;
+use spirv_std::{Sampler, spirv};
+
+pub type Image2 = Image!(2D, format = rgba16f, sampled = false, depth = false);
+pub type CubeImage = Image!(cube, type = f32, sampled = true, depth = false);
+
+// NB! constants.data_0..data_3 hold the columns of the inverse view-projection matrix
+#[spirv(compute(threads(16, 16)))]
+pub fn main_cs(
+    #[spirv(push_constant)] constants: &ComputePushConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] image: &Image2,
+    #[spirv(descriptor_set = 1, binding = 0)] environment: &CubeImage,
+    #[spirv(descriptor_set = 1, binding = 1)] sampler: &Sampler,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let texel_coord = global_coord.xy();
+    let image_size: UVec2 = image.query_size();
+
+    if texel_coord.x >= image_size.x || texel_coord.y >= image_size.y {
+        return;
+    }
+
+    let inverse_view_projection = Mat4::from_cols(
+        constants.data_0,
+        constants.data_1,
+        constants.data_2,
+        constants.data_3,
+    );
+    let direction = get_view_direction(inverse_view_projection, texel_coord, image_size);
+
+    let color: Vec4 = environment.sample_by_lod(*sampler, direction, 0.0);
+    unsafe { image.write(texel_coord, Vec4::from((color.xyz(), 1.0))) };
+}
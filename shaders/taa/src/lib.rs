@@ -0,0 +1,61 @@
+#![no_std]
+
+use koi_gpu::{ComputePushConstants, load_clamped, sample_bilinear};
+use spirv_std::glam::{IVec2, UVec2, UVec3, Vec2, Vec3Swizzles, Vec4, Vec4Swizzles};
+use spirv_std::image::Image;
+use spirv_std::spirv;
+
+pub type Image2 = Image!(2D, format = rgba16f, sampled = false, depth = false);
+pub type VelocityImage = Image!(2D, format = rg16f, sampled = false, depth = false);
+
+#[spirv(compute(threads(16, 16)))]
+pub fn main_cs(
+    #[spirv(push_constant)] constants: &ComputePushConstants,
+    #[spirv(descriptor_set = 0, binding = 0)] color: &Image2,
+    #[spirv(descriptor_set = 0, binding = 1)] velocity: &VelocityImage,
+    #[spirv(descriptor_set = 0, binding = 2)] history_in: &Image2,
+    #[spirv(descriptor_set = 0, binding = 3)] history_out: &Image2,
+    #[spirv(global_invocation_id)] global_coord: UVec3,
+) {
+    let texel_coord = global_coord.xy();
+    let image_size: UVec2 = color.query_size();
+
+    if texel_coord.x >= image_size.x || texel_coord.y >= image_size.y {
+        return;
+    }
+
+    let blend_factor = constants.data_0.x;
+    let history_valid = constants.data_0.y > 0.0;
+
+    let current: Vec4 = color.read(texel_coord);
+
+    // 3x3 neighborhood bounds used to reject stale history
+    let mut minimum = current;
+    let mut maximum = current;
+    for y in -1..2 {
+        for x in -1..2 {
+            let neighbor =
+                load_clamped(color, texel_coord.as_ivec2() + IVec2::new(x, y), image_size);
+            minimum = minimum.min(neighbor);
+            maximum = maximum.max(neighbor);
+        }
+    }
+
+    let motion: Vec4 = velocity.read(texel_coord);
+    let uv = (texel_coord.as_vec2() + Vec2::splat(0.5)) / image_size.as_vec2();
+    let previous_uv = uv - motion.xy();
+
+    let offscreen =
+        previous_uv.x < 0.0 || previous_uv.y < 0.0 || previous_uv.x > 1.0 || previous_uv.y > 1.0;
+
+    let result = if !history_valid || offscreen {
+        current
+    } else {
+        let history = sample_bilinear(history_in, previous_uv, image_size)
+            .max(minimum)
+            .min(maximum);
+        history.lerp(current, blend_factor)
+    };
+
+    unsafe { history_out.write(texel_coord, result) };
+}
@@ -0,0 +1,87 @@
+#![cfg_attr(target_arch = "spirv", no_std)]
+
+use koi_gpu::{
+    COMPACT_VERTEX_SIZE, CompactVertex, FrameData, Instance, PushConstants, VERTEX_FORMAT_COMPACT,
+    VERTEX_SIZE, Vertex, load,
+};
+use spirv_std::glam::{Mat3, UVec4, Vec2, Vec3, Vec4, Vec4Swizzles};
+use spirv_std::spirv;
+
+// Pulls the instance's vertices from its buffer device address, in either vertex format.
+#[spirv(vertex)]
+#[cfg_attr(not(target_arch = "spirv"), allow(unused_variables))]
+pub fn main_vs(
+    #[spirv(vertex_index)] vertex_index: i32,
+    // first_instance carries the instance index for both direct and indirect draws
+    #[spirv(instance_index)] instance_index: i32,
+    #[spirv(push_constant)] constants: &PushConstants,
+    #[spirv(storage_buffer, descriptor_set = 1, binding = 0)] instances: &[Instance],
+    #[spirv(position)] out_position: &mut Vec4,
+    out_color: &mut Vec4,
+    out_uv: &mut Vec2,
+    out_current_position: &mut Vec4,
+    out_previous_position: &mut Vec4,
+    out_normal: &mut Vec3,
+    out_world_position: &mut Vec3,
+    out_tangent: &mut Vec4,
+    #[spirv(flat)] out_textures: &mut UVec4,
+    #[spirv(flat)] out_factors: &mut Vec4,
+) {
+    let instance = &instances[instance_index as usize];
+    let vertex_index = vertex_index as u64;
+    let mut vertex = Vertex {
+        position_uv_x: Vec4::ZERO,
+        normal_uv_y: Vec4::ZERO,
+        color: Vec4::ZERO,
+        tangent: Vec4::ZERO,
+    };
+    if instance.vertex_format.x == VERTEX_FORMAT_COMPACT {
+        let mut compact = CompactVertex {
+            position_xy: 0,
+            position_z_sign: 0,
+            normal: 0,
+            tangent: 0,
+            uv: 0,
+            color: 0,
+        };
+        let address = instance.vertex_buffer_address + vertex_index * COMPACT_VERTEX_SIZE;
+        unsafe { load::<CompactVertex, 4>(address, &mut compact) };
+        vertex = compact.decode(
+            instance.quantization_origin.xyz(),
+            instance.quantization_extent.xyz(),
+        );
+    } else {
+        let address = instance.vertex_buffer_address + vertex_index * VERTEX_SIZE;
+        unsafe { load::<Vertex, 16>(address, &mut vertex) };
+    }
+
+    #[cfg(target_arch = "spirv")]
+    let frame_data = unsafe { FrameData::load(constants.frame_data_address) };
+    // host builds only type-check the shader
+    #[cfg(not(target_arch = "spirv"))]
+    let frame_data = FrameData::default();
+    let local_position = Vec4::from((vertex.position_uv_x.xyz(), 1.0));
+    let position = instance.transform * local_position;
+    *out_position = frame_data.view_projection * position;
+    // unjittered clip positions for motion vectors
+    let mut current_position = *out_position;
+    current_position.x -= frame_data.jitter.x * out_position.w;
+    current_position.y -= frame_data.jitter.y * out_position.w;
+    *out_current_position = current_position;
+    // NB! skinned and morphed vertices only carry their instance's motion, not their own
+    let mut previous_position =
+        frame_data.previous_view_projection * instance.previous_transform * local_position;
+    previous_position.x -= frame_data.jitter.z * previous_position.w;
+    previous_position.y -= frame_data.jitter.w * previous_position.w;
+    *out_previous_position = previous_position;
+
+    *out_color = vertex.color * instance.base_color;
+    *out_uv = Vec2::new(vertex.position_uv_x.w, vertex.normal_uv_y.w);
+    // NB! assumes uniform scale; non-uniform scale needs the inverse transpose
+    let rotation = Mat3::from_mat4(instance.transform);
+    *out_normal = rotation * vertex.normal_uv_y.xyz();
+    *out_world_position = position.xyz();
+    *out_tangent = Vec4::from((rotation * vertex.tangent.xyz(), vertex.tangent.w));
+    *out_textures = instance.textures;
+    *out_factors = instance.factors;
+}